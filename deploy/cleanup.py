@@ -3,7 +3,6 @@
 import argparse
 import subprocess
 import json
-from substrateinterface import Keypair, KeypairType
 
 parser = argparse.ArgumentParser(description='Cleanup after unsuccessful deployment')
 parser.add_argument('--node', 
@@ -3,6 +3,7 @@
 import argparse
 import subprocess
 import json
+import sys
 
 parser = argparse.ArgumentParser(description='Deploy blockchain')
 parser.add_argument('--validator-node', 
@@ -14,6 +15,17 @@ parser.add_argument('--boot-node-addr', help='first (boot) node ip address', req
 parser.add_argument('--secrets', help='secrets file', required=True)
 parser.add_argument('--env', help='production or staging', choices=['prod', 'stage'], required=True)
 parser.add_argument('--tag', help='tag of docker image', required=True)
+parser.add_argument('--standby-after-slots',
+  help='Optional, not the default failover: run every validator node as a GRANDPA voter with its own '
+    'authority key, the second one as hot standby authoring after this many slots without a block. '
+    'Needs at least 4 authorities in the secrets file and one validator node per authority',
+  type=int
+)
+parser.add_argument('--prometheus-host',
+  help='host address to publish the Prometheus port 9615 of each node on, '
+    'e.g. a private network address; never a public one',
+  default='127.0.0.1'
+)
 parser.add_argument('--with-existing-data', 
   help='Do not initialize data directory, just start containers', 
   action='store_true'
@@ -52,14 +64,14 @@ def init_node(host):
   print('Initialize node', host)
   prepare_blockchain_dir(host)
 
-def init_keystore(host):
-  print('Initialize keystore', host)
-  key_types = dict(aura = 'Sr25519', gran = 'Ed25519')
+def init_keystore(host, authority):
+  print('Initialize keystore', host, 'authority =', authority)
+  key_types = dict(aura = 'Sr25519', gran = 'Ed25519', live = 'Sr25519')
   for key_type in key_types:
     scheme = key_types[key_type]
     key_file_name = f"blockchain_deploy_key_{key_type}"
     with open(f"/tmp/{key_file_name}", 'w') as file:
-      file.write(secrets['authorities'][0])
+      file.write(secrets['authorities'][authority])
 
     print(f"Copy authority key file {key_type}", host)
     run(f"scp /tmp/{key_file_name} {host}:/tmp")
@@ -92,11 +104,15 @@ def run_api_node(host):
   -p 30333:30333 \
   -p 9933:9933 \
   -p 9944:9944 \
+  -p 9955:9955 \
+  -p {args.prometheus_host}:9615:9615 \
   -v /var/blockchain:/data \
   coldstack/privatechain:{args.tag} \
   --name 'Coldstack Public {args.env}' \
   --pruning archive \
-  --no-telemetry --no-prometheus \
+  --coldstack-history \
+  --no-telemetry \
+  --prometheus-external \
   --chain /chainspec/{args.env}.json \
   --execution wasm \
   --port 30333   \
@@ -105,25 +121,30 @@ def run_api_node(host):
   --ws-external  \
   --rpc-port 9933 \
   --rpc-cors all \
+  --health-listen 0.0.0.0:9955 \
   --bootnodes /ip4/{args.boot_node_addr}/tcp/30333/p2p/{secrets['peer_id']} \
   "
   run_ssh(host, input)
 
 
-def run_validator_node(host, is_boot_node, is_validator):
-  print('Run validator node on host', host, 'is_boot_node =', is_boot_node)
+def run_validator_node(host, is_boot_node, is_validator, is_standby=False, authority=0):
+  print('Run validator node on host', host, 'is_boot_node =', is_boot_node, 'is_standby =', is_standby)
   if not args.with_existing_data:
     init_node(host)
-    init_keystore(host)
+    init_keystore(host, authority)
   input = f"docker run \
   -d \
   --restart unless-stopped \
   -p 30333:30333 \
+  -p 9955:9955 \
+  -p {args.prometheus_host}:9615:9615 \
   -v /var/blockchain:/data \
   coldstack/privatechain:{args.tag} \
   --name 'Coldstack Validator {args.env}' \
+  --health-listen 0.0.0.0:9955 \
   --pruning archive \
-  --no-telemetry --no-prometheus \
+  --no-telemetry \
+  --prometheus-external \
   --chain /chainspec/{args.env}.json \
   --execution wasm \
   --port 30333 \
@@ -132,6 +153,11 @@ def run_validator_node(host, is_boot_node, is_validator):
     input = f"{input} \
       --validator \
     "
+  if is_standby:
+    input = f"{input} \
+      --validator \
+      --standby-after-slots {args.standby_after_slots} \
+    "
   if is_boot_node:
     input = f"{input} \
      --node-key {secrets['nodekey']} \
@@ -145,8 +171,26 @@ def run_validator_node(host, is_boot_node, is_validator):
 
 secrets = read_secrets_file()
 
+# GRANDPA finalizes with votes of more than 2/3 of the voters. With fewer than
+# 4, finality stops while any of them is down, and a standby taking over
+# authoring would not help.
+MIN_GRANDPA_VOTERS = 4
+
+with_standby = args.standby_after_slots is not None
+if with_standby:
+  authorities = len(secrets['authorities'])
+  if authorities < MIN_GRANDPA_VOTERS or len(args.validator_node) != authorities:
+    sys.exit(f"--standby-after-slots needs at least {MIN_GRANDPA_VOTERS} authorities and one "
+      f"--validator-node per authority, got {authorities} authorities and "
+      f"{len(args.validator_node)} validator nodes")
+
 for i, host in enumerate(args.validator_node):
-  run_validator_node(host, is_boot_node = (i == 0), is_validator = (i == 0))
+  run_validator_node(host,
+    is_boot_node = (i == 0),
+    is_validator = (i == 0) or (with_standby and i != 1),
+    is_standby = with_standby and i == 1,
+    authority = i if with_standby else 0,
+  )
 
 for host in args.api_node:
   run_api_node(host)
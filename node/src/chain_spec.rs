@@ -2,8 +2,9 @@ use sp_core::{Pair, Public, sr25519};
 use node_template_runtime::{
 	AccountId, AuraConfig, BalancesConfig, GenesisConfig, GrandpaConfig,
 	SudoConfig, SystemConfig, WASM_BINARY, Signature, ColdStackConfig,
-  opaque::SessionKeys, ValidatorSetConfig, SessionConfig
+  opaque::SessionKeys, ValidatorSetConfig, SessionConfig, LivenessConfig, CouncilConfig,
 };
+use pallet_template::{GatewayInfo, GenesisPermission};
 use sp_consensus_aura::sr25519::AuthorityId as AuraId;
 use sp_finality_grandpa::AuthorityId as GrandpaId;
 use sp_runtime::traits::{Verify, IdentifyAccount};
@@ -15,6 +16,31 @@ use sc_service::ChainType;
 /// Specialized `ChainSpec`. This is a specialization of the general Substrate ChainSpec type.
 pub type ChainSpec = sc_service::GenericChainSpec<GenesisConfig>;
 
+/// One CLS, the ColdStack token, in its smallest units.
+pub const CLS: u128 = 1_000_000_000_000_000_000;
+
+/// CLS issued on Ethereum, mirrored by `ColdStack::TotalIssuance`.
+pub const TOTAL_ISSUANCE: u128 = 50_000_000 * CLS;
+
+/// Token properties shown by wallets and explorers.
+pub fn properties() -> sc_service::Properties {
+	let mut properties = sc_service::Properties::new();
+	properties.insert("ss58Format".into(), 42.into());
+	properties.insert("tokenDecimals".into(), 18.into());
+	properties.insert("tokenSymbol".into(), "CLS".into());
+	properties
+}
+
+/// The production chain, as deployed.
+pub fn coldstack_prod_config() -> Result<ChainSpec, String> {
+	ChainSpec::from_json_bytes(&include_bytes!("../../chainspec/production/chainspecRaw.json")[..])
+}
+
+/// The staging chain, as deployed.
+pub fn coldstack_staging_config() -> Result<ChainSpec, String> {
+	ChainSpec::from_json_bytes(&include_bytes!("../../chainspec/staging/chainspecRaw.json")[..])
+}
+
 /// Generate a crypto pair from seed.
 pub fn get_from_seed<TPublic: Public>(seed: &str) -> <TPublic::Pair as Pair>::Public {
 	TPublic::Pair::from_string(&format!("//{}", seed), None)
@@ -35,7 +61,8 @@ fn session_keys(
 	aura: AuraId,
 	grandpa: GrandpaId,
 ) -> SessionKeys {
-	SessionKeys { aura, grandpa }
+	// Liveness heartbeats are signed with the Aura key, under its own key type.
+	SessionKeys { liveness: sr25519::Public::from(aura.clone()).into(), aura, grandpa }
 }
 
 pub fn authority_keys_from_seed(s: &str) -> (
@@ -74,7 +101,7 @@ pub fn development_config() -> Result<ChainSpec, String> {
 				get_account_id_from_seed::<sr25519::Public>("Alice//stash"),
 				get_account_id_from_seed::<sr25519::Public>("Bob//stash"),
 			],
-			true,
+			cold_stack_genesis(get_account_id_from_seed::<sr25519::Public>("Alice")),
 		),
 		// Bootnodes
 		vec![],
@@ -83,7 +110,7 @@ pub fn development_config() -> Result<ChainSpec, String> {
 		// Protocol ID
 		None,
 		// Properties
-		None,
+		Some(properties()),
 		// Extensions
 		None,
 	))
@@ -122,7 +149,7 @@ pub fn local_testnet_config() -> Result<ChainSpec, String> {
 				get_account_id_from_seed::<sr25519::Public>("Eve//stash"),
 				get_account_id_from_seed::<sr25519::Public>("Ferdie//stash"),
 			],
-			true,
+			cold_stack_genesis(get_account_id_from_seed::<sr25519::Public>("Alice")),
 		),
 		// Bootnodes
 		vec![],
@@ -131,21 +158,20 @@ pub fn local_testnet_config() -> Result<ChainSpec, String> {
 		// Protocol ID
 		None,
 		// Properties
-		None,
+		Some(properties()),
 		// Extensions
 		None,
 	))
 }
 
 /// Configure initial storage state for FRAME modules.
-fn testnet_genesis(
+pub fn testnet_genesis(
 	wasm_binary: &[u8],
 	initial_authorities: Vec<(AccountId, AuraId, GrandpaId)>,
 	root_key: AccountId,
 	endowed_accounts: Vec<AccountId>,
-	_enable_println: bool,
+	cold_stack: ColdStackConfig,
 ) -> GenesisConfig {
-  let root_key_clone = root_key.clone();
 	GenesisConfig {
 		system: SystemConfig {
 			// Add Wasm runtime to storage.
@@ -174,9 +200,51 @@ fn testnet_genesis(
 			// Assign network admin rights.
 			key: root_key,
 		},
-    cold_stack: ColdStackConfig {
-			key: root_key_clone,
-      total_issuance: 50_000_000u128 * u128::pow(10, 18),
-    }
+    cold_stack,
+		liveness: LivenessConfig {
+			offline_threshold: 3,
+			remove_offline: false,
+		},
+		// The validators' accounts form the council of test chains.
+		council: CouncilConfig {
+			members: initial_authorities.iter().map(|x| x.0.clone()).collect(),
+			phantom: Default::default(),
+		},
+	}
+}
+
+/// ColdStack genesis of development and local test chains.
+///
+/// Bob runs a filenode and Charlie the billing service, both behind a single
+/// gateway, and one ETH address holds some tokens, so that clients can be
+/// tried out without replaying extrinsics first.
+fn cold_stack_genesis(root_key: AccountId) -> ColdStackConfig {
+	let gateway = vec![0x01; 20];
+	let user = vec![0x10; 20];
+	let user_balance = 1_000 * CLS;
+
+	ColdStackConfig {
+		key: root_key,
+		total_issuance: TOTAL_ISSUANCE,
+		locked_funds: Some(TOTAL_ISSUANCE - user_balance),
+		balances: vec![(user, user_balance)],
+		file_permissions: vec![GenesisPermission {
+			eth_address: vec![0x02; 20],
+			account_id: get_account_id_from_seed::<sr25519::Public>("Bob"),
+			node_url: b"http://127.0.0.1:3001".to_vec(),
+		}],
+		billing_permissions: vec![GenesisPermission {
+			eth_address: vec![0x03; 20],
+			account_id: get_account_id_from_seed::<sr25519::Public>("Charlie"),
+			node_url: b"http://127.0.0.1:3002".to_vec(),
+		}],
+		gateways: vec![GatewayInfo {
+			address: gateway,
+			seed_address: None,
+			storage: 0,
+			is_active: true,
+			node_url: b"http://127.0.0.1:3000".to_vec(),
+		}],
+		..Default::default()
 	}
 }
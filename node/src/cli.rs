@@ -8,6 +8,15 @@ pub struct Cli {
 
 	#[structopt(flatten)]
 	pub run: RunCmd,
+
+	#[structopt(flatten)]
+	pub health: crate::health::HealthParams,
+
+	#[structopt(flatten)]
+	pub standby: crate::standby::StandbyParams,
+
+	#[structopt(flatten)]
+	pub history: crate::history::HistoryParams,
 }
 
 #[derive(Debug, StructOpt)]
@@ -17,6 +26,12 @@ pub enum Subcommand {
 	/// Build a chain specification.
 	BuildSpec(sc_cli::BuildSpecCmd),
 
+	/// Generate deployment secrets and derive validator keys from them.
+	Secrets(crate::secrets::SecretsSubcommand),
+
+	/// Build the chain spec of a new environment from its secrets file.
+	EnvironmentSpec(crate::environment_spec::EnvironmentSpecCmd),
+
 	/// Validate blocks.
 	CheckBlock(sc_cli::CheckBlockCmd),
 
@@ -35,6 +50,18 @@ pub enum Subcommand {
 	/// Revert the chain to a previous state.
 	Revert(sc_cli::RevertCmd),
 
+	/// Run a node and index its finalized ColdStack and balances events into
+	/// an SQLite database.
+	IndexEvents(crate::event_index::IndexEventsCmd),
+
+	/// Export ColdStack ledger movements and balances of a block or date
+	/// range.
+	ExportLedger(crate::ledger_export::ExportLedgerCmd),
+
+	/// Write a chain spec whose ColdStack genesis reproduces the ColdStack
+	/// state of a block.
+	Regenesis(crate::regenesis::RegenesisCmd),
+
 	/// The custom benchmark subcommmand benchmarking runtime pallets.
 	#[structopt(name = "benchmark", about = "Benchmark runtime pallets.")]
 	Benchmark(frame_benchmarking_cli::BenchmarkCmd),
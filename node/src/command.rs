@@ -50,6 +50,8 @@ impl SubstrateCli for Cli {
 		Ok(match id {
 			"dev" => Box::new(chain_spec::development_config()?),
 			"" | "local" => Box::new(chain_spec::local_testnet_config()?),
+			"coldstack-prod" => Box::new(chain_spec::coldstack_prod_config()?),
+			"coldstack-staging" => Box::new(chain_spec::coldstack_staging_config()?),
 			path => Box::new(chain_spec::ChainSpec::from_json_file(
 				std::path::PathBuf::from(path),
 			)?),
@@ -67,6 +69,8 @@ pub fn run() -> sc_cli::Result<()> {
 
 	match &cli.subcommand {
 		Some(Subcommand::Key(cmd)) => cmd.run(&cli),
+		Some(Subcommand::EnvironmentSpec(cmd)) => cmd.run(),
+		Some(Subcommand::Secrets(cmd)) => cmd.run(&cli),
 		Some(Subcommand::BuildSpec(cmd)) => {
 			let runner = cli.create_runner(cmd)?;
 			runner.sync_run(|config| cmd.run(config.chain_spec, config.network))
@@ -125,12 +129,48 @@ pub fn run() -> sc_cli::Result<()> {
 				You can enable it with `--features runtime-benchmarks`.".into())
 			}
 		},
+		Some(Subcommand::ExportLedger(cmd)) => {
+			let runner = cli.create_runner(cmd)?;
+			runner.sync_run(|config| {
+				let PartialComponents { client, .. } = service::new_partial(&config)?;
+				cmd.run(client)
+			})
+		},
+		Some(Subcommand::Regenesis(cmd)) => {
+			let runner = cli.create_runner(cmd)?;
+			runner.sync_run(|config| {
+				let base_spec = match &cmd.base_spec {
+					Some(id) => cli.load_spec(id)?,
+					None => config.chain_spec.cloned_box(),
+				};
+				let PartialComponents { client, .. } = service::new_partial(&config)?;
+				cmd.run(client, base_spec)
+			})
+		},
+		Some(Subcommand::IndexEvents(cmd)) => {
+			let runner = cli.create_runner(&cmd.run)?;
+			runner.run_node_until_exit(|config| async move {
+				match config.role {
+					Role::Light => Err(sc_service::Error::Other(
+						"Indexing events needs a full node.".into()
+					)),
+					// Blocks are indexed from genesis, so their state must
+					// not have been pruned.
+					_ => service::ensure_archive(&config, "`index-events`").and_then(|()| service::new_full_with(
+						config, &Default::default(), &Default::default(),
+						|client, _, task_manager| cmd.spawn(client, task_manager),
+					)),
+				}.map_err(sc_cli::Error::Service)
+			})
+		},
 		None => {
 			let runner = cli.create_runner(&cli.run)?;
 			runner.run_node_until_exit(|config| async move {
 				match config.role {
 					Role::Light => service::new_light(config),
-					_ => service::new_full(config),
+					_ => service::new_full_with(config, &cli.standby, &cli.history, |client, network, task_manager| {
+						cli.health.spawn(client, network, task_manager)
+					}),
 				}.map_err(sc_cli::Error::Service)
 			})
 		}
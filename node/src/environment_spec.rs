@@ -0,0 +1,97 @@
+//! `environment-spec` subcommand: builds the chain spec of a new deployment
+//! from its secrets file, replacing `chainspec/create_chainspec.py`.
+
+use std::path::PathBuf;
+
+use node_template_runtime::{ColdStackConfig, WASM_BINARY};
+use sc_service::{config::MultiaddrWithPeerId, ChainType};
+use structopt::StructOpt;
+
+use crate::{chain_spec::{self, ChainSpec, TOTAL_ISSUANCE}, secrets::Secrets};
+
+/// Build the chain spec of a new environment from its secrets file.
+#[derive(Debug, StructOpt)]
+pub struct EnvironmentSpecCmd {
+	/// Secrets file with validator, sudo and admin mnemonics, as written by
+	/// `secrets generate`.
+	#[structopt(long, value_name = "FILE", parse(from_os_str))]
+	pub secrets: PathBuf,
+
+	/// Human readable name of the chain.
+	#[structopt(long, default_value = "ColdStack")]
+	pub name: String,
+
+	/// Chain id. Nodes keep their database under `chains/<id>`.
+	#[structopt(long, default_value = "coldstack")]
+	pub id: String,
+
+	/// IP address of the boot node, whose peer id is taken from the secrets
+	/// file.
+	#[structopt(long, value_name = "IP")]
+	pub boot_node_addr: Option<String>,
+
+	/// Additional boot nodes.
+	#[structopt(long, value_name = "ADDR")]
+	pub bootnodes: Vec<MultiaddrWithPeerId>,
+
+	/// Write the chain spec in raw form.
+	#[structopt(long)]
+	pub raw: bool,
+
+	/// Write the chain spec to this file instead of stdout.
+	#[structopt(long, value_name = "FILE", parse(from_os_str))]
+	pub output: Option<PathBuf>,
+}
+
+impl EnvironmentSpecCmd {
+	/// Build the chain spec and write it out.
+	pub fn run(&self) -> sc_cli::Result<()> {
+		let secrets = Secrets::read(&self.secrets)?;
+		let authorities = secrets.authority_keys()?;
+		let sudo = secrets.sudo_account()?;
+		let admin = secrets.admin_account()?;
+
+		let mut bootnodes = self.bootnodes.clone();
+		if let Some(addr) = &self.boot_node_addr {
+			let peer_id = secrets.peer_id.as_ref()
+				.ok_or("--boot-node-addr needs `peer_id` in the secrets file.")?;
+			bootnodes.push(format!("/ip4/{}/tcp/30333/p2p/{}", addr, peer_id).parse()
+				.map_err(|e| format!("Invalid boot node address: {:?}", e))?);
+		}
+
+		let wasm_binary = WASM_BINARY.ok_or("Wasm binary not available")?;
+		let spec = ChainSpec::from_genesis(
+			&self.name,
+			&self.id,
+			ChainType::Live,
+			move || {
+				// Sudo and validators pay transaction fees.
+				let mut endowed = vec![sudo.clone()];
+				endowed.extend(authorities.iter().map(|(account, _, _)| account.clone()));
+				chain_spec::testnet_genesis(
+					wasm_binary,
+					authorities.clone(),
+					sudo.clone(),
+					endowed,
+					ColdStackConfig {
+						key: admin.clone(),
+						total_issuance: TOTAL_ISSUANCE,
+						..Default::default()
+					},
+				)
+			},
+			bootnodes,
+			None,
+			None,
+			Some(chain_spec::properties()),
+			None,
+		);
+
+		let json = spec.as_json(self.raw)?;
+		match &self.output {
+			Some(path) => std::fs::write(path, json)?,
+			None => println!("{}", json),
+		}
+		Ok(())
+	}
+}
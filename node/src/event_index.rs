@@ -0,0 +1,510 @@
+//! `index-events` subcommand: copies ColdStack and balances events of
+//! finalized blocks into an SQLite database.
+//!
+//! Only finalized blocks are written, so rows never have to be rolled back
+//! on a re-org. Each block is written in one SQLite transaction together
+//! with its events, so an interrupted run resumes after the last block in
+//! `blocks`.
+
+use std::{path::{Path, PathBuf}, sync::Arc};
+
+use codec::Decode;
+use futures::StreamExt;
+use log::{error, info, warn};
+use node_template_runtime::{opaque::Block, Event, Hash};
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use sc_cli::RunCmd;
+use sc_client_api::{BlockchainEvents, StorageProvider};
+use sc_service::{error::Error as ServiceError, TaskManager};
+use sp_blockchain::HeaderBackend;
+use sp_core::{hashing::twox_128, storage::StorageKey};
+use sp_runtime::generic::BlockId;
+use structopt::StructOpt;
+
+use crate::service::FullClient;
+
+/// Version of the table layout below. A database written with another
+/// version is refused rather than mixed with new rows.
+const SCHEMA_VERSION: i64 = 1;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS meta (
+	key TEXT PRIMARY KEY,
+	value INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS blocks (
+	number INTEGER PRIMARY KEY,
+	hash BLOB NOT NULL UNIQUE,
+	timestamp INTEGER,
+	spec_version INTEGER NOT NULL,
+	events_decoded INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS events (
+	block_number INTEGER NOT NULL REFERENCES blocks (number),
+	event_index INTEGER NOT NULL,
+	extrinsic_index INTEGER,
+	pallet TEXT NOT NULL,
+	name TEXT NOT NULL,
+	PRIMARY KEY (block_number, event_index)
+);
+CREATE TABLE IF NOT EXISTS coldstack_files (
+	block_number INTEGER NOT NULL,
+	event_index INTEGER NOT NULL,
+	name TEXT NOT NULL,
+	user_eth_address BLOB NOT NULL,
+	file_name_hash BLOB NOT NULL,
+	file_size_bytes TEXT,
+	file_contents_hash BLOB,
+	gateway_eth_address BLOB,
+	filenode_eth_address BLOB NOT NULL,
+	file_storage_class INTEGER,
+	is_forced INTEGER,
+	PRIMARY KEY (block_number, event_index),
+	FOREIGN KEY (block_number, event_index) REFERENCES events (block_number, event_index)
+);
+CREATE INDEX IF NOT EXISTS coldstack_files_user ON coldstack_files (user_eth_address);
+CREATE TABLE IF NOT EXISTS coldstack_ledger (
+	block_number INTEGER NOT NULL,
+	event_index INTEGER NOT NULL,
+	name TEXT NOT NULL,
+	from_eth_address BLOB,
+	to_eth_address BLOB,
+	value TEXT NOT NULL,
+	PRIMARY KEY (block_number, event_index),
+	FOREIGN KEY (block_number, event_index) REFERENCES events (block_number, event_index)
+);
+CREATE INDEX IF NOT EXISTS coldstack_ledger_from ON coldstack_ledger (from_eth_address);
+CREATE INDEX IF NOT EXISTS coldstack_ledger_to ON coldstack_ledger (to_eth_address);
+CREATE TABLE IF NOT EXISTS coldstack_gateways (
+	block_number INTEGER NOT NULL,
+	event_index INTEGER NOT NULL,
+	name TEXT NOT NULL,
+	gateway_eth_address BLOB NOT NULL,
+	seed_eth_address BLOB,
+	storage INTEGER,
+	node_url TEXT,
+	is_active INTEGER,
+	PRIMARY KEY (block_number, event_index),
+	FOREIGN KEY (block_number, event_index) REFERENCES events (block_number, event_index)
+);
+CREATE TABLE IF NOT EXISTS coldstack_permissions (
+	block_number INTEGER NOT NULL,
+	event_index INTEGER NOT NULL,
+	name TEXT NOT NULL,
+	eth_address BLOB NOT NULL,
+	account_id BLOB NOT NULL,
+	node_url TEXT,
+	PRIMARY KEY (block_number, event_index),
+	FOREIGN KEY (block_number, event_index) REFERENCES events (block_number, event_index)
+);
+CREATE TABLE IF NOT EXISTS coldstack_failures (
+	block_number INTEGER NOT NULL,
+	event_index INTEGER NOT NULL,
+	name TEXT NOT NULL,
+	item_index INTEGER,
+	filenode_eth_address BLOB,
+	sequence INTEGER,
+	error TEXT NOT NULL,
+	FOREIGN KEY (block_number, event_index) REFERENCES events (block_number, event_index)
+);
+CREATE TABLE IF NOT EXISTS balances (
+	block_number INTEGER NOT NULL,
+	event_index INTEGER NOT NULL,
+	name TEXT NOT NULL,
+	account_id BLOB NOT NULL,
+	counterparty BLOB,
+	amount TEXT NOT NULL,
+	PRIMARY KEY (block_number, event_index),
+	FOREIGN KEY (block_number, event_index) REFERENCES events (block_number, event_index)
+);
+CREATE INDEX IF NOT EXISTS balances_account ON balances (account_id);
+";
+
+/// Run a full node and index its finalized ColdStack and balances events
+/// into an SQLite database. Needs `--pruning archive`.
+#[derive(Debug, StructOpt)]
+pub struct IndexEventsCmd {
+	/// SQLite database to write to. Created if it does not exist; indexing
+	/// resumes after the last block it holds.
+	#[structopt(long, value_name = "PATH", parse(from_os_str))]
+	pub sqlite: PathBuf,
+
+	#[allow(missing_docs)]
+	#[structopt(flatten)]
+	pub run: RunCmd,
+}
+
+impl IndexEventsCmd {
+	/// Spawn the indexer on `task_manager`. It first catches up with the
+	/// blocks already finalized in the local database, then follows
+	/// finality. Failing to index a block takes the node down.
+	///
+	/// Indexing reads the state of every block from genesis on, so the node
+	/// must run with `--pruning archive`.
+	pub fn spawn(&self, client: Arc<FullClient>, task_manager: &TaskManager) -> Result<(), ServiceError> {
+		let mut indexer = EventIndexer::open(&self.sqlite, client.clone())?;
+		let mut finality = client.finality_notification_stream();
+
+		task_manager.spawn_essential_handle().spawn_blocking("event-indexer", async move {
+			loop {
+				if let Err(e) = indexer.index_finalized() {
+					error!(target: "event-indexer", "Indexing stopped: {}", e);
+					return
+				}
+				if finality.next().await.is_none() {
+					return
+				}
+			}
+		});
+		Ok(())
+	}
+}
+
+/// Storage key of a plain storage value.
+pub(crate) fn storage_value_key(pallet: &[u8], item: &[u8]) -> StorageKey {
+	let mut key = twox_128(pallet).to_vec();
+	key.extend_from_slice(&twox_128(item));
+	StorageKey(key)
+}
+
+/// `Timestamp::Now` of block `at` in milliseconds, `None` for genesis.
+pub(crate) fn block_timestamp(client: &FullClient, at: &BlockId<Block>) -> Result<Option<u64>, ServiceError> {
+	Ok(client.storage(at, &storage_value_key(b"Timestamp", b"Now"))?
+		.and_then(|raw| u64::decode(&mut &raw.0[..]).ok()))
+}
+
+/// `System::Events` of block `at`, or `None` if they do not decode with
+/// this node's event layout.
+pub(crate) fn block_events(
+	client: &FullClient,
+	at: &BlockId<Block>,
+) -> Result<Option<Vec<frame_system::EventRecord<Event, Hash>>>, ServiceError> {
+	Ok(match client.storage(at, &storage_value_key(b"System", b"Events"))? {
+		Some(raw) => Vec::<frame_system::EventRecord<Event, Hash>>::decode(&mut &raw.0[..]).ok(),
+		None => Some(Vec::new()),
+	})
+}
+
+fn sql_error(e: rusqlite::Error) -> ServiceError {
+	ServiceError::Other(format!("Event index database error: {}", e))
+}
+
+/// Writes events of finalized blocks of `client` to an SQLite database.
+pub struct EventIndexer {
+	db: Connection,
+	client: Arc<FullClient>,
+}
+
+impl EventIndexer {
+	/// Open or create the database at `path` and check that it was written
+	/// from the same chain.
+	pub fn open(path: &Path, client: Arc<FullClient>) -> Result<Self, ServiceError> {
+		let db = Connection::open(path).map_err(sql_error)?;
+		db.execute_batch(SCHEMA).map_err(sql_error)?;
+
+		let version: Option<i64> = db
+			.query_row("SELECT value FROM meta WHERE key = 'schema_version'", [], |row| row.get(0))
+			.optional()
+			.map_err(sql_error)?;
+		match version {
+			None => {
+				db.execute("INSERT INTO meta (key, value) VALUES ('schema_version', ?1)", params![SCHEMA_VERSION])
+					.map_err(sql_error)?;
+			},
+			Some(SCHEMA_VERSION) => {},
+			Some(version) => return Err(ServiceError::Other(format!(
+				"Event index {} has schema version {}, expected {}; index into a new database.",
+				path.display(), version, SCHEMA_VERSION,
+			))),
+		}
+
+		let indexer = EventIndexer { db, client };
+		if let Some((number, hash)) = indexer.last_indexed()? {
+			let canonical = indexer.client.hash(number)?;
+			if canonical.as_ref().map(|h| h.as_bytes()) != Some(&hash[..]) {
+				return Err(ServiceError::Other(format!(
+					"Block #{} in {} is not on this node's chain; index into a new database.",
+					number, path.display(),
+				)))
+			}
+		}
+		Ok(indexer)
+	}
+
+	/// Number and hash of the last indexed block.
+	fn last_indexed(&self) -> Result<Option<(u32, Vec<u8>)>, ServiceError> {
+		self.db
+			.query_row("SELECT number, hash FROM blocks ORDER BY number DESC LIMIT 1", [], |row| {
+				Ok((row.get(0)?, row.get(1)?))
+			})
+			.optional()
+			.map_err(sql_error)
+	}
+
+	/// Index all finalized blocks after the last indexed one.
+	pub fn index_finalized(&mut self) -> Result<(), ServiceError> {
+		let finalized = self.client.info().finalized_number;
+		let next = match self.last_indexed()? {
+			Some((number, _)) => number + 1,
+			None => 0,
+		};
+		for number in next..=finalized {
+			self.index_block(number)?;
+			if number % 10_000 == 0 {
+				info!(target: "event-indexer", "Indexed events up to block #{}", number);
+			}
+		}
+		Ok(())
+	}
+
+	fn index_block(&mut self, number: u32) -> Result<(), ServiceError> {
+		let hash = self.client.hash(number)?
+			.ok_or_else(|| ServiceError::Other(format!("Finalized block #{} not found.", number)))?;
+		let at = BlockId::<Block>::Hash(hash);
+
+		let timestamp = block_timestamp(&self.client, &at)?;
+		let spec_version = self.client.runtime_version_at(&at)?.spec_version;
+		let records = block_events(&self.client, &at)?;
+		if records.is_none() {
+			// Events of runtimes with another event layout, see docs/events.md.
+			warn!(
+				target: "event-indexer",
+				"Unable to decode events of block #{} (spec_version {}); recorded with events_decoded = 0",
+				number, spec_version,
+			);
+		}
+
+		let tx = self.db.transaction().map_err(sql_error)?;
+		tx.execute(
+			"INSERT INTO blocks (number, hash, timestamp, spec_version, events_decoded) VALUES (?1, ?2, ?3, ?4, ?5)",
+			params![number, hash.as_bytes(), timestamp.map(|t| t as i64), spec_version, records.is_some()],
+		).map_err(sql_error)?;
+		for (index, record) in records.unwrap_or_default().into_iter().enumerate() {
+			let extrinsic_index = match record.phase {
+				frame_system::Phase::ApplyExtrinsic(index) => Some(index),
+				_ => None,
+			};
+			insert_event(&tx, number, index as u32, extrinsic_index, record.event).map_err(sql_error)?;
+		}
+		tx.commit().map_err(sql_error)
+	}
+}
+
+fn insert_event(
+	tx: &Transaction,
+	block_number: u32,
+	event_index: u32,
+	extrinsic_index: Option<u32>,
+	event: Event,
+) -> rusqlite::Result<()> {
+	let (pallet, name) = match &event {
+		Event::ColdStack(event) => ("ColdStack", coldstack_event_name(event)),
+		Event::Balances(event) => ("Balances", balances_event_name(event)),
+		_ => return Ok(()),
+	};
+	tx.execute(
+		"INSERT INTO events (block_number, event_index, extrinsic_index, pallet, name) VALUES (?1, ?2, ?3, ?4, ?5)",
+		params![block_number, event_index, extrinsic_index, pallet, name],
+	)?;
+
+	match event {
+		Event::ColdStack(event) => insert_coldstack_event(tx, block_number, event_index, name, event),
+		Event::Balances(event) => insert_balances_event(tx, block_number, event_index, name, event),
+		_ => Ok(()),
+	}
+}
+
+type ColdStackEvent = pallet_template::Event<node_template_runtime::Runtime>;
+type BalancesEvent = pallet_balances::Event<node_template_runtime::Runtime>;
+
+fn coldstack_event_name(event: &ColdStackEvent) -> &'static str {
+	use pallet_template::Event::*;
+	match event {
+		Upload { .. } => "Upload",
+		ChangeStatusGateway { .. } => "ChangeStatusGateway",
+		DeleteGateway { .. } => "DeleteGateway",
+		Download { .. } => "Download",
+		Delete { .. } => "Delete",
+		Deposit { .. } => "Deposit",
+		Withdraw { .. } => "Withdraw",
+		Transfer { .. } => "Transfer",
+		StartStaking { .. } => "StartStaking",
+		CancelStaking { .. } => "CancelStaking",
+		EndStaking { .. } => "EndStaking",
+		RewardStaking { .. } => "RewardStaking",
+		FilePermissionGranted { .. } => "FilePermissionGranted",
+		FilePermissionRevoked { .. } => "FilePermissionRevoked",
+		BillingPermissionGranted { .. } => "BillingPermissionGranted",
+		BillingPermissionRevoked { .. } => "BillingPermissionRevoked",
+		GatewayNodeRegistered { .. } => "GatewayNodeRegistered",
+		BatchCompleted { .. } => "BatchCompleted",
+		BatchInterrupted { .. } => "BatchInterrupted",
+		UsageRejected { .. } => "UsageRejected",
+		KeyChanged { .. } => "KeyChanged",
+		_ => "Unknown",
+	}
+}
+
+fn balances_event_name(event: &BalancesEvent) -> &'static str {
+	use pallet_balances::Event::*;
+	match event {
+		Endowed(..) => "Endowed",
+		DustLost(..) => "DustLost",
+		Transfer(..) => "Transfer",
+		BalanceSet(..) => "BalanceSet",
+		Deposit(..) => "Deposit",
+		Reserved(..) => "Reserved",
+		Unreserved(..) => "Unreserved",
+		ReserveRepatriated(..) => "ReserveRepatriated",
+		_ => "Unknown",
+	}
+}
+
+fn insert_coldstack_event(
+	tx: &Transaction,
+	block_number: u32,
+	event_index: u32,
+	name: &str,
+	event: ColdStackEvent,
+) -> rusqlite::Result<()> {
+	use pallet_template::Event::*;
+
+	let ledger = |from: Option<&[u8]>, to: Option<&[u8]>, value: u128| tx.execute(
+		"INSERT INTO coldstack_ledger (block_number, event_index, name, from_eth_address, to_eth_address, value)
+		VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+		params![block_number, event_index, name, from, to, value.to_string()],
+	);
+	let permission = |eth_address: &[u8], account_id: &[u8], node_url: Option<&[u8]>| tx.execute(
+		"INSERT INTO coldstack_permissions (block_number, event_index, name, eth_address, account_id, node_url)
+		VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+		params![
+			block_number, event_index, name, eth_address, account_id,
+			node_url.map(|url| String::from_utf8_lossy(url).into_owned()),
+		],
+	);
+	let failure = |item_index: Option<u32>, filenode: Option<&[u8]>, sequence: Option<u64>, error: String| tx.execute(
+		"INSERT INTO coldstack_failures (block_number, event_index, name, item_index, filenode_eth_address, sequence, error)
+		VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+		params![block_number, event_index, name, item_index, filenode, sequence.map(|s| s as i64), error],
+	);
+
+	let inserted = match event {
+		Upload {
+			user_eth_address,
+			file_name_hash,
+			file_size_bytes,
+			file_contents_hash,
+			gateway_eth_address,
+			filenode_eth_address,
+			file_storage_class,
+			is_forced,
+		} => tx.execute(
+			"INSERT INTO coldstack_files (block_number, event_index, name, user_eth_address, file_name_hash,
+				file_size_bytes, file_contents_hash, gateway_eth_address, filenode_eth_address,
+				file_storage_class, is_forced)
+			VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+			params![
+				block_number, event_index, name, user_eth_address, file_name_hash,
+				file_size_bytes.to_string(), file_contents_hash, gateway_eth_address, filenode_eth_address,
+				file_storage_class, is_forced,
+			],
+		),
+		Download {
+			user_eth_address,
+			file_name_hash,
+			file_size_bytes,
+			file_contents_hash,
+			gateway_eth_address,
+			filenode_eth_address,
+		} => tx.execute(
+			"INSERT INTO coldstack_files (block_number, event_index, name, user_eth_address, file_name_hash,
+				file_size_bytes, file_contents_hash, gateway_eth_address, filenode_eth_address)
+			VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+			params![
+				block_number, event_index, name, user_eth_address, file_name_hash,
+				file_size_bytes.to_string(), file_contents_hash, gateway_eth_address, filenode_eth_address,
+			],
+		),
+		Delete { user_eth_address, file_name_hash, filenode_eth_address } => tx.execute(
+			"INSERT INTO coldstack_files (block_number, event_index, name, user_eth_address, file_name_hash,
+				filenode_eth_address)
+			VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+			params![block_number, event_index, name, user_eth_address, file_name_hash, filenode_eth_address],
+		),
+		Deposit { eth_address, value } => ledger(None, Some(&eth_address), value),
+		Withdraw { eth_address, value } => ledger(Some(&eth_address), None, value),
+		Transfer { from, to, value }
+		| StartStaking { from, to, value }
+		| CancelStaking { from, to, value }
+		| EndStaking { from, to, value } => ledger(Some(&from), Some(&to), value),
+		RewardStaking { from, eth_address, value } => ledger(Some(&from), Some(&eth_address), value),
+		FilePermissionGranted { eth_address, account_id, node_url }
+		| BillingPermissionGranted { eth_address, account_id, node_url } =>
+			permission(&eth_address, AsRef::<[u8]>::as_ref(&account_id), Some(&node_url)),
+		FilePermissionRevoked { eth_address, account_id }
+		| BillingPermissionRevoked { eth_address, account_id } =>
+			permission(&eth_address, AsRef::<[u8]>::as_ref(&account_id), None),
+		GatewayNodeRegistered { eth_address, seed_eth_address, storage, node_url, is_active } => tx.execute(
+			"INSERT INTO coldstack_gateways (block_number, event_index, name, gateway_eth_address,
+				seed_eth_address, storage, node_url, is_active)
+			VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+			params![
+				block_number, event_index, name, eth_address, seed_eth_address, storage,
+				String::from_utf8_lossy(&node_url).into_owned(), is_active,
+			],
+		),
+		ChangeStatusGateway { gateway_eth_address, is_active } => tx.execute(
+			"INSERT INTO coldstack_gateways (block_number, event_index, name, gateway_eth_address, is_active)
+			VALUES (?1, ?2, ?3, ?4, ?5)",
+			params![block_number, event_index, name, gateway_eth_address, is_active],
+		),
+		DeleteGateway { gateway_eth_address } => tx.execute(
+			"INSERT INTO coldstack_gateways (block_number, event_index, name, gateway_eth_address)
+			VALUES (?1, ?2, ?3, ?4)",
+			params![block_number, event_index, name, gateway_eth_address],
+		),
+		BatchCompleted { failed, .. } => {
+			for (index, error) in failed {
+				failure(Some(index), None, None, format!("{:?}", error))?;
+			}
+			Ok(0)
+		},
+		BatchInterrupted { index, error } => failure(Some(index), None, None, format!("{:?}", error)),
+		UsageRejected { filenode_eth_address, sequence, error } =>
+			failure(None, Some(&filenode_eth_address), Some(sequence), format!("{:?}", error)),
+		_ => Ok(0),
+	};
+	inserted.map(|_| ())
+}
+
+fn insert_balances_event(
+	tx: &Transaction,
+	block_number: u32,
+	event_index: u32,
+	name: &str,
+	event: BalancesEvent,
+) -> rusqlite::Result<()> {
+	use pallet_balances::Event::*;
+
+	let (account_id, counterparty, amount) = match event {
+		Endowed(who, amount)
+		| DustLost(who, amount)
+		| Deposit(who, amount)
+		| Reserved(who, amount)
+		| Unreserved(who, amount)
+		| BalanceSet(who, amount, _) => (who, None, amount),
+		Transfer(from, to, amount) | ReserveRepatriated(from, to, amount, _) => (from, Some(to), amount),
+		_ => return Ok(()),
+	};
+	tx.execute(
+		"INSERT INTO balances (block_number, event_index, name, account_id, counterparty, amount)
+		VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+		params![
+			block_number, event_index, name,
+			AsRef::<[u8]>::as_ref(&account_id),
+			counterparty.as_ref().map(AsRef::<[u8]>::as_ref),
+			amount.to_string(),
+		],
+	)?;
+	Ok(())
+}
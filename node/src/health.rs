@@ -0,0 +1,195 @@
+//! `/health` and `/ready` HTTP endpoints for orchestrator probes.
+//!
+//! `/health` fails when the node stopped importing blocks, whether it
+//! authors them or syncs them. `/ready` additionally needs the node to be in
+//! sync, connected and close to finality. Both answer with a JSON report and
+//! status 200, or 503 listing the failed checks.
+
+use std::{
+	io::{BufRead, BufReader, Write},
+	net::{SocketAddr, TcpListener, TcpStream},
+	sync::{Arc, Mutex},
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use futures::StreamExt;
+use log::{info, warn};
+use node_template_runtime::opaque::Block;
+use sc_client_api::BlockchainEvents;
+use sc_service::{error::Error as ServiceError, TaskManager};
+use serde::Serialize;
+use sp_blockchain::HeaderBackend;
+use sp_runtime::generic::BlockId;
+use structopt::StructOpt;
+
+use crate::{event_index::block_timestamp, service::{FullClient, FullNetwork}};
+
+/// How often the listener checks for probes.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Health endpoint options.
+#[derive(Debug, Clone, StructOpt)]
+pub struct HealthParams {
+	/// Serve `/health` and `/ready` on this address, e.g. `0.0.0.0:9955`.
+	#[structopt(long, value_name = "ADDR")]
+	pub health_listen: Option<SocketAddr>,
+
+	/// Longest time in seconds without a new best block, and largest age of
+	/// the best block for `/ready`.
+	#[structopt(long, value_name = "SECONDS", default_value = "60")]
+	pub health_max_block_age: u64,
+
+	/// Largest distance between the best and the finalized block for
+	/// `/ready`.
+	#[structopt(long, value_name = "BLOCKS", default_value = "20")]
+	pub health_max_finality_lag: u32,
+
+	/// Fewest connected peers for `/ready`.
+	#[structopt(long, value_name = "COUNT", default_value = "1")]
+	pub health_min_peers: usize,
+}
+
+/// State of the node as reported by the endpoints.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthReport {
+	pub best_number: u32,
+	pub finalized_number: u32,
+	pub finality_lag: u32,
+	/// Seconds since the node last imported a new best block, or since it
+	/// started.
+	pub seconds_since_import: u64,
+	/// Seconds since the timestamp of the best block.
+	pub best_block_age: Option<u64>,
+	pub peers: usize,
+	pub is_syncing: bool,
+	/// Checks that failed, empty if the probe passed.
+	pub failures: Vec<String>,
+}
+
+struct Probe {
+	params: HealthParams,
+	client: Arc<FullClient>,
+	network: Arc<FullNetwork>,
+	last_import: Arc<Mutex<Instant>>,
+}
+
+impl Probe {
+	fn report(&self, ready: bool) -> HealthReport {
+		let info = self.client.info();
+		let seconds_since_import = self.last_import.lock().expect("not poisoned; qed").elapsed().as_secs();
+		let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+		let best_block_age = block_timestamp(&self.client, &BlockId::<Block>::Hash(info.best_hash))
+			.ok()
+			.flatten()
+			.map(|timestamp| now.saturating_sub(timestamp) / 1000);
+		let mut report = HealthReport {
+			best_number: info.best_number,
+			finalized_number: info.finalized_number,
+			finality_lag: info.best_number.saturating_sub(info.finalized_number),
+			seconds_since_import,
+			best_block_age,
+			peers: self.network.num_connected(),
+			is_syncing: self.network.is_major_syncing(),
+			failures: Vec::new(),
+		};
+
+		let params = &self.params;
+		let mut failures = Vec::new();
+		let mut check = |ok: bool, failure: String| if !ok {
+			failures.push(failure)
+		};
+		check(
+			seconds_since_import <= params.health_max_block_age,
+			format!("no new best block for {}s", seconds_since_import),
+		);
+		if ready {
+			check(!report.is_syncing, "major sync in progress".into());
+			check(
+				report.peers >= params.health_min_peers,
+				format!("{} peers, expected at least {}", report.peers, params.health_min_peers),
+			);
+			check(
+				report.finality_lag <= params.health_max_finality_lag,
+				format!("finality lags {} blocks behind", report.finality_lag),
+			);
+			check(
+				best_block_age.map_or(false, |age| age <= params.health_max_block_age),
+				format!("best block is {}s old", best_block_age.map_or("?".into(), |age| age.to_string())),
+			);
+		}
+		report.failures = failures;
+		report
+	}
+
+	fn answer(&self, stream: TcpStream) -> std::io::Result<()> {
+		stream.set_nonblocking(false)?;
+		stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+		let mut request_line = String::new();
+		BufReader::new(&stream).read_line(&mut request_line)?;
+		let path = request_line.split(' ').nth(1).unwrap_or("");
+
+		let (status, body) = match path {
+			"/health" | "/ready" => {
+				let report = self.report(path == "/ready");
+				let status = if report.failures.is_empty() { "200 OK" } else { "503 Service Unavailable" };
+				(status, serde_json::to_string(&report).unwrap_or_default())
+			},
+			_ => ("404 Not Found", String::new()),
+		};
+		let mut stream = stream;
+		write!(
+			stream,
+			"HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+			status, body.len(), body,
+		)
+	}
+}
+
+impl HealthParams {
+	/// Serve the endpoints, if `--health-listen` is given.
+	pub fn spawn(
+		&self,
+		client: Arc<FullClient>,
+		network: Arc<FullNetwork>,
+		task_manager: &TaskManager,
+	) -> Result<(), ServiceError> {
+		let address = match self.health_listen {
+			Some(address) => address,
+			None => return Ok(()),
+		};
+		let listener = TcpListener::bind(address)
+			.map_err(|e| ServiceError::Other(format!("Unable to serve health endpoint on {}: {}", address, e)))?;
+		listener.set_nonblocking(true)?;
+		info!("Health endpoint on http://{}/health and /ready", address);
+
+		let last_import = Arc::new(Mutex::new(Instant::now()));
+		let mut imports = client.import_notification_stream();
+		let imported = last_import.clone();
+		task_manager.spawn_handle().spawn("health-imports", async move {
+			while let Some(notification) = imports.next().await {
+				if notification.is_new_best {
+					*imported.lock().expect("not poisoned; qed") = Instant::now();
+				}
+			}
+		});
+
+		let probe = Probe { params: self.clone(), client, network, last_import };
+		task_manager.spawn_handle().spawn_blocking("health-endpoint", async move {
+			loop {
+				match listener.accept() {
+					Ok((stream, _)) => if let Err(e) = probe.answer(stream) {
+						warn!("Health probe failed: {}", e);
+					},
+					Err(e) => {
+						if e.kind() != std::io::ErrorKind::WouldBlock {
+							warn!("Health endpoint: {}", e);
+						}
+						futures_timer::Delay::new(POLL_INTERVAL).await
+					},
+				}
+			}
+		});
+		Ok(())
+	}
+}
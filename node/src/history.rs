@@ -0,0 +1,125 @@
+//! Writes the ColdStack file and ledger history of finalized blocks to the
+//! offchain database, where `coldstack_fileInfo`, `coldstack_fileHistory`
+//! and `coldstack_ledgerHistory` read it.
+//!
+//! Only finalized blocks are written, so no record has to be rolled back on
+//! a re-org. Ledger balances are replayed from the state of each block's
+//! parent, hence the node must run with `--pruning archive`.
+//!
+//! The writer only runs with `--coldstack-history`.
+
+use std::sync::Arc;
+
+use codec::Decode;
+use futures::StreamExt;
+use log::{error, info, warn};
+use node_template_runtime::{opaque::Block, Event};
+use pallet_template_rpc::{proof::balance_storage_key, HistoryWriter};
+use sc_client_api::{BlockchainEvents, StorageProvider};
+use sc_service::{error::Error as ServiceError, TaskManager};
+use sp_blockchain::HeaderBackend;
+use sp_core::{offchain::OffchainStorage, storage::StorageKey};
+use sp_runtime::generic::BlockId;
+use structopt::StructOpt;
+
+use crate::{event_index::block_events, service::FullClient};
+
+/// ColdStack history options.
+#[derive(Debug, Clone, Default, StructOpt)]
+pub struct HistoryParams {
+	/// Write the ColdStack file and ledger history of finalized blocks to the
+	/// offchain database, for `coldstack_fileInfo`, `coldstack_fileHistory`
+	/// and `coldstack_ledgerHistory`. Needs `--pruning archive`.
+	#[structopt(long)]
+	pub coldstack_history: bool,
+}
+
+/// Spawn the history writer on `task_manager`. It first catches up with the
+/// blocks already finalized in the local database, then follows finality.
+/// Failing to write a block takes the node down.
+pub fn spawn<S: OffchainStorage + 'static>(
+	client: Arc<FullClient>,
+	storage: S,
+	task_manager: &TaskManager,
+) -> Result<(), ServiceError> {
+	let mut writer = HistoryWriter::new(storage);
+	if let Some((number, hash)) = writer.last_block() {
+		let canonical = client.hash(number as u32)?;
+		if canonical.as_ref().map(|h| h.as_bytes()) != Some(&hash[..]) {
+			return Err(ServiceError::Other(format!(
+				"Block #{} of the ColdStack history is not on this node's chain; \
+				start from a new base path.",
+				number,
+			)))
+		}
+	}
+	let mut finality = client.finality_notification_stream();
+
+	task_manager.spawn_essential_handle().spawn_blocking("history-writer", async move {
+		loop {
+			if let Err(e) = write_finalized(&client, &mut writer) {
+				error!(target: "history-writer", "Writing history stopped: {}", e);
+				return
+			}
+			if finality.next().await.is_none() {
+				return
+			}
+		}
+	});
+	Ok(())
+}
+
+/// Write all finalized blocks after the last written one. Genesis has no
+/// events, so writing starts with block 1.
+fn write_finalized<S: OffchainStorage>(
+	client: &FullClient,
+	writer: &mut HistoryWriter<S>,
+) -> Result<(), ServiceError> {
+	let finalized = client.info().finalized_number;
+	let next = writer.last_block().map_or(1, |(number, _)| number as u32 + 1);
+	for number in next..=finalized {
+		write_block(client, writer, number)?;
+		if number % 10_000 == 0 {
+			info!(target: "history-writer", "Wrote history up to block #{}", number);
+		}
+	}
+	Ok(())
+}
+
+fn write_block<S: OffchainStorage>(
+	client: &FullClient,
+	writer: &mut HistoryWriter<S>,
+	number: u32,
+) -> Result<(), ServiceError> {
+	let hash = client.hash(number)?
+		.ok_or_else(|| ServiceError::Other(format!("Finalized block #{} not found.", number)))?;
+	let parent = BlockId::<Block>::Number(number - 1);
+
+	let events = match block_events(client, &BlockId::Hash(hash))? {
+		Some(records) => records.into_iter()
+			.filter_map(|record| match record.event {
+				Event::ColdStack(event) => Some(event),
+				_ => None,
+			})
+			.collect(),
+		None => {
+			// Events of runtimes with another event layout, see docs/events.md.
+			warn!(
+				target: "history-writer",
+				"Unable to decode events of block #{}; it is left out of the history",
+				number,
+			);
+			Vec::new()
+		},
+	};
+
+	writer.write_block(number as u64, hash.as_bytes(), events, |eth_address| {
+		let key = StorageKey(balance_storage_key(eth_address));
+		Ok(match client.storage(&parent, &key)? {
+			Some(raw) => u128::decode(&mut &raw.0[..]).map_err(|e| ServiceError::Other(format!(
+				"Unable to decode balance at block #{}: {}", number - 1, e,
+			)))?,
+			None => 0,
+		})
+	})
+}
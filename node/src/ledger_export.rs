@@ -0,0 +1,325 @@
+//! `export-ledger` subcommand: exports ColdStack balance movements of a
+//! block or date range, with opening and closing balances per ETH address.
+
+use std::{
+	collections::BTreeMap,
+	fs::File,
+	io::{BufWriter, Write},
+	path::PathBuf,
+	sync::Arc,
+};
+
+use chrono::{Duration, NaiveDate, TimeZone, Utc};
+use codec::Decode;
+use node_template_runtime::opaque::Block;
+use pallet_template_rpc::proof::balance_storage_key;
+use sc_cli::{CliConfiguration, DatabaseParams, PruningParams, SharedParams};
+use sc_client_api::StorageProvider;
+use sc_service::error::Error as ServiceError;
+use serde::Serialize;
+use sp_blockchain::HeaderBackend;
+use sp_core::storage::StorageKey;
+use sp_runtime::generic::BlockId;
+use structopt::StructOpt;
+
+use crate::{event_index::{block_events, block_timestamp}, service::FullClient};
+
+/// Output format of `export-ledger`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LedgerFormat {
+	Csv,
+	Json,
+}
+
+impl std::str::FromStr for LedgerFormat {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"csv" => Ok(LedgerFormat::Csv),
+			"json" => Ok(LedgerFormat::Json),
+			_ => Err(format!("Unknown format `{}`, expected `csv` or `json`", s)),
+		}
+	}
+}
+
+/// Export ColdStack ledger movements of finalized blocks from the local
+/// database, with opening and closing balances per ETH address.
+#[derive(Debug, StructOpt)]
+pub struct ExportLedgerCmd {
+	/// First block to export.
+	#[structopt(long, value_name = "BLOCK", conflicts_with = "from-date")]
+	pub from_block: Option<u32>,
+
+	/// Last block to export. Defaults to the last finalized block.
+	#[structopt(long, value_name = "BLOCK", conflicts_with = "to-date")]
+	pub to_block: Option<u32>,
+
+	/// First day to export, `YYYY-MM-DD` in UTC.
+	#[structopt(long, value_name = "DATE", parse(try_from_str = parse_date))]
+	pub from_date: Option<NaiveDate>,
+
+	/// Last day to export, inclusive, `YYYY-MM-DD` in UTC.
+	#[structopt(long, value_name = "DATE", parse(try_from_str = parse_date))]
+	pub to_date: Option<NaiveDate>,
+
+	/// `csv` or `json`.
+	#[structopt(long, value_name = "FORMAT", default_value = "csv")]
+	pub format: LedgerFormat,
+
+	/// Directory to write `ledger.csv` and `balances.csv`, or `ledger.json`,
+	/// to.
+	#[structopt(long, value_name = "DIR", parse(from_os_str))]
+	pub output_dir: PathBuf,
+
+	#[allow(missing_docs)]
+	#[structopt(flatten)]
+	pub shared_params: SharedParams,
+
+	#[allow(missing_docs)]
+	#[structopt(flatten)]
+	pub pruning_params: PruningParams,
+
+	#[allow(missing_docs)]
+	#[structopt(flatten)]
+	pub database_params: DatabaseParams,
+}
+
+fn parse_date(s: &str) -> Result<NaiveDate, String> {
+	NaiveDate::parse_from_str(s, "%Y-%m-%d").map_err(|e| format!("Invalid date `{}`: {}", s, e))
+}
+
+/// A balance movement of a ColdStack event.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Movement {
+	pub block_number: u32,
+	pub block_hash: String,
+	/// Block time, RFC 3339 in UTC.
+	pub block_time: String,
+	pub extrinsic_index: Option<u32>,
+	pub event_index: u32,
+	/// Event name, e.g. `Transfer`.
+	pub action: &'static str,
+	/// Debited address, empty for deposits and staking rewards.
+	pub from: Option<String>,
+	/// Credited address, empty for withdrawals.
+	pub to: Option<String>,
+	pub value: String,
+}
+
+/// Balances of an ETH address at the edges of the exported range.
+///
+/// `opening + credited - debited = closing` unless the balance also changed
+/// outside of ledger events, which `reconciled` flags.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddressBalance {
+	pub eth_address: String,
+	pub opening: String,
+	pub credited: String,
+	pub debited: String,
+	pub closing: String,
+	pub reconciled: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LedgerExport {
+	from_block: u32,
+	to_block: u32,
+	movements: Vec<Movement>,
+	balances: Vec<AddressBalance>,
+}
+
+fn hex(bytes: &[u8]) -> String {
+	format!("0x{}", sp_core::hexdisplay::HexDisplay::from(&bytes))
+}
+
+fn block_time(timestamp: Option<u64>) -> String {
+	Utc.timestamp_millis(timestamp.unwrap_or(0) as i64).to_rfc3339()
+}
+
+impl ExportLedgerCmd {
+	/// Run the export against `client`.
+	pub fn run(&self, client: Arc<FullClient>) -> sc_cli::Result<()> {
+		let finalized = client.info().finalized_number;
+		let from_block = match (self.from_block, self.from_date) {
+			(Some(block), _) => block,
+			(None, Some(date)) => first_block_at(&client, date_millis(date), finalized)?,
+			(None, None) => return Err("Either --from-block or --from-date is required.".into()),
+		};
+		let to_block = match (self.to_block, self.to_date) {
+			(Some(block), _) => block.min(finalized),
+			(None, Some(date)) => first_block_at(&client, date_millis(date + Duration::days(1)), finalized)?
+				.saturating_sub(1),
+			(None, None) => finalized,
+		};
+		if from_block > to_block {
+			return Err(format!("Empty block range #{}..=#{}.", from_block, to_block).into())
+		}
+
+		let mut movements = Vec::new();
+		// credited, debited
+		let mut totals = BTreeMap::<Vec<u8>, (u128, u128)>::new();
+		for number in from_block..=to_block {
+			let hash = canonical_hash(&client, number)?;
+			let at = BlockId::<Block>::Hash(hash);
+			let time = block_time(block_timestamp(&client, &at)?);
+			let records = block_events(&client, &at)?.ok_or_else(|| format!(
+				"Unable to decode events of block #{}; it predates this node's event layout.",
+				number,
+			))?;
+
+			for (event_index, record) in records.into_iter().enumerate() {
+				let event = match record.event {
+					node_template_runtime::Event::ColdStack(event) => event,
+					_ => continue,
+				};
+				let (action, from, to, value) = match ledger_movement(event) {
+					Some(movement) => movement,
+					None => continue,
+				};
+				if let Some(from) = &from {
+					totals.entry(from.clone()).or_default().1 += value;
+				}
+				if let Some(to) = &to {
+					totals.entry(to.clone()).or_default().0 += value;
+				}
+				movements.push(Movement {
+					block_number: number,
+					block_hash: format!("{:?}", hash),
+					block_time: time.clone(),
+					extrinsic_index: match record.phase {
+						frame_system::Phase::ApplyExtrinsic(index) => Some(index),
+						_ => None,
+					},
+					event_index: event_index as u32,
+					action,
+					from: from.as_deref().map(hex),
+					to: to.as_deref().map(hex),
+					value: value.to_string(),
+				});
+			}
+		}
+
+		let opening_at = match from_block {
+			0 => None,
+			number => Some(BlockId::<Block>::Hash(canonical_hash(&client, number - 1)?)),
+		};
+		let closing_at = BlockId::<Block>::Hash(canonical_hash(&client, to_block)?);
+		let mut balances = Vec::new();
+		for (eth_address, (credited, debited)) in totals {
+			let opening = match &opening_at {
+				Some(at) => balance_at(&client, at, &eth_address)?,
+				None => 0,
+			};
+			let closing = balance_at(&client, &closing_at, &eth_address)?;
+			balances.push(AddressBalance {
+				eth_address: hex(&eth_address),
+				opening: opening.to_string(),
+				credited: credited.to_string(),
+				debited: debited.to_string(),
+				closing: closing.to_string(),
+				reconciled: (opening + credited).checked_sub(debited) == Some(closing),
+			});
+		}
+
+		std::fs::create_dir_all(&self.output_dir)?;
+		match self.format {
+			LedgerFormat::Json => {
+				let file = File::create(self.output_dir.join("ledger.json"))?;
+				let export = LedgerExport { from_block, to_block, movements, balances };
+				serde_json::to_writer_pretty(BufWriter::new(file), &export)
+					.map_err(|e| format!("Unable to write ledger: {}", e))?;
+			},
+			LedgerFormat::Csv => {
+				let mut out = BufWriter::new(File::create(self.output_dir.join("ledger.csv"))?);
+				writeln!(out, "block_number,block_hash,block_time,extrinsic_index,event_index,action,from,to,value")?;
+				for m in movements {
+					writeln!(
+						out, "{},{},{},{},{},{},{},{},{}",
+						m.block_number, m.block_hash, m.block_time,
+						m.extrinsic_index.map(|i| i.to_string()).unwrap_or_default(), m.event_index,
+						m.action, m.from.unwrap_or_default(), m.to.unwrap_or_default(), m.value,
+					)?;
+				}
+				out.flush()?;
+
+				let mut out = BufWriter::new(File::create(self.output_dir.join("balances.csv"))?);
+				writeln!(out, "eth_address,opening,credited,debited,closing,reconciled")?;
+				for b in balances {
+					writeln!(
+						out, "{},{},{},{},{},{}",
+						b.eth_address, b.opening, b.credited, b.debited, b.closing, b.reconciled,
+					)?;
+				}
+				out.flush()?;
+			},
+		}
+		Ok(())
+	}
+}
+
+/// Name, debited address, credited address and value of a ledger event.
+fn ledger_movement(
+	event: pallet_template::Event<node_template_runtime::Runtime>,
+) -> Option<(&'static str, Option<Vec<u8>>, Option<Vec<u8>>, u128)> {
+	use pallet_template::Event::*;
+	Some(match event {
+		Deposit { eth_address, value } => ("Deposit", None, Some(eth_address), value),
+		Withdraw { eth_address, value } => ("Withdraw", Some(eth_address), None, value),
+		Transfer { from, to, value } => ("Transfer", Some(from), Some(to), value),
+		StartStaking { from, to, value } => ("StartStaking", Some(from), Some(to), value),
+		CancelStaking { from, to, value } => ("CancelStaking", Some(from), Some(to), value),
+		EndStaking { from, to, value } => ("EndStaking", Some(from), Some(to), value),
+		// The reward comes out of the locked funds; `from` is not debited.
+		RewardStaking { eth_address, value, .. } => ("RewardStaking", None, Some(eth_address), value),
+		_ => return None,
+	})
+}
+
+fn canonical_hash(client: &FullClient, number: u32) -> Result<node_template_runtime::Hash, ServiceError> {
+	client.hash(number)?
+		.ok_or_else(|| ServiceError::Other(format!("Block #{} not found.", number)))
+}
+
+fn balance_at(client: &FullClient, at: &BlockId<Block>, eth_address: &[u8]) -> Result<u128, ServiceError> {
+	let raw = client.storage(at, &StorageKey(balance_storage_key(eth_address)))?;
+	Ok(raw.and_then(|raw| u128::decode(&mut &raw.0[..]).ok()).unwrap_or(0))
+}
+
+/// Start of `date` in milliseconds since the epoch.
+fn date_millis(date: NaiveDate) -> u64 {
+	date.and_hms(0, 0, 0).timestamp_millis().max(0) as u64
+}
+
+/// First block up to `finalized` with a timestamp at or after `millis`, or
+/// `finalized + 1` if there is none. Block timestamps never decrease.
+fn first_block_at(client: &FullClient, millis: u64, finalized: u32) -> Result<u32, ServiceError> {
+	let (mut low, mut high) = (0, finalized + 1);
+	while low < high {
+		let mid = low + (high - low) / 2;
+		let at = BlockId::<Block>::Hash(canonical_hash(client, mid)?);
+		if block_timestamp(client, &at)?.unwrap_or(0) < millis {
+			low = mid + 1;
+		} else {
+			high = mid;
+		}
+	}
+	Ok(low)
+}
+
+impl CliConfiguration for ExportLedgerCmd {
+	fn shared_params(&self) -> &SharedParams {
+		&self.shared_params
+	}
+
+	fn pruning_params(&self) -> Option<&PruningParams> {
+		Some(&self.pruning_params)
+	}
+
+	fn database_params(&self) -> Option<&DatabaseParams> {
+		Some(&self.database_params)
+	}
+}
@@ -6,7 +6,16 @@ mod chain_spec;
 mod service;
 mod cli;
 mod command;
+mod environment_spec;
+mod event_index;
+mod health;
+mod history;
+mod ledger_export;
+mod metrics;
+mod regenesis;
 mod rpc;
+mod secrets;
+mod standby;
 
 fn main() -> sc_cli::Result<()> {
 	command::run()
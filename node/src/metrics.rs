@@ -0,0 +1,195 @@
+//! ColdStack metrics on the node's Prometheus endpoint.
+//!
+//! Gauges are set from the state of the best block every `UPDATE_INTERVAL`.
+//! Usage counters are fed from the events of finalized blocks only, so a
+//! re-org never counts a call twice; they start from zero when the node
+//! starts. Blocks whose state was pruned before they were counted are
+//! skipped with a warning.
+
+use std::{sync::Arc, time::Duration};
+
+use futures_timer::Delay;
+use log::warn;
+use node_template_runtime::{opaque::Block, Event};
+use prometheus_endpoint::{register, CounterVec, Gauge, GaugeVec, Opts, PrometheusError, Registry, F64, U64};
+use sc_client_api::StorageProvider;
+use sc_service::{error::Error as ServiceError, PruningMode, TaskManager};
+use sp_blockchain::HeaderBackend;
+use sp_runtime::generic::BlockId;
+
+use crate::{
+	event_index::{block_events, storage_value_key},
+	regenesis::{map, value},
+	service::FullClient,
+};
+
+/// Time between two metrics updates. Summing `Balances` reads the whole
+/// map, too much work to do for every block.
+const UPDATE_INTERVAL: Duration = Duration::from_secs(30);
+
+struct Metrics {
+	usage: CounterVec<U64>,
+	usage_bytes: CounterVec<U64>,
+	files: Gauge<U64>,
+	bytes_stored: Gauge<F64>,
+	total_issuance: Gauge<F64>,
+	locked_funds: Gauge<F64>,
+	balances: Gauge<F64>,
+	ledger_balanced: Gauge<U64>,
+	gateways: GaugeVec<U64>,
+	permissions: GaugeVec<U64>,
+}
+
+impl Metrics {
+	fn register(registry: &Registry) -> Result<Self, PrometheusError> {
+		Ok(Metrics {
+			usage: register(CounterVec::new(
+				Opts::new("coldstack_usage_total", "Finalized uploads, downloads and deletes"),
+				&["kind"],
+			)?, registry)?,
+			usage_bytes: register(CounterVec::new(
+				Opts::new("coldstack_usage_bytes_total", "Bytes of finalized uploads and downloads"),
+				&["kind"],
+			)?, registry)?,
+			files: register(Gauge::new("coldstack_files", "TotalFileCount")?, registry)?,
+			bytes_stored: register(Gauge::new("coldstack_bytes_stored", "TotalFileSize")?, registry)?,
+			total_issuance: register(Gauge::new("coldstack_total_issuance", "TotalIssuance, in base units")?, registry)?,
+			locked_funds: register(Gauge::new("coldstack_locked_funds", "LockedFunds, in base units")?, registry)?,
+			balances: register(
+				Gauge::new("coldstack_balances", "Sum of ColdStack balances, in base units")?,
+				registry,
+			)?,
+			ledger_balanced: register(Gauge::new(
+				"coldstack_ledger_balanced",
+				"1 if balances plus LockedFunds equal TotalIssuance, 0 otherwise",
+			)?, registry)?,
+			gateways: register(GaugeVec::new(
+				Opts::new("coldstack_gateways", "Registered gateways by state"),
+				&["state"],
+			)?, registry)?,
+			permissions: register(GaugeVec::new(
+				Opts::new("coldstack_permissions", "Granted filenode and billing permissions"),
+				&["kind"],
+			)?, registry)?,
+		})
+	}
+
+	/// Set the gauges from the state at `at`.
+	fn update_state(&self, client: &FullClient, at: &BlockId<Block>) -> Result<(), ServiceError> {
+		let total_issuance: u128 = value(client, at, "TotalIssuance")?.unwrap_or_default();
+		let locked_funds: u128 = value(client, at, "LockedFunds")?.unwrap_or_default();
+		let balances = map::<Vec<u8>, u128>(client, at, "Balances")?
+			.into_iter()
+			.try_fold(0u128, |sum, (_, balance)| sum.checked_add(balance));
+		let balanced = balances.and_then(|sum| sum.checked_add(locked_funds)) == Some(total_issuance);
+		if !balanced && self.ledger_balanced.get() == 1 {
+			warn!(
+				"ColdStack ledger out of balance at {}: balances {:?} + locked {} != issuance {}",
+				at, balances, locked_funds, total_issuance,
+			);
+		}
+
+		self.files.set(value::<u128>(client, at, "TotalFileCount")?.unwrap_or_default() as u64);
+		self.bytes_stored.set(value::<u128>(client, at, "TotalFileSize")?.unwrap_or_default() as f64);
+		self.total_issuance.set(total_issuance as f64);
+		self.locked_funds.set(locked_funds as f64);
+		self.balances.set(balances.map_or(f64::INFINITY, |sum| sum as f64));
+		self.ledger_balanced.set(balanced as u64);
+
+		// `Gateway` keeps its fields private; it encodes like this tuple.
+		let gateways = map::<Vec<u8>, (Vec<u8>, Option<Vec<u8>>, u8, bool)>(client, at, "Gateways")?;
+		let active = gateways.iter().filter(|(_, (_, _, _, is_active))| *is_active).count();
+		self.gateways.with_label_values(&["active"]).set(active as u64);
+		self.gateways.with_label_values(&["inactive"]).set((gateways.len() - active) as u64);
+
+		let permissions = [("file", "FilePermissionOwnersByETHAddress"), ("billing", "BillingPermissionOwnersByETHAddress")];
+		for &(kind, item) in permissions.iter() {
+			let keys = client.storage_keys(at, &storage_value_key(b"ColdStack", item.as_bytes()))?;
+			self.permissions.with_label_values(&[kind]).set(keys.len() as u64);
+		}
+		Ok(())
+	}
+
+	/// Count the usage events of block `at`.
+	fn count_usage(&self, client: &FullClient, at: &BlockId<Block>) -> Result<(), ServiceError> {
+		use pallet_template::Event::*;
+
+		let events = match block_events(client, at)? {
+			Some(events) => events,
+			None => {
+				warn!("Unable to decode the events of block {}, usage metrics skip it", at);
+				return Ok(())
+			},
+		};
+		for record in events {
+			let (kind, bytes) = match record.event {
+				Event::ColdStack(Upload { file_size_bytes, .. }) => ("upload", Some(file_size_bytes)),
+				Event::ColdStack(Download { file_size_bytes, .. }) => ("download", Some(file_size_bytes)),
+				Event::ColdStack(Delete { .. }) => ("delete", None),
+				_ => continue,
+			};
+			self.usage.with_label_values(&[kind]).inc();
+			if let Some(bytes) = bytes {
+				self.usage_bytes.with_label_values(&[kind]).inc_by(bytes as u64);
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Number of finalized blocks whose state `pruning` keeps, `None` if it
+/// keeps all of them.
+fn kept_blocks(pruning: &PruningMode) -> Option<u32> {
+	match pruning {
+		PruningMode::Constrained(constraints) => constraints.max_blocks,
+		_ => None,
+	}
+}
+
+/// Register the ColdStack metrics on `registry` and update them every
+/// `UPDATE_INTERVAL`, on a thread of their own. `pruning` is the node's
+/// state pruning, which limits how far back usage can be counted.
+pub fn spawn(
+	client: Arc<FullClient>,
+	registry: &Registry,
+	pruning: &PruningMode,
+	task_manager: &TaskManager,
+) -> Result<(), ServiceError> {
+	let metrics = Metrics::register(registry)?;
+	let kept_blocks = kept_blocks(pruning);
+	metrics.ledger_balanced.set(1);
+	let mut counted = client.info().finalized_number;
+
+	task_manager.spawn_handle().spawn_blocking("coldstack-metrics", async move {
+		loop {
+			let best = BlockId::Hash(client.info().best_hash);
+			if let Err(e) = metrics.update_state(&client, &best) {
+				warn!("Unable to update ColdStack metrics: {}", e);
+			}
+
+			let finalized = client.info().finalized_number;
+			// Counting fell behind by more than the pruning window, e.g. after
+			// failing for a while: the events of the oldest blocks are gone.
+			if let Some(oldest) = kept_blocks.map(|kept| finalized.saturating_sub(kept)) {
+				if counted + 1 < oldest {
+					warn!(
+						"State of blocks #{}..#{} is pruned, ColdStack usage metrics skip them",
+						counted + 1, oldest - 1,
+					);
+					counted = oldest - 1;
+				}
+			}
+			while counted < finalized {
+				let at = BlockId::Number(counted + 1);
+				if let Err(e) = metrics.count_usage(&client, &at) {
+					warn!("Unable to count ColdStack usage of block {}: {}", at, e);
+					break
+				}
+				counted += 1;
+			}
+
+			Delay::new(UPDATE_INTERVAL).await;
+		}
+	});
+	Ok(())
+}
@@ -0,0 +1,215 @@
+//! `regenesis` subcommand: carries the ColdStack state of a block into the
+//! `ColdStackConfig` of a new chain spec.
+//!
+//! Unlike `export-state`, which copies raw storage, this decodes the ledger,
+//! permissions, gateways and file totals, so the new chain may run a runtime
+//! with a different storage layout.
+//!
+//! Stored files live in the offchain database, which genesis cannot fill, so
+//! they are lost. The command refuses a state with uploads unless
+//! `--without-files` is given.
+
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
+
+use codec::Decode;
+use log::warn;
+use node_template_runtime::{opaque::Block, AccountId, ColdStackConfig};
+use pallet_template::{GatewayInfo, GenesisPermission};
+use sc_cli::{BlockNumberOrHash, ChainSpec, CliConfiguration, DatabaseParams, PruningParams, SharedParams};
+use sc_client_api::StorageProvider;
+use sc_service::error::Error as ServiceError;
+use sp_blockchain::HeaderBackend;
+use sp_runtime::generic::BlockId;
+use structopt::StructOpt;
+
+use crate::{event_index::storage_value_key, service::FullClient};
+
+/// Write a chain spec whose ColdStack genesis reproduces the ColdStack state
+/// of a block of the local database.
+#[derive(Debug, StructOpt)]
+pub struct RegenesisCmd {
+	/// Block to take the state from, by number or hash. Defaults to the last
+	/// finalized block.
+	#[structopt(long, value_name = "HASH or NUMBER")]
+	pub at: Option<BlockNumberOrHash>,
+
+	/// Chain spec of the new chain, whose `coldStack` genesis is replaced.
+	/// Must not be raw. Defaults to the spec given with `--chain`.
+	#[structopt(long, value_name = "CHAIN_SPEC")]
+	pub base_spec: Option<String>,
+
+	/// Write the new chain spec to this file instead of stdout.
+	#[structopt(long, value_name = "FILE", parse(from_os_str))]
+	pub output: Option<PathBuf>,
+
+	/// Write the new chain spec in raw form.
+	#[structopt(long)]
+	pub raw: bool,
+
+	/// Write the new chain spec although files were uploaded, dropping the
+	/// stored files. Only their totals are carried over.
+	#[structopt(long)]
+	pub without_files: bool,
+
+	#[allow(missing_docs)]
+	#[structopt(flatten)]
+	pub shared_params: SharedParams,
+
+	#[allow(missing_docs)]
+	#[structopt(flatten)]
+	pub pruning_params: PruningParams,
+
+	#[allow(missing_docs)]
+	#[structopt(flatten)]
+	pub database_params: DatabaseParams,
+}
+
+impl RegenesisCmd {
+	/// Read the ColdStack state from `client` and write it into `base_spec`.
+	pub fn run(&self, client: Arc<FullClient>, base_spec: Box<dyn ChainSpec>) -> sc_cli::Result<()> {
+		let at = match &self.at {
+			Some(at) => at.parse::<Block>()?,
+			None => BlockId::Hash(client.info().finalized_hash),
+		};
+		let genesis = cold_stack_genesis(&client, &at)?;
+		genesis.validate()
+			.map_err(|e| format!("ColdStack state at {} is not a valid genesis: {}", at, e))?;
+		if genesis.total_file_count > 0 {
+			if !self.without_files {
+				return Err(format!(
+					"ColdStack state at {} has {} uploads. Stored files live in the offchain \
+					database and cannot be carried into genesis; pass `--without-files` to \
+					drop them.",
+					at, genesis.total_file_count,
+				).into())
+			}
+			warn!(
+				"Dropping the stored files of {} uploads; the new chain keeps only their totals.",
+				genesis.total_file_count,
+			);
+		}
+
+		let mut spec: serde_json::Value = serde_json::from_str(&base_spec.as_json(false)?)
+			.map_err(|e| format!("Unable to parse base chain spec: {}", e))?;
+		let runtime = spec.pointer_mut("/genesis/runtime")
+			.and_then(serde_json::Value::as_object_mut)
+			.ok_or("Base chain spec is raw; pass one built without `--raw`.")?;
+		let cold_stack = serde_json::to_value(&genesis)
+			.map_err(|e| format!("Unable to serialize ColdStack genesis: {}", e))?;
+		runtime.insert("coldStack".into(), cold_stack);
+
+		let json = serde_json::to_vec_pretty(&spec)
+			.map_err(|e| format!("Unable to serialize chain spec: {}", e))?;
+		let json = if self.raw {
+			crate::chain_spec::ChainSpec::from_json_bytes(json)?.as_json(true)?.into_bytes()
+		} else {
+			json
+		};
+
+		match &self.output {
+			Some(path) => std::fs::write(path, json)?,
+			None => {
+				use std::io::Write;
+				std::io::stdout().write_all(&json)?;
+			},
+		}
+		Ok(())
+	}
+}
+
+fn decode_error(item: &str, e: codec::Error) -> ServiceError {
+	ServiceError::Other(format!("Unable to decode ColdStack::{}: {}", item, e))
+}
+
+/// The ColdStack storage value `item` at `at`.
+pub(crate) fn value<V: Decode>(client: &FullClient, at: &BlockId<Block>, item: &str) -> Result<Option<V>, ServiceError> {
+	client.storage(at, &storage_value_key(b"ColdStack", item.as_bytes()))?
+		.map(|raw| V::decode(&mut &raw.0[..]).map_err(|e| decode_error(item, e)))
+		.transpose()
+}
+
+/// Entries of the ColdStack map `item` at `at`, with the hashed map keys
+/// left for the caller to decode.
+fn map_entries(client: &FullClient, at: &BlockId<Block>, item: &str) -> Result<Vec<(Vec<u8>, Vec<u8>)>, ServiceError> {
+	let prefix = storage_value_key(b"ColdStack", item.as_bytes());
+	Ok(client.storage_pairs(at, &prefix)?
+		.into_iter()
+		.map(|(key, value)| (key.0[prefix.0.len()..].to_vec(), value.0))
+		.collect())
+}
+
+/// Decode a `Blake2_128Concat` key off the front of `input`.
+fn blake2_128_concat<K: Decode>(input: &mut &[u8], item: &str) -> Result<K, ServiceError> {
+	if input.len() < 16 {
+		return Err(decode_error(item, "key shorter than its hash".into()))
+	}
+	*input = &input[16..];
+	K::decode(input).map_err(|e| decode_error(item, e))
+}
+
+/// Entries of a ColdStack map with a single `Blake2_128Concat` key.
+pub(crate) fn map<K: Decode, V: Decode>(client: &FullClient, at: &BlockId<Block>, item: &str) -> Result<Vec<(K, V)>, ServiceError> {
+	map_entries(client, at, item)?
+		.into_iter()
+		.map(|(key, value)| Ok((
+			blake2_128_concat(&mut &key[..], item)?,
+			V::decode(&mut &value[..]).map_err(|e| decode_error(item, e))?,
+		)))
+		.collect()
+}
+
+/// `ColdStackConfig` reproducing the ColdStack state at `at`.
+///
+/// Counters that only exist to order or deduplicate calls, such as
+/// `UsageSequence` and `ProcessedRequests`, start over on the new chain.
+fn cold_stack_genesis(client: &FullClient, at: &BlockId<Block>) -> Result<ColdStackConfig, ServiceError> {
+	let node_urls: BTreeMap<Vec<u8>, Vec<u8>> = map(client, at, "NodeURLs")?.into_iter().collect();
+	let permissions = |item| -> Result<Vec<GenesisPermission<AccountId>>, ServiceError> {
+		Ok(map::<Vec<u8>, AccountId>(client, at, item)?
+			.into_iter()
+			.map(|(eth_address, account_id)| GenesisPermission {
+				node_url: node_urls.get(&eth_address).cloned().unwrap_or_default(),
+				eth_address,
+				account_id,
+			})
+			.collect())
+	};
+
+	// `Gateway` keeps its fields private; it encodes like this tuple.
+	let gateways = map::<Vec<u8>, (Vec<u8>, Option<Vec<u8>>, u8, bool)>(client, at, "Gateways")?
+		.into_iter()
+		.map(|(address, (_, seed_address, storage, is_active))| GatewayInfo {
+			node_url: node_urls.get(&address).cloned().unwrap_or_default(),
+			address,
+			seed_address,
+			storage,
+			is_active,
+		})
+		.collect();
+
+	Ok(ColdStackConfig {
+		key: value(client, at, "Key")?.unwrap_or_default(),
+		total_issuance: value(client, at, "TotalIssuance")?.unwrap_or_default(),
+		locked_funds: Some(value(client, at, "LockedFunds")?.unwrap_or_default()),
+		balances: map(client, at, "Balances")?,
+		file_permissions: permissions("FilePermissionOwnersByETHAddress")?,
+		billing_permissions: permissions("BillingPermissionOwnersByETHAddress")?,
+		gateways,
+		total_file_count: value(client, at, "TotalFileCount")?.unwrap_or_default(),
+		total_file_size: value(client, at, "TotalFileSize")?.unwrap_or_default(),
+	})
+}
+
+impl CliConfiguration for RegenesisCmd {
+	fn shared_params(&self) -> &SharedParams {
+		&self.shared_params
+	}
+
+	fn pruning_params(&self) -> Option<&PruningParams> {
+		Some(&self.pruning_params)
+	}
+
+	fn database_params(&self) -> Option<&DatabaseParams> {
+		Some(&self.database_params)
+	}
+}
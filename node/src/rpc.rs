@@ -7,44 +7,59 @@
 
 use std::sync::Arc;
 
-use node_template_runtime::{opaque::Block, AccountId, Balance, Index};
+use node_template_runtime::{opaque::Block, AccountId, Balance, Index, Runtime};
 use sp_api::ProvideRuntimeApi;
 use sp_blockchain::{Error as BlockChainError, HeaderMetadata, HeaderBackend};
 use sp_block_builder::BlockBuilder;
+use sc_client_api::{Backend, BlockchainEvents, ProofProvider, StorageProvider};
+use sc_rpc::SubscriptionTaskExecutor;
 pub use sc_rpc_api::DenyUnsafe;
 use sp_transaction_pool::TransactionPool;
 
 
 /// Full client dependencies.
-pub struct FullDeps<C, P> {
+pub struct FullDeps<C, P, B> {
 	/// The client instance to use.
 	pub client: Arc<C>,
+	/// The backend, for access to the offchain database.
+	pub backend: Arc<B>,
 	/// Transaction pool instance.
 	pub pool: Arc<P>,
 	/// Whether to deny unsafe calls
 	pub deny_unsafe: DenyUnsafe,
+	/// Executor for RPC subscriptions.
+	pub subscription_executor: SubscriptionTaskExecutor,
 }
 
 /// Instantiate all full RPC extensions.
-pub fn create_full<C, P>(
-	deps: FullDeps<C, P>,
+pub fn create_full<C, P, B>(
+	deps: FullDeps<C, P, B>,
 ) -> jsonrpc_core::IoHandler<sc_rpc::Metadata> where
+	B: Backend<Block> + Send + Sync + 'static,
 	C: ProvideRuntimeApi<Block>,
 	C: HeaderBackend<Block> + HeaderMetadata<Block, Error=BlockChainError> + 'static,
+	C: ProofProvider<Block> + BlockchainEvents<Block> + StorageProvider<Block, B>,
 	C: Send + Sync + 'static,
 	C::Api: substrate_frame_rpc_system::AccountNonceApi<Block, AccountId, Index>,
 	C::Api: pallet_transaction_payment_rpc::TransactionPaymentRuntimeApi<Block, Balance>,
+	C::Api: pallet_template_rpc::ColdStackRuntimeApi<Block, AccountId>,
 	C::Api: BlockBuilder<Block>,
 	P: TransactionPool + 'static,
 {
 	use substrate_frame_rpc_system::{FullSystem, SystemApi};
 	use pallet_transaction_payment_rpc::{TransactionPayment, TransactionPaymentApi};
+	use pallet_template_rpc::{
+		ColdStack, ColdStackApi, ColdStackEvents, ColdStackEventsApi, ColdStackHistory,
+		ColdStackHistoryApi,
+	};
 
 	let mut io = jsonrpc_core::IoHandler::default();
 	let FullDeps {
 		client,
+		backend,
 		pool,
 		deny_unsafe,
+		subscription_executor,
 	} = deps;
 
 	io.extend_with(
@@ -55,10 +70,23 @@ pub fn create_full<C, P>(
 		TransactionPaymentApi::to_delegate(TransactionPayment::new(client.clone()))
 	);
 
-	// Extend this RPC with a custom API by using the following syntax.
-	// `YourRpcStruct` should have a reference to a client, which is needed
-	// to call into the runtime.
-	// `io.extend_with(YourRpcTrait::to_delegate(YourRpcStruct::new(ReferenceToClient, ...)));`
+	io.extend_with(
+		ColdStackApi::<_, AccountId>::to_delegate(ColdStack::new(client.clone()))
+	);
+
+	io.extend_with(
+		ColdStackEventsApi::to_delegate(
+			ColdStackEvents::<_, _, B, Runtime>::new(client.clone(), subscription_executor, deny_unsafe)
+		)
+	);
+
+	// History records live in the offchain database, which only nodes with
+	// a local database have.
+	if let Some(storage) = backend.offchain_storage() {
+		io.extend_with(
+			ColdStackHistoryApi::to_delegate(ColdStackHistory::new(storage))
+		);
+	}
 
 	io
 }
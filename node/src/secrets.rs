@@ -0,0 +1,261 @@
+//! `secrets` subcommand: creates deployment secrets files and turns them into
+//! keystore entries and chain spec authorities, replacing the Python scripts
+//! in `deploy/` and `chainspec/`.
+//!
+//! A secrets file holds mnemonics of the validators, sudo and ColdStack admin
+//! accounts, and the node key and peer id of the boot node. Validator
+//! mnemonics give the Aura (sr25519), GRANDPA (ed25519) and liveness
+//! (sr25519, the same key as Aura) keys.
+
+use std::{
+	fs::OpenOptions,
+	io::Write,
+	os::unix::fs::OpenOptionsExt,
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+
+use node_template_runtime::AccountId;
+use sc_cli::{BasePath, KeystoreParams, SharedParams, SubstrateCli};
+use sc_keystore::LocalKeystore;
+use sc_network::config::identity::{ed25519 as node_key, PublicKey};
+use sc_service::config::KeystoreConfig;
+use serde::{Deserialize, Serialize};
+use sp_consensus_aura::sr25519::AuthorityId as AuraId;
+use sp_core::{crypto::{KeyTypeId, Ss58Codec}, ed25519, hexdisplay::HexDisplay, sr25519, Pair};
+use sp_finality_grandpa::AuthorityId as GrandpaId;
+use sp_keystore::{SyncCryptoStore, SyncCryptoStorePtr};
+use sp_runtime::traits::IdentifyAccount;
+use structopt::StructOpt;
+
+/// Contents of a secrets file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Secrets {
+	/// Mnemonics of the validators.
+	pub authorities: Vec<String>,
+	/// Mnemonic of the sudo account.
+	pub sudo: String,
+	/// Mnemonic of the ColdStack admin account.
+	pub admin: String,
+	/// Hex-encoded libp2p key of the boot node, for `--node-key`.
+	pub nodekey: Option<String>,
+	/// Peer id of the boot node, for other nodes' `--bootnodes`.
+	pub peer_id: Option<String>,
+}
+
+impl Secrets {
+	/// Read a secrets file.
+	pub fn read(path: &Path) -> Result<Self, String> {
+		let secrets: Secrets = std::fs::read(path)
+			.map_err(|e| format!("Unable to read {}: {}", path.display(), e))
+			.and_then(|bytes| serde_json::from_slice(&bytes)
+				.map_err(|e| format!("Unable to parse {}: {}", path.display(), e)))?;
+		if secrets.authorities.is_empty() {
+			return Err(format!("{} lists no authorities.", path.display()))
+		}
+		Ok(secrets)
+	}
+
+	/// Account, Aura and GRANDPA keys of each validator.
+	pub fn authority_keys(&self) -> Result<Vec<(AccountId, AuraId, GrandpaId)>, String> {
+		self.authorities.iter()
+			.map(|mnemonic| {
+				let aura = pair::<sr25519::Pair>("authority", mnemonic)?.public();
+				let grandpa = pair::<ed25519::Pair>("authority", mnemonic)?.public();
+				Ok((account(aura), AuraId::from(aura), GrandpaId::from(grandpa)))
+			})
+			.collect()
+	}
+
+	/// Sudo account.
+	pub fn sudo_account(&self) -> Result<AccountId, String> {
+		Ok(account(pair::<sr25519::Pair>("sudo", &self.sudo)?.public()))
+	}
+
+	/// ColdStack admin account.
+	pub fn admin_account(&self) -> Result<AccountId, String> {
+		Ok(account(pair::<sr25519::Pair>("admin", &self.admin)?.public()))
+	}
+}
+
+fn pair<P: Pair>(what: &str, mnemonic: &str) -> Result<P, String> {
+	P::from_phrase(mnemonic, None)
+		.map(|(pair, _)| pair)
+		.map_err(|e| format!("Invalid {} mnemonic: {:?}", what, e))
+}
+
+/// Public key and hex seed of the `P` key of validator `mnemonic`.
+///
+/// Keystores get the seed rather than the mnemonic: a keystore password
+/// encrypts the keystore, but a password next to a mnemonic would also be
+/// taken as the mnemonic's derivation password, giving another key than the
+/// one in the chain spec.
+fn public_and_seed<P: Pair>(mnemonic: &str) -> Result<(P::Public, String), String> {
+	let (pair, seed) = P::from_phrase(mnemonic, None)
+		.map_err(|e| format!("Invalid authority mnemonic: {:?}", e))?;
+	Ok((pair.public(), format!("0x{}", HexDisplay::from(&seed.as_ref()))))
+}
+
+fn account(public: sr25519::Public) -> AccountId {
+	sp_runtime::MultiSigner::from(public).into_account()
+}
+
+/// Deployment secrets utilities.
+#[derive(Debug, StructOpt)]
+pub enum SecretsSubcommand {
+	/// Generate a new secrets file.
+	Generate(GenerateSecretsCmd),
+
+	/// Insert the Aura, GRANDPA and liveness keys of a validator into the
+	/// keystore.
+	InsertKeys(InsertKeysCmd),
+
+	/// Print the chain spec `validatorSet` and `session` entries of the
+	/// validators.
+	Authorities(AuthoritiesCmd),
+}
+
+impl SecretsSubcommand {
+	/// Run the subcommand.
+	pub fn run<C: SubstrateCli>(&self, cli: &C) -> sc_cli::Result<()> {
+		match self {
+			SecretsSubcommand::Generate(cmd) => cmd.run(),
+			SecretsSubcommand::InsertKeys(cmd) => cmd.run(cli),
+			SecretsSubcommand::Authorities(cmd) => cmd.run(),
+		}
+	}
+}
+
+/// Generate a secrets file with fresh mnemonics and boot node key.
+#[derive(Debug, StructOpt)]
+pub struct GenerateSecretsCmd {
+	/// File to write. An existing file is not overwritten.
+	#[structopt(long, value_name = "FILE", parse(from_os_str))]
+	pub file: PathBuf,
+
+	/// Number of validators.
+	#[structopt(long, default_value = "1")]
+	pub authorities: usize,
+}
+
+impl GenerateSecretsCmd {
+	/// Run the command.
+	pub fn run(&self) -> sc_cli::Result<()> {
+		if self.file.exists() {
+			return Err(format!("{} already exists.", self.file.display()).into())
+		}
+		let mnemonic = || sr25519::Pair::generate_with_phrase(None).1;
+		let node_key = node_key::Keypair::generate();
+		let secrets = Secrets {
+			authorities: (0..self.authorities).map(|_| mnemonic()).collect(),
+			sudo: mnemonic(),
+			admin: mnemonic(),
+			nodekey: Some(HexDisplay::from(&node_key.secret().as_ref()).to_string()),
+			peer_id: Some(PublicKey::Ed25519(node_key.public()).into_peer_id().to_base58()),
+		};
+		let json = serde_json::to_string_pretty(&secrets)
+			.map_err(|e| format!("Unable to serialize secrets: {}", e))?;
+		// Readable by the owner only: the file holds every mnemonic of the
+		// deployment.
+		OpenOptions::new()
+			.write(true)
+			.create_new(true)
+			.mode(0o600)
+			.open(&self.file)?
+			.write_all(json.as_bytes())?;
+		Ok(())
+	}
+}
+
+/// Insert the Aura, GRANDPA and liveness keys of a validator from a secrets
+/// file into the keystore of a node.
+#[derive(Debug, StructOpt)]
+pub struct InsertKeysCmd {
+	/// Secrets file.
+	#[structopt(long, value_name = "FILE", parse(from_os_str))]
+	pub secrets: PathBuf,
+
+	/// Position of the validator in the secrets file's `authorities`.
+	#[structopt(long, default_value = "0")]
+	pub authority: usize,
+
+	#[allow(missing_docs)]
+	#[structopt(flatten)]
+	pub shared_params: SharedParams,
+
+	#[allow(missing_docs)]
+	#[structopt(flatten)]
+	pub keystore_params: KeystoreParams,
+}
+
+impl InsertKeysCmd {
+	/// Run the command.
+	pub fn run<C: SubstrateCli>(&self, cli: &C) -> sc_cli::Result<()> {
+		let secrets = Secrets::read(&self.secrets)?;
+		let mnemonic = secrets.authorities.get(self.authority).ok_or_else(|| format!(
+			"{} lists {} authorities, there is no authority {}.",
+			self.secrets.display(), secrets.authorities.len(), self.authority,
+		))?;
+
+		let base_path = self.shared_params.base_path()
+			.unwrap_or_else(|| BasePath::from_project("", "", &C::executable_name()));
+		let chain_spec = cli.load_spec(&self.shared_params.chain_id(self.shared_params.is_dev()))?;
+		let config_dir = base_path.config_dir(chain_spec.id());
+		let keystore: SyncCryptoStorePtr = match self.keystore_params.keystore_config(&config_dir)? {
+			(_, KeystoreConfig::Path { path, password }) => Arc::new(LocalKeystore::open(path, password)?),
+			_ => unreachable!("keystore_config always returns path and password; qed"),
+		};
+
+		let (aura, aura_seed) = public_and_seed::<sr25519::Pair>(mnemonic)?;
+		let (grandpa, grandpa_seed) = public_and_seed::<ed25519::Pair>(mnemonic)?;
+		let keys = [
+			(KeyTypeId(*b"aura"), &aura_seed, aura.0),
+			(KeyTypeId(*b"gran"), &grandpa_seed, grandpa.0),
+			(KeyTypeId(*b"live"), &aura_seed, aura.0),
+		];
+		for (key_type, seed, public) in keys.iter() {
+			SyncCryptoStore::insert_unknown(&*keystore, *key_type, seed, &public[..])
+				.map_err(|_| sc_cli::Error::KeyStoreOperation)?;
+		}
+		Ok(())
+	}
+}
+
+/// Print the chain spec entries of the validators of a secrets file.
+#[derive(Debug, StructOpt)]
+pub struct AuthoritiesCmd {
+	/// Secrets file.
+	#[structopt(long, value_name = "FILE", parse(from_os_str))]
+	pub secrets: PathBuf,
+}
+
+impl AuthoritiesCmd {
+	/// Run the command.
+	pub fn run(&self) -> sc_cli::Result<()> {
+		let authorities = Secrets::read(&self.secrets)?.authority_keys()?;
+		let entries = serde_json::json!({
+			"validatorSet": {
+				"validators": authorities.iter()
+					.map(|(account, _, _)| account.to_ss58check())
+					.collect::<Vec<_>>(),
+			},
+			"session": {
+				"keys": authorities.iter()
+					.map(|(account, aura, grandpa)| serde_json::json!([
+						account.to_ss58check(),
+						account.to_ss58check(),
+						{
+							"aura": aura.to_ss58check(),
+							"grandpa": grandpa.to_ss58check(),
+							"liveness": aura.to_ss58check(),
+						},
+					]))
+					.collect::<Vec<_>>(),
+			},
+		});
+		let json = serde_json::to_string_pretty(&entries)
+			.map_err(|e| format!("Unable to serialize authorities: {}", e))?;
+		println!("{}", json);
+		Ok(())
+	}
+}
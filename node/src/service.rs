@@ -1,17 +1,18 @@
 //! Service and ServiceFactory implementation. Specialized wrapper over substrate service.
 
 use std::{sync::Arc, time::Duration};
-use sc_client_api::{ExecutorProvider, RemoteBackend};
+use sc_client_api::{Backend, ExecutorProvider, RemoteBackend};
 use node_template_runtime::{self, opaque::Block, RuntimeApi};
 use sc_service::{error::Error as ServiceError, Configuration, TaskManager};
 use sc_executor::native_executor_instance;
 pub use sc_executor::NativeExecutor;
 use sp_consensus_aura::sr25519::AuthorityPair as AuraPair;
-use sc_consensus_aura::{ImportQueueParams, StartAuraParams, SlotProportion};
+use sc_consensus_aura::{BuildAuraWorkerParams, ImportQueueParams, SlotProportion};
 use sc_finality_grandpa::SharedVoterState;
-use sc_keystore::LocalKeystore;
+use coldstack_signer::RemoteKeystore;
 use sc_telemetry::{Telemetry, TelemetryWorker};
 use sp_consensus::SlotData;
+use crate::{history::HistoryParams, standby::StandbyParams};
 
 // Our native executor instance.
 native_executor_instance!(
@@ -21,7 +22,8 @@ native_executor_instance!(
 	frame_benchmarking::benchmarking::HostFunctions,
 );
 
-type FullClient = sc_service::TFullClient<Block, RuntimeApi, Executor>;
+pub type FullClient = sc_service::TFullClient<Block, RuntimeApi, Executor>;
+pub type FullNetwork = sc_network::NetworkService<Block, <Block as sp_runtime::traits::Block>::Hash>;
 type FullBackend = sc_service::TFullBackend<Block>;
 type FullSelectChain = sc_consensus::LongestChain<FullBackend, Block>;
 
@@ -35,11 +37,6 @@ pub fn new_partial(config: &Configuration) -> Result<sc_service::PartialComponen
 		Option<Telemetry>,
 	)
 >, ServiceError> {
-	if config.keystore_remote.is_some() {
-		return Err(ServiceError::Other(
-			format!("Remote Keystores are not supported.")))
-	}
-
 	let telemetry = config.telemetry_endpoints.clone()
 		.filter(|x| !x.is_empty())
 		.map(|endpoints| -> Result<_, sc_telemetry::Error> {
@@ -117,15 +114,36 @@ pub fn new_partial(config: &Configuration) -> Result<sc_service::PartialComponen
 	})
 }
 
-fn remote_keystore(_url: &String) -> Result<Arc<LocalKeystore>, &'static str> {
-	// FIXME: here would the concrete keystore be built,
-	//        must return a concrete type (NOT `LocalKeystore`) that
-	//        implements `CryptoStore` and `SyncCryptoStore`
-	Err("Remote Keystore not supported.")
+/// Keystore of a `coldstack-signer` daemon, sent the token in
+/// `COLDSTACK_SIGNER_TOKEN` if set.
+fn remote_keystore(url: &str) -> Result<Arc<RemoteKeystore>, String> {
+	RemoteKeystore::open(url, std::env::var(coldstack_signer::TOKEN_ENV).ok()).map(Arc::new)
 }
 
 /// Builds a new service for a full client.
-pub fn new_full(mut config: Configuration) -> Result<TaskManager, ServiceError> {
+pub fn new_full(config: Configuration) -> Result<TaskManager, ServiceError> {
+	new_full_with(config, &StandbyParams::default(), &HistoryParams::default(), |_, _, _| Ok(()))
+}
+
+/// Fail unless the node keeps the state of all blocks, which `what` needs.
+pub fn ensure_archive(config: &Configuration, what: &str) -> Result<(), ServiceError> {
+	if config.state_pruning.is_archive() {
+		Ok(())
+	} else {
+		Err(ServiceError::Other(format!("{} needs `--pruning archive`.", what)))
+	}
+}
+
+/// Builds a new service for a full client, letting `extend` spawn extra
+/// tasks on the client and network before the network starts. An authority
+/// authors as a hot standby if `standby` says so, and the ColdStack history
+/// is written if `history` says so.
+pub fn new_full_with(
+	mut config: Configuration,
+	standby: &StandbyParams,
+	history: &HistoryParams,
+	extend: impl FnOnce(Arc<FullClient>, Arc<FullNetwork>, &TaskManager) -> Result<(), ServiceError>,
+) -> Result<TaskManager, ServiceError> {
 	let sc_service::PartialComponents {
 		client,
 		backend,
@@ -166,6 +184,18 @@ pub fn new_full(mut config: Configuration) -> Result<TaskManager, ServiceError>
 		);
 	}
 
+	// The ColdStack history is written to the offchain database, replaying
+	// balances from the state of past blocks.
+	if history.coldstack_history {
+		ensure_archive(&config, "`--coldstack-history`")?;
+		let storage = backend.offchain_storage().ok_or_else(|| ServiceError::Other(
+			"ColdStack history needs an offchain database.".into()
+		))?;
+		crate::history::spawn(client.clone(), storage, &task_manager)?;
+	}
+
+	extend(client.clone(), network.clone(), &task_manager)?;
+
 	let role = config.role.clone();
 	let force_authoring = config.force_authoring;
 	let backoff_authoring_blocks: Option<()> = None;
@@ -173,15 +203,22 @@ pub fn new_full(mut config: Configuration) -> Result<TaskManager, ServiceError>
 	let enable_grandpa = !config.disable_grandpa;
 	let prometheus_registry = config.prometheus_registry().cloned();
 
+	if let Some(registry) = prometheus_registry.as_ref() {
+		crate::metrics::spawn(client.clone(), registry, &config.state_pruning, &task_manager)?;
+	}
+
 	let rpc_extensions_builder = {
 		let client = client.clone();
+		let backend = backend.clone();
 		let pool = transaction_pool.clone();
 
-		Box::new(move |deny_unsafe, _| {
+		Box::new(move |deny_unsafe, subscription_executor| {
 			let deps = crate::rpc::FullDeps {
 				client: client.clone(),
+				backend: backend.clone(),
 				pool: pool.clone(),
 				deny_unsafe,
+				subscription_executor,
 			};
 
 			crate::rpc::create_full(deps)
@@ -220,36 +257,48 @@ pub fn new_full(mut config: Configuration) -> Result<TaskManager, ServiceError>
 		let slot_duration = sc_consensus_aura::slot_duration(&*client)?;
 		let raw_slot_duration = slot_duration.slot_duration();
 
-		let aura = sc_consensus_aura::start_aura::<AuraPair, _, _, _, _, _, _, _, _, _, _, _>(
-			StartAuraParams {
-				slot_duration,
+		let worker = sc_consensus_aura::build_aura_worker::<AuraPair, _, _, _, _, _, _, _, _>(
+			BuildAuraWorkerParams {
 				client: client.clone(),
-				select_chain,
 				block_import,
 				proposer_factory,
-				create_inherent_data_providers: move |_, ()| async move {
-					let timestamp = sp_timestamp::InherentDataProvider::from_system_time();
-
-					let slot =
-						sp_consensus_aura::inherents::InherentDataProvider::from_timestamp_and_duration(
-							*timestamp,
-							raw_slot_duration,
-						);
-
-					Ok((timestamp, slot))
-				},
 				force_authoring,
 				backoff_authoring_blocks,
 				keystore: keystore_container.sync_keystore(),
-				can_author_with,
 				sync_oracle: network.clone(),
 				justification_sync_link: network.clone(),
 				block_proposal_slot_portion: SlotProportion::new(2f32 / 3f32),
 				max_block_proposal_slot_portion: None,
 				telemetry: telemetry.as_ref().map(|x| x.handle()),
 			},
+		);
+		let worker = standby.worker(
+			worker,
+			client.clone(),
+			keystore_container.sync_keystore(),
+			raw_slot_duration,
+			&task_manager,
 		)?;
 
+		let aura = sc_consensus_slots::start_slot_worker(
+			slot_duration,
+			select_chain,
+			worker,
+			network.clone(),
+			move |_, ()| async move {
+				let timestamp = sp_timestamp::InherentDataProvider::from_system_time();
+
+				let slot =
+					sp_consensus_aura::inherents::InherentDataProvider::from_timestamp_and_duration(
+						*timestamp,
+						raw_slot_duration,
+					);
+
+				Ok((timestamp, slot))
+			},
+			can_author_with,
+		);
+
 		// the AURA authoring task is considered essential, i.e. if it
 		// fails we take down the service with it.
 		task_manager.spawn_essential_handle().spawn_blocking("aura", aura);
@@ -0,0 +1,201 @@
+//! Hot standby validators and a guard against two nodes authoring with the
+//! same key.
+//!
+//! A standby holds its own session key, registered as an authority next to
+//! the active validator's. Aura gives each authority its own slots, so the
+//! two never claim the same slot. The standby lets its slots pass while the
+//! chain advances, takes over once `--standby-after-slots` slots in a row
+//! passed without a block, and goes back to standby when a newer block of
+//! another authority arrives.
+//!
+//! Taking over authoring does not help if finality stops with the active
+//! validator. GRANDPA finalizes with votes of more than two thirds of its
+//! voters, so a standby is refused unless there are enough voters for one of
+//! them to be down, i.e. at least `MIN_GRANDPA_VOTERS`.
+//!
+//! Every authoring node also watches for blocks sealed with one of its keys
+//! that it did not author. Another node then holds the same key, so this one
+//! stops authoring until it is restarted rather than equivocate.
+
+use std::{
+	sync::{atomic::{AtomicBool, Ordering}, Arc},
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use futures::StreamExt;
+use log::{error, info, warn};
+use node_template_runtime::opaque::Block;
+use sc_client_api::BlockchainEvents;
+use sc_consensus_slots::{SlotInfo, SlotResult, SlotWorker};
+use sc_service::{error::Error as ServiceError, TaskManager};
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_consensus::BlockOrigin;
+use sp_consensus_aura::{sr25519::AuthoritySignature, AuraApi, Slot};
+use sp_core::crypto::{key_types::AURA, Public};
+use sp_finality_grandpa::GrandpaApi;
+use sp_keystore::{SyncCryptoStore, SyncCryptoStorePtr};
+use sp_runtime::{generic::BlockId, traits::{Block as BlockT, Header as _}};
+use structopt::StructOpt;
+
+use crate::service::FullClient;
+
+/// Fewest GRANDPA voters that still finalize with one voter down: with `n`
+/// equally weighted voters, `(n - 1) / 3` of them may be missing.
+pub const MIN_GRANDPA_VOTERS: usize = 4;
+
+/// Hot standby options.
+#[derive(Debug, Clone, Default, StructOpt)]
+pub struct StandbyParams {
+	/// Run this validator as hot standby: author with its own session key
+	/// only after this many slots in a row passed without a block. Needs at
+	/// least 4 GRANDPA voters.
+	#[structopt(long, value_name = "SLOTS", requires = "validator")]
+	pub standby_after_slots: Option<u64>,
+}
+
+fn slot_of(header: &<Block as BlockT>::Header) -> Option<Slot> {
+	sc_consensus_aura::find_pre_digest::<Block, AuthoritySignature>(header).ok()
+}
+
+/// Aura slot worker that lets slots pass while on standby, or for good once
+/// another node authored with one of this node's keys.
+pub struct StandbyWorker<W> {
+	inner: W,
+	after_slots: Option<u64>,
+	active: bool,
+	/// Latest slot this node authored or took over at.
+	since: Slot,
+	duplicate_key: Arc<AtomicBool>,
+}
+
+#[async_trait::async_trait]
+impl<W, Proof> SlotWorker<Block, Proof> for StandbyWorker<W>
+where
+	W: SlotWorker<Block, Proof> + Send,
+	Proof: Send + 'static,
+{
+	async fn on_slot(&mut self, slot_info: SlotInfo<Block>) -> Option<SlotResult<Block, Proof>> {
+		if self.duplicate_key.load(Ordering::Relaxed) {
+			return None
+		}
+		let head_slot = match slot_of(&slot_info.chain_head) {
+			Some(slot) => slot,
+			None => {
+				warn!(
+					"No Aura slot in best block {}, skipping slot {}",
+					slot_info.chain_head.hash(), u64::from(slot_info.slot),
+				);
+				return None
+			},
+		};
+		if head_slot >= slot_info.slot {
+			warn!("Best block is at slot {}, skipping slot {}", u64::from(head_slot), u64::from(slot_info.slot));
+			return None
+		}
+
+		if let Some(after_slots) = self.after_slots {
+			if self.active && head_slot > self.since {
+				info!("Block at slot {} from another authority, back to standby", u64::from(head_slot));
+				self.active = false;
+			}
+			if !self.active {
+				let missed = u64::from(slot_info.slot) - u64::from(head_slot) - 1;
+				if missed < after_slots {
+					return None
+				}
+				warn!(
+					"{} slots without a block, standby takes over authoring at slot {}",
+					missed, u64::from(slot_info.slot),
+				);
+				self.active = true;
+				self.since = slot_info.slot;
+			}
+		}
+
+		let slot = slot_info.slot;
+		let result = self.inner.on_slot(slot_info).await;
+		if result.is_some() {
+			self.since = slot;
+		}
+		result
+	}
+}
+
+impl StandbyParams {
+	/// Wrap the Aura `worker` of an authority, and stop it if a block sealed
+	/// with one of the keys in `keystore` arrives from elsewhere.
+	pub fn worker<W>(
+		&self,
+		worker: W,
+		client: Arc<FullClient>,
+		keystore: SyncCryptoStorePtr,
+		slot_duration: Duration,
+		task_manager: &TaskManager,
+	) -> Result<StandbyWorker<W>, ServiceError> {
+		if self.standby_after_slots == Some(0) {
+			return Err(ServiceError::Other("--standby-after-slots must be at least 1.".into()))
+		}
+		if let Some(after_slots) = self.standby_after_slots {
+			let best = BlockId::Hash(client.info().best_hash);
+			let voters = client.runtime_api().grandpa_authorities(&best)
+				.map_err(|e| ServiceError::Other(format!("Unable to read GRANDPA voters: {:?}", e)))?
+				.len();
+			if voters < MIN_GRANDPA_VOTERS {
+				return Err(ServiceError::Other(format!(
+					"--standby-after-slots needs at least {} GRANDPA voters, the chain has {}; \
+					with fewer, finality stops while any validator is down.",
+					MIN_GRANDPA_VOTERS, voters,
+				)))
+			}
+			info!("Hot standby, authoring after {} slots without a block", after_slots);
+		}
+
+		// Blocks of earlier runs may come back while syncing; only blocks
+		// from slots after the start count as duplicates.
+		let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+		let start_slot = (now.as_millis() / slot_duration.as_millis().max(1)) as u64;
+		let duplicate_key = Arc::new(AtomicBool::new(false));
+		let detected = duplicate_key.clone();
+		let mut imports = client.import_notification_stream();
+
+		task_manager.spawn_handle().spawn("duplicate-key-guard", async move {
+			while let Some(notification) = imports.next().await {
+				if notification.origin == BlockOrigin::Own {
+					continue
+				}
+				let slot = match slot_of(&notification.header) {
+					Some(slot) if u64::from(slot) >= start_slot => slot,
+					_ => continue,
+				};
+				let parent = BlockId::Hash(*notification.header.parent_hash());
+				let authorities = match client.runtime_api().authorities(&parent) {
+					Ok(authorities) if !authorities.is_empty() => authorities,
+					Ok(_) => continue,
+					Err(e) => {
+						warn!("Unable to read Aura authorities at {}: {:?}", parent, e);
+						continue
+					},
+				};
+				let author = &authorities[(u64::from(slot) % authorities.len() as u64) as usize];
+				if SyncCryptoStore::has_keys(&*keystore, &[(author.to_raw_vec(), AURA)]) {
+					error!(
+						"Block {} at slot {} is sealed with this node's key {:?} by another node. \
+						 Authoring stops until restart; make sure only one node holds the key.",
+						notification.hash, u64::from(slot), author,
+					);
+					detected.store(true, Ordering::Relaxed);
+					return
+				}
+			}
+		});
+
+		Ok(StandbyWorker {
+			inner: worker,
+			after_slots: self.standby_after_slots,
+			active: false,
+			since: 0.into(),
+			duplicate_key,
+		})
+	}
+}
@@ -0,0 +1,265 @@
+//! Validator candidacy: accounts that registered session keys apply as
+//! validator candidates by reserving a bond. `ApproveOrigin` approves them
+//! into the validator set, which takes effect at the next session, or
+//! rejects them and returns the bond. Approved candidates withdraw to leave
+//! the validator set, and claim their bond back `UnbondingPeriod` later, once
+//! offences from their time as validator had time to be reported. The bond of
+//! a candidate reported for misbehaving, also while unbonding, is burned
+//! through `slash`.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+pub use pallet::*;
+
+#[cfg(test)]
+mod mock;
+
+#[cfg(test)]
+mod tests;
+
+#[frame_support::pallet]
+pub mod pallet {
+  use frame_support::{
+    dispatch::DispatchResult,
+    pallet_prelude::*,
+    traits::{Currency, ReservableCurrency},
+  };
+  use frame_system::pallet_prelude::*;
+  use codec::{Encode, Decode};
+  use sp_runtime::traits::Saturating;
+
+  pub type BalanceOf<T> =
+    <<T as Config>::Currency as Currency<<T as frame_system::Config>::AccountId>>::Balance;
+
+  /// The validator set candidates join.
+  pub trait ValidatorRegistry<AccountId> {
+    /// Whether `who` registered session keys.
+    fn has_session_keys(who: &AccountId) -> bool;
+
+    /// Whether `who` is a validator from the next session on.
+    fn is_validator(who: &AccountId) -> bool;
+
+    /// Add `who` to the validator set from the next session on.
+    fn add_validator(who: &AccountId) -> DispatchResult;
+
+    /// Remove `who` from the validator set from the next session on. Fails
+    /// if that leaves too few validators.
+    fn remove_validator(who: &AccountId) -> DispatchResult;
+  }
+
+  #[pallet::config]
+  pub trait Config: frame_system::Config {
+    type Event: From<Event<Self>> + IsType<<Self as frame_system::Config>::Event>;
+
+    /// Currency the bond is reserved in.
+    type Currency: ReservableCurrency<Self::AccountId>;
+
+    type Validators: ValidatorRegistry<Self::AccountId>;
+
+    /// Origin allowed to approve and reject candidates.
+    type ApproveOrigin: EnsureOrigin<Self::Origin>;
+
+    /// Bond reserved while an account is a candidate.
+    #[pallet::constant]
+    type CandidacyBond: Get<BalanceOf<Self>>;
+
+    /// Maximum number of candidates, pending and approved.
+    #[pallet::constant]
+    type MaxCandidates: Get<u32>;
+
+    /// Blocks an approved candidate's bond stays reserved after it withdraws:
+    /// long enough for it to leave the active validator set and for its
+    /// offences to be reported.
+    #[pallet::constant]
+    type UnbondingPeriod: Get<Self::BlockNumber>;
+  }
+
+  #[derive(Clone, PartialEq, Eq, Encode, Decode, RuntimeDebug)]
+  pub struct Candidate<Balance> {
+    /// Bond reserved at application.
+    pub bond: Balance,
+    /// Whether the candidate was approved into the validator set.
+    pub approved: bool,
+  }
+
+  #[pallet::pallet]
+  #[pallet::generate_store(pub(super) trait Store)]
+  pub struct Pallet<T>(_);
+
+  #[pallet::storage]
+  #[pallet::getter(fn candidates)]
+  pub type Candidates<T: Config> =
+    StorageMap<_, Blake2_128Concat, T::AccountId, Candidate<BalanceOf<T>>>;
+
+  #[pallet::storage]
+  #[pallet::getter(fn candidate_count)]
+  pub type CandidateCount<T: Config> = StorageValue<_, u32, ValueQuery>;
+
+  /// Bonds of withdrawn approved candidates, with the block from which they
+  /// can be claimed.
+  #[pallet::storage]
+  #[pallet::getter(fn unbonding)]
+  pub type Unbonding<T: Config> =
+    StorageMap<_, Blake2_128Concat, T::AccountId, (BalanceOf<T>, T::BlockNumber)>;
+
+  #[pallet::event]
+  #[pallet::metadata(T::AccountId = "AccountId", BalanceOf<T> = "Balance")]
+  #[pallet::generate_deposit(pub(super) fn deposit_event)]
+  pub enum Event<T: Config> {
+    /// An account applied as validator candidate. \[candidate, bond\]
+    Applied { candidate: T::AccountId, bond: BalanceOf<T> },
+    /// A candidate joins the validator set at the next session. \[candidate\]
+    Approved { candidate: T::AccountId },
+    /// A candidate was rejected and got its bond back. \[candidate\]
+    Rejected { candidate: T::AccountId },
+    /// A candidate withdrew, leaving the validator set at the next session if
+    /// it was in it. A pending candidate got its bond back, an approved one
+    /// claims it after `UnbondingPeriod`. \[candidate\]
+    Withdrawn { candidate: T::AccountId },
+    /// A withdrawn candidate claimed its bond back. \[candidate, bond\]
+    Claimed { candidate: T::AccountId, bond: BalanceOf<T> },
+    /// A candidate was reported for misbehaving and its bond burned.
+    /// \[candidate, amount\]
+    Slashed { candidate: T::AccountId, amount: BalanceOf<T> },
+  }
+
+  #[pallet::error]
+  pub enum Error<T> {
+    /// The account is a candidate already.
+    AlreadyCandidate,
+    /// The account is a validator already.
+    AlreadyValidator,
+    /// The account has no session keys; set them with `session.setKeys` first.
+    NoSessionKeys,
+    /// There are `MaxCandidates` candidates already.
+    TooManyCandidates,
+    /// The account is not a candidate.
+    NotCandidate,
+    /// The candidate was approved already.
+    AlreadyApproved,
+    /// The bond of an earlier candidacy is still unbonding; claim it first.
+    Unbonding,
+    /// The account has no bond to claim.
+    NotUnbonding,
+    /// The bond cannot be claimed before `UnbondingPeriod` has passed.
+    StillUnbonding,
+  }
+
+  #[pallet::hooks]
+  impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {}
+
+  #[pallet::call]
+  impl<T: Config> Pallet<T> {
+    /// Apply as validator candidate, reserving `CandidacyBond`.
+    #[pallet::weight(T::DbWeight::get().reads_writes(6, 3))]
+    pub fn apply(origin: OriginFor<T>) -> DispatchResult {
+      let who = ensure_signed(origin)?;
+      ensure!(!Candidates::<T>::contains_key(&who), Error::<T>::AlreadyCandidate);
+      ensure!(!Unbonding::<T>::contains_key(&who), Error::<T>::Unbonding);
+      ensure!(!T::Validators::is_validator(&who), Error::<T>::AlreadyValidator);
+      ensure!(T::Validators::has_session_keys(&who), Error::<T>::NoSessionKeys);
+      let count = CandidateCount::<T>::get();
+      ensure!(count < T::MaxCandidates::get(), Error::<T>::TooManyCandidates);
+
+      let bond = T::CandidacyBond::get();
+      T::Currency::reserve(&who, bond)?;
+      Candidates::<T>::insert(&who, Candidate { bond, approved: false });
+      CandidateCount::<T>::put(count + 1);
+      Self::deposit_event(Event::Applied { candidate: who, bond });
+      Ok(())
+    }
+
+    /// Add a pending candidate to the validator set from the next session on.
+    #[pallet::weight(T::DbWeight::get().reads_writes(3, 3))]
+    pub fn approve(origin: OriginFor<T>, candidate: T::AccountId) -> DispatchResult {
+      T::ApproveOrigin::ensure_origin(origin)?;
+      Candidates::<T>::try_mutate(&candidate, |entry| -> DispatchResult {
+        let entry = entry.as_mut().ok_or(Error::<T>::NotCandidate)?;
+        ensure!(!entry.approved, Error::<T>::AlreadyApproved);
+        ensure!(T::Validators::has_session_keys(&candidate), Error::<T>::NoSessionKeys);
+        T::Validators::add_validator(&candidate)?;
+        entry.approved = true;
+        Ok(())
+      })?;
+      Self::deposit_event(Event::Approved { candidate });
+      Ok(())
+    }
+
+    /// Reject a pending candidate and return its bond.
+    #[pallet::weight(T::DbWeight::get().reads_writes(2, 3))]
+    pub fn reject(origin: OriginFor<T>, candidate: T::AccountId) -> DispatchResult {
+      T::ApproveOrigin::ensure_origin(origin)?;
+      let entry = Candidates::<T>::get(&candidate).ok_or(Error::<T>::NotCandidate)?;
+      ensure!(!entry.approved, Error::<T>::AlreadyApproved);
+      Self::release(&candidate, entry.bond);
+      Self::deposit_event(Event::Rejected { candidate });
+      Ok(())
+    }
+
+    /// Stop being a candidate. A pending candidate gets the bond back. An
+    /// approved candidate still in the validator set leaves it at the next
+    /// session, which fails if that would leave too few validators; its bond
+    /// stays reserved until `claim`ed after `UnbondingPeriod`.
+    #[pallet::weight(T::DbWeight::get().reads_writes(5, 5))]
+    pub fn withdraw(origin: OriginFor<T>) -> DispatchResult {
+      let who = ensure_signed(origin)?;
+      let entry = Candidates::<T>::get(&who).ok_or(Error::<T>::NotCandidate)?;
+      if entry.approved {
+        if T::Validators::is_validator(&who) {
+          T::Validators::remove_validator(&who)?;
+        }
+        let unlocks_at = frame_system::Pallet::<T>::block_number().saturating_add(T::UnbondingPeriod::get());
+        Unbonding::<T>::insert(&who, (entry.bond, unlocks_at));
+        Self::remove(&who);
+      } else {
+        Self::release(&who, entry.bond);
+      }
+      Self::deposit_event(Event::Withdrawn { candidate: who });
+      Ok(())
+    }
+
+    /// Get back the bond of a withdrawn candidacy once `UnbondingPeriod` has
+    /// passed.
+    #[pallet::weight(T::DbWeight::get().reads_writes(2, 2))]
+    pub fn claim(origin: OriginFor<T>) -> DispatchResult {
+      let who = ensure_signed(origin)?;
+      let (bond, unlocks_at) = Unbonding::<T>::get(&who).ok_or(Error::<T>::NotUnbonding)?;
+      ensure!(frame_system::Pallet::<T>::block_number() >= unlocks_at, Error::<T>::StillUnbonding);
+      T::Currency::unreserve(&who, bond);
+      Unbonding::<T>::remove(&who);
+      Self::deposit_event(Event::Claimed { candidate: who, bond });
+      Ok(())
+    }
+  }
+
+  impl<T: Config> Pallet<T> {
+    /// Burn the bond of `who`, reported for misbehaving, and end its
+    /// candidacy. A bond still unbonding is burned too. Does nothing if `who`
+    /// has no bond.
+    pub fn slash(who: &T::AccountId) {
+      // `apply` refuses accounts with an unbonding bond, so there is at most
+      // one of the two.
+      let bond = if let Some(entry) = Candidates::<T>::get(who) {
+        Self::remove(who);
+        entry.bond
+      } else if let Some((bond, _)) = Unbonding::<T>::take(who) {
+        bond
+      } else {
+        return
+      };
+      let (_burned, unslashed) = T::Currency::slash_reserved(who, bond);
+      let amount = bond - unslashed;
+      Self::deposit_event(Event::Slashed { candidate: who.clone(), amount });
+    }
+
+    fn release(who: &T::AccountId, bond: BalanceOf<T>) {
+      T::Currency::unreserve(who, bond);
+      Self::remove(who);
+    }
+
+    fn remove(who: &T::AccountId) {
+      Candidates::<T>::remove(who);
+      CandidateCount::<T>::mutate(|count| *count = count.saturating_sub(1));
+    }
+  }
+}
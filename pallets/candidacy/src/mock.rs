@@ -0,0 +1,139 @@
+use crate as pallet_candidacy;
+use std::cell::RefCell;
+use sp_core::H256;
+use frame_support::parameter_types;
+use frame_system::EnsureRoot;
+use sp_runtime::{
+	traits::{BlakeTwo256, IdentityLookup}, testing::Header, DispatchError, DispatchResult,
+};
+use frame_system as system;
+
+type UncheckedExtrinsic = frame_system::mocking::MockUncheckedExtrinsic<Test>;
+type Block = frame_system::mocking::MockBlock<Test>;
+
+// Configure a mock runtime to test the pallet.
+frame_support::construct_runtime!(
+	pub enum Test where
+		Block = Block,
+		NodeBlock = Block,
+		UncheckedExtrinsic = UncheckedExtrinsic,
+	{
+		System: frame_system::{Pallet, Call, Config, Storage, Event<T>},
+		Balances: pallet_balances::{Pallet, Call, Storage, Config<T>, Event<T>},
+		Candidacy: pallet_candidacy::{Pallet, Call, Storage, Event<T>},
+	}
+);
+
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+	pub const SS58Prefix: u8 = 42;
+	pub const ExistentialDeposit: u64 = 1;
+	pub const CandidacyBond: u64 = 100;
+	pub const MaxCandidates: u32 = 2;
+	pub const UnbondingPeriod: u64 = 10;
+}
+
+impl system::Config for Test {
+	type BaseCallFilter = ();
+	type BlockWeights = ();
+	type BlockLength = ();
+	type DbWeight = ();
+	type Origin = Origin;
+	type Call = Call;
+	type Index = u64;
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = Event;
+	type BlockHashCount = BlockHashCount;
+	type Version = ();
+	type PalletInfo = PalletInfo;
+	type AccountData = pallet_balances::AccountData<u64>;
+	type OnNewAccount = ();
+	type OnKilledAccount = ();
+	type SystemWeightInfo = ();
+	type SS58Prefix = SS58Prefix;
+	type OnSetCode = ();
+}
+
+impl pallet_balances::Config for Test {
+	type MaxLocks = ();
+	type MaxReserves = ();
+	type ReserveIdentifier = [u8; 8];
+	type Balance = u64;
+	type Event = Event;
+	type DustRemoval = ();
+	type ExistentialDeposit = ExistentialDeposit;
+	type AccountStore = System;
+	type WeightInfo = ();
+}
+
+thread_local! {
+	/// Accounts with session keys.
+	pub static KEYS: RefCell<Vec<u64>> = RefCell::new(vec![]);
+	/// Validators of the next session.
+	pub static VALIDATORS: RefCell<Vec<u64>> = RefCell::new(vec![]);
+}
+
+/// Validators are never removed below this many.
+pub const MIN_VALIDATORS: usize = 1;
+
+pub struct TestValidators;
+
+impl pallet_candidacy::ValidatorRegistry<u64> for TestValidators {
+	fn has_session_keys(who: &u64) -> bool {
+		KEYS.with(|k| k.borrow().contains(who))
+	}
+
+	fn is_validator(who: &u64) -> bool {
+		validators().contains(who)
+	}
+
+	fn add_validator(who: &u64) -> DispatchResult {
+		VALIDATORS.with(|v| v.borrow_mut().push(*who));
+		Ok(())
+	}
+
+	fn remove_validator(who: &u64) -> DispatchResult {
+		VALIDATORS.with(|v| {
+			let mut validators = v.borrow_mut();
+			if validators.len() <= MIN_VALIDATORS {
+				return Err(DispatchError::Other("Too few validators left"))
+			}
+			validators.retain(|v| v != who);
+			Ok(())
+		})
+	}
+}
+
+impl pallet_candidacy::Config for Test {
+	type Event = Event;
+	type Currency = Balances;
+	type Validators = TestValidators;
+	type ApproveOrigin = EnsureRoot<u64>;
+	type CandidacyBond = CandidacyBond;
+	type MaxCandidates = MaxCandidates;
+	type UnbondingPeriod = UnbondingPeriod;
+}
+
+pub fn validators() -> Vec<u64> {
+	VALIDATORS.with(|v| v.borrow().clone())
+}
+
+// Build genesis storage according to the mock runtime. Accounts 1 to 4 have
+// session keys, 4 too little to bond and 5 no keys; 10 is the only validator.
+pub fn new_test_ext() -> sp_io::TestExternalities {
+	let mut storage = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+	pallet_balances::GenesisConfig::<Test> {
+		balances: vec![(1, 1000), (2, 1000), (3, 1000), (4, 50)],
+	}.assimilate_storage(&mut storage).unwrap();
+	KEYS.with(|k| *k.borrow_mut() = vec![1, 2, 3, 4]);
+	VALIDATORS.with(|v| *v.borrow_mut() = vec![10]);
+
+	let mut ext: sp_io::TestExternalities = storage.into();
+	ext.execute_with(|| System::set_block_number(1));
+	ext
+}
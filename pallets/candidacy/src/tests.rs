@@ -0,0 +1,160 @@
+use crate::{mock::*, Candidate, Error, Event as CandidacyEvent};
+use frame_support::{assert_noop, assert_ok, traits::ReservableCurrency};
+use sp_runtime::{traits::BadOrigin, DispatchError};
+
+fn last_event() -> Event {
+	System::events().pop().expect("an event was deposited").event
+}
+
+#[test]
+fn apply_reserves_bond() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Candidacy::apply(Origin::signed(1)));
+		assert_eq!(Balances::reserved_balance(1), 100);
+		assert_eq!(Candidacy::candidates(1), Some(Candidate { bond: 100, approved: false }));
+		assert_eq!(Candidacy::candidate_count(), 1);
+		assert_eq!(last_event(), Event::Candidacy(CandidacyEvent::Applied { candidate: 1, bond: 100 }));
+
+		assert_noop!(Candidacy::apply(Origin::signed(1)), Error::<Test>::AlreadyCandidate);
+		assert_noop!(Candidacy::apply(Origin::signed(5)), Error::<Test>::NoSessionKeys);
+		assert_noop!(Candidacy::apply(Origin::signed(10)), Error::<Test>::AlreadyValidator);
+		assert!(Candidacy::apply(Origin::signed(4)).is_err());
+		assert_eq!(Candidacy::candidates(4), None);
+
+		assert_ok!(Candidacy::apply(Origin::signed(2)));
+		assert_noop!(Candidacy::apply(Origin::signed(3)), Error::<Test>::TooManyCandidates);
+	});
+}
+
+#[test]
+fn approve_adds_validator() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Candidacy::apply(Origin::signed(1)));
+		assert_noop!(Candidacy::approve(Origin::signed(1), 1), BadOrigin);
+		assert_noop!(Candidacy::approve(Origin::root(), 2), Error::<Test>::NotCandidate);
+
+		assert_ok!(Candidacy::approve(Origin::root(), 1));
+		assert_eq!(validators(), vec![10, 1]);
+		assert_eq!(Candidacy::candidates(1), Some(Candidate { bond: 100, approved: true }));
+		assert_eq!(last_event(), Event::Candidacy(CandidacyEvent::Approved { candidate: 1 }));
+
+		assert_noop!(Candidacy::approve(Origin::root(), 1), Error::<Test>::AlreadyApproved);
+		assert_noop!(Candidacy::reject(Origin::root(), 1), Error::<Test>::AlreadyApproved);
+	});
+}
+
+#[test]
+fn reject_returns_bond() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Candidacy::apply(Origin::signed(1)));
+		assert_noop!(Candidacy::reject(Origin::signed(1), 1), BadOrigin);
+
+		assert_ok!(Candidacy::reject(Origin::root(), 1));
+		assert_eq!(Balances::reserved_balance(1), 0);
+		assert_eq!(Candidacy::candidates(1), None);
+		assert_eq!(Candidacy::candidate_count(), 0);
+		assert_eq!(validators(), vec![10]);
+		assert_eq!(last_event(), Event::Candidacy(CandidacyEvent::Rejected { candidate: 1 }));
+	});
+}
+
+#[test]
+fn withdraw_leaves_validator_set_above_floor() {
+	new_test_ext().execute_with(|| {
+		assert_noop!(Candidacy::withdraw(Origin::signed(1)), Error::<Test>::NotCandidate);
+		assert_ok!(Candidacy::apply(Origin::signed(1)));
+		assert_ok!(Candidacy::approve(Origin::root(), 1));
+
+		assert_ok!(Candidacy::withdraw(Origin::signed(1)));
+		assert_eq!(validators(), vec![10]);
+		assert_eq!(Candidacy::candidates(1), None);
+		assert_eq!(Candidacy::unbonding(1), Some((100, 11)));
+		assert_eq!(Balances::reserved_balance(1), 100);
+		assert_eq!(last_event(), Event::Candidacy(CandidacyEvent::Withdrawn { candidate: 1 }));
+
+		// Approved, then the only validator left.
+		assert_ok!(Candidacy::apply(Origin::signed(2)));
+		assert_ok!(Candidacy::approve(Origin::root(), 2));
+		VALIDATORS.with(|v| v.borrow_mut().retain(|v| *v == 2));
+		assert_noop!(
+			Candidacy::withdraw(Origin::signed(2)),
+			DispatchError::Other("Too few validators left"),
+		);
+		assert_eq!(Candidacy::unbonding(2), None);
+
+		// Removed from the validator set otherwise, e.g. by governance.
+		VALIDATORS.with(|v| *v.borrow_mut() = vec![10]);
+		assert_ok!(Candidacy::withdraw(Origin::signed(2)));
+		assert_eq!(Candidacy::unbonding(2), Some((100, 11)));
+	});
+}
+
+#[test]
+fn withdraw_returns_pending_bond() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Candidacy::apply(Origin::signed(1)));
+		assert_ok!(Candidacy::withdraw(Origin::signed(1)));
+		assert_eq!(Balances::reserved_balance(1), 0);
+		assert_eq!(Candidacy::unbonding(1), None);
+		assert_eq!(Candidacy::candidate_count(), 0);
+	});
+}
+
+#[test]
+fn claim_returns_bond_after_unbonding_period() {
+	new_test_ext().execute_with(|| {
+		assert_noop!(Candidacy::claim(Origin::signed(1)), Error::<Test>::NotUnbonding);
+		assert_ok!(Candidacy::apply(Origin::signed(1)));
+		assert_ok!(Candidacy::approve(Origin::root(), 1));
+		assert_ok!(Candidacy::withdraw(Origin::signed(1)));
+		assert_noop!(Candidacy::apply(Origin::signed(1)), Error::<Test>::Unbonding);
+
+		System::set_block_number(10);
+		assert_noop!(Candidacy::claim(Origin::signed(1)), Error::<Test>::StillUnbonding);
+
+		System::set_block_number(11);
+		assert_ok!(Candidacy::claim(Origin::signed(1)));
+		assert_eq!(Balances::reserved_balance(1), 0);
+		assert_eq!(Candidacy::unbonding(1), None);
+		assert_eq!(last_event(), Event::Candidacy(CandidacyEvent::Claimed { candidate: 1, bond: 100 }));
+		assert_ok!(Candidacy::apply(Origin::signed(1)));
+	});
+}
+
+#[test]
+fn slash_burns_bond() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Candidacy::apply(Origin::signed(1)));
+		assert_ok!(Candidacy::approve(Origin::root(), 1));
+		VALIDATORS.with(|v| *v.borrow_mut() = vec![10]);
+
+		Candidacy::slash(&1);
+		assert_eq!(Balances::reserved_balance(1), 0);
+		assert_eq!(Balances::free_balance(1), 900);
+		assert_eq!(Balances::total_issuance(), 2950);
+		assert_eq!(Candidacy::candidates(1), None);
+		assert_eq!(Candidacy::candidate_count(), 0);
+		assert_eq!(last_event(), Event::Candidacy(CandidacyEvent::Slashed { candidate: 1, amount: 100 }));
+		assert_noop!(Candidacy::withdraw(Origin::signed(1)), Error::<Test>::NotCandidate);
+
+		// Not a candidate.
+		Candidacy::slash(&2);
+		assert_eq!(Balances::free_balance(2), 1000);
+	});
+}
+
+#[test]
+fn slash_burns_unbonding_bond() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Candidacy::apply(Origin::signed(1)));
+		assert_ok!(Candidacy::approve(Origin::root(), 1));
+		assert_ok!(Candidacy::withdraw(Origin::signed(1)));
+
+		Candidacy::slash(&1);
+		assert_eq!(Balances::reserved_balance(1), 0);
+		assert_eq!(Balances::free_balance(1), 900);
+		assert_eq!(Candidacy::unbonding(1), None);
+		assert_eq!(last_event(), Event::Candidacy(CandidacyEvent::Slashed { candidate: 1, amount: 100 }));
+		assert_noop!(Candidacy::claim(Origin::signed(1)), Error::<Test>::NotUnbonding);
+	});
+}
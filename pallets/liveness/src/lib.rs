@@ -0,0 +1,403 @@
+//! Validator liveness: every session each validator sends a heartbeat,
+//! signed with its `live` session key, from its offchain worker. When the session
+//! ends the validators without a heartbeat are reported offline, and after
+//! `OfflineThreshold` offline sessions in a row they can be removed from
+//! the validator set.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+pub use pallet::*;
+
+#[cfg(test)]
+mod mock;
+
+#[cfg(test)]
+mod tests;
+
+/// Key type of the session key heartbeats are signed with.
+pub const KEY_TYPE: sp_runtime::KeyTypeId = sp_runtime::KeyTypeId(*b"live");
+
+/// The sr25519 session key of the pallet.
+pub mod sr25519 {
+  mod app_sr25519 {
+    use sp_application_crypto::{app_crypto, sr25519};
+    app_crypto!(sr25519, crate::KEY_TYPE);
+  }
+
+  sp_application_crypto::with_pair! {
+    pub type AuthorityPair = app_sr25519::Pair;
+  }
+  pub type AuthoritySignature = app_sr25519::Signature;
+  pub type AuthorityId = app_sr25519::Public;
+}
+
+#[frame_support::pallet]
+pub mod pallet {
+  use frame_support::{
+    dispatch::DispatchResult,
+    pallet_prelude::*,
+    traits::{OneSessionHandler, ValidatorSet},
+  };
+  use frame_system::{offchain::{SendTransactionTypes, SubmitTransaction}, pallet_prelude::*};
+  use codec::{Encode, Decode};
+  use sp_runtime::{
+    traits::{Saturating, Zero},
+    transaction_validity::TransactionPriority,
+    RuntimeAppPublic,
+  };
+  use sp_staking::SessionIndex;
+  use sp_std::vec::Vec;
+
+  /// Blocks between two attempts of a validator to get its heartbeat in.
+  pub const HEARTBEAT_RETRY: u32 = 5;
+
+  /// Takes a validator out of the validator set from the next session on.
+  pub trait RemoveValidator<AccountId> {
+    fn remove_validator(who: &AccountId) -> DispatchResult;
+  }
+
+  impl<AccountId> RemoveValidator<AccountId> for () {
+    fn remove_validator(_who: &AccountId) -> DispatchResult {
+      Err(DispatchError::Other("Removing validators is not supported"))
+    }
+  }
+
+  #[pallet::config]
+  pub trait Config: frame_system::Config + SendTransactionTypes<Call<Self>> {
+    type Event: From<Event<Self>> + IsType<<Self as frame_system::Config>::Event>;
+
+    /// Session key heartbeats are signed with.
+    type AuthorityId: Member + Parameter + RuntimeAppPublic + Default + Ord + MaybeSerializeDeserialize;
+
+    /// Source of the current session index.
+    type ValidatorSet: ValidatorSet<Self::AccountId>;
+
+    /// Removes validators that stayed offline, if `RemoveOffline` is set.
+    type ValidatorRemoval: RemoveValidator<Self::AccountId>;
+
+    /// Origin allowed to change the offline threshold.
+    type ConfigOrigin: EnsureOrigin<Self::Origin>;
+
+    /// Transaction pool priority of heartbeats.
+    #[pallet::constant]
+    type UnsignedPriority: Get<TransactionPriority>;
+
+    /// Sessions shorter than this many blocks are not judged, since
+    /// validators may not have had the time to send a heartbeat.
+    #[pallet::constant]
+    type MinSessionLength: Get<Self::BlockNumber>;
+
+    /// For how many sessions received heartbeats are kept.
+    #[pallet::constant]
+    type HistoryDepth: Get<SessionIndex>;
+  }
+
+  /// Proof that a validator was online in a session.
+  #[derive(Clone, PartialEq, Eq, Encode, Decode, RuntimeDebug)]
+  pub struct Heartbeat<BlockNumber> {
+    /// Block the heartbeat was created at.
+    pub block_number: BlockNumber,
+    pub session_index: SessionIndex,
+    /// Index of the validator in `Keys`.
+    pub authority_index: u32,
+  }
+
+  #[pallet::pallet]
+  #[pallet::generate_store(pub(super) trait Store)]
+  pub struct Pallet<T>(_);
+
+  /// Block the current session started at.
+  #[pallet::storage]
+  #[pallet::getter(fn session_start)]
+  pub type SessionStart<T: Config> = StorageValue<_, T::BlockNumber, ValueQuery>;
+
+  /// Validators of the current session.
+  #[pallet::storage]
+  #[pallet::getter(fn validators)]
+  pub type Validators<T: Config> = StorageValue<_, Vec<T::AccountId>, ValueQuery>;
+
+  /// Session keys of `Validators`, in the same order.
+  #[pallet::storage]
+  #[pallet::getter(fn keys)]
+  pub type Keys<T: Config> = StorageValue<_, Vec<T::AuthorityId>, ValueQuery>;
+
+  /// Block at which the heartbeat of a validator was included, per session.
+  #[pallet::storage]
+  #[pallet::getter(fn received_heartbeats)]
+  pub type ReceivedHeartbeats<T: Config> = StorageDoubleMap<
+    _,
+    Twox64Concat,
+    SessionIndex,
+    Blake2_128Concat,
+    T::AccountId,
+    T::BlockNumber,
+  >;
+
+  /// Sessions in a row a validator was offline.
+  #[pallet::storage]
+  #[pallet::getter(fn offline_sessions)]
+  pub type OfflineSessions<T: Config> = StorageMap<_, Blake2_128Concat, T::AccountId, u32, ValueQuery>;
+
+  /// Offline sessions in a row after which a validator is reported with
+  /// `Offline`. Zero turns reporting off.
+  #[pallet::storage]
+  #[pallet::getter(fn offline_threshold)]
+  pub type OfflineThreshold<T: Config> = StorageValue<_, u32, ValueQuery>;
+
+  /// Whether validators reported with `Offline` are removed from the
+  /// validator set.
+  #[pallet::storage]
+  #[pallet::getter(fn remove_offline)]
+  pub type RemoveOffline<T: Config> = StorageValue<_, bool, ValueQuery>;
+
+  #[pallet::genesis_config]
+  pub struct GenesisConfig {
+    pub offline_threshold: u32,
+    pub remove_offline: bool,
+  }
+
+  #[cfg(feature = "std")]
+  impl Default for GenesisConfig {
+    fn default() -> Self {
+      Self { offline_threshold: 3, remove_offline: false }
+    }
+  }
+
+  #[pallet::genesis_build]
+  impl<T: Config> GenesisBuild<T> for GenesisConfig {
+    fn build(&self) {
+      OfflineThreshold::<T>::put(self.offline_threshold);
+      RemoveOffline::<T>::put(self.remove_offline);
+    }
+  }
+
+  #[pallet::event]
+  #[pallet::metadata(T::AccountId = "AccountId", T::BlockNumber = "BlockNumber")]
+  #[pallet::generate_deposit(pub(super) fn deposit_event)]
+  pub enum Event<T: Config> {
+    /// A validator sent its heartbeat. \[validator, session\]
+    HeartbeatReceived { validator: T::AccountId, session: SessionIndex },
+    /// All validators were online in a session. \[session\]
+    AllGood { session: SessionIndex },
+    /// Validators without a heartbeat in a session. \[session, offline\]
+    SomeOffline { session: SessionIndex, offline: Vec<T::AccountId> },
+    /// A session was too short to judge. \[session, length\]
+    SessionNotJudged { session: SessionIndex, length: T::BlockNumber },
+    /// A validator was offline for `OfflineThreshold` sessions or more in a
+    /// row. \[validator, sessions\]
+    Offline { validator: T::AccountId, sessions: u32 },
+    /// An offline validator leaves the validator set at the next session.
+    /// \[validator\]
+    ValidatorRemoved { validator: T::AccountId },
+    /// An offline validator could not be removed. \[validator, error\]
+    RemovalFailed { validator: T::AccountId, error: DispatchError },
+    /// \[threshold, remove_offline\]
+    OfflineThresholdSet { threshold: u32, remove_offline: bool },
+  }
+
+  #[pallet::error]
+  pub enum Error<T> {
+    /// The heartbeat is not for the current session.
+    StaleHeartbeat,
+    /// The heartbeat names no validator of the current session.
+    UnknownAuthority,
+    /// The validator already sent a heartbeat this session.
+    DuplicateHeartbeat,
+    /// The heartbeat's signature does not match the validator's key.
+    BadSignature,
+  }
+
+  #[pallet::hooks]
+  impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
+    fn offchain_worker(now: T::BlockNumber) {
+      if sp_io::offchain::is_validator() {
+        if let Err(e) = Self::send_heartbeats(now) {
+          log::debug!(target: "runtime::liveness", "No heartbeat sent at {:?}: {}", now, e);
+        }
+      }
+    }
+  }
+
+  #[pallet::call]
+  impl<T: Config> Pallet<T> {
+    /// Record the heartbeat of a validator. Submitted unsigned by its
+    /// offchain worker.
+    #[pallet::weight(T::DbWeight::get().reads_writes(4, 1))]
+    pub fn heartbeat(
+      origin: OriginFor<T>,
+      heartbeat: Heartbeat<T::BlockNumber>,
+      signature: <T::AuthorityId as RuntimeAppPublic>::Signature,
+    ) -> DispatchResult {
+      ensure_none(origin)?;
+      let validator = Self::check_heartbeat(&heartbeat, &signature)?;
+
+      ReceivedHeartbeats::<T>::insert(heartbeat.session_index, &validator, frame_system::Pallet::<T>::block_number());
+      Self::deposit_event(Event::HeartbeatReceived { validator, session: heartbeat.session_index });
+      Ok(())
+    }
+
+    /// Report validators after `threshold` offline sessions in a row, and
+    /// remove them from the validator set if `remove_offline`.
+    #[pallet::weight(T::DbWeight::get().writes(2))]
+    pub fn set_offline_threshold(origin: OriginFor<T>, threshold: u32, remove_offline: bool) -> DispatchResult {
+      T::ConfigOrigin::ensure_origin(origin)?;
+      OfflineThreshold::<T>::put(threshold);
+      RemoveOffline::<T>::put(remove_offline);
+      Self::deposit_event(Event::OfflineThresholdSet { threshold, remove_offline });
+      Ok(())
+    }
+  }
+
+  #[pallet::validate_unsigned]
+  impl<T: Config> ValidateUnsigned for Pallet<T> {
+    type Call = Call<T>;
+
+    fn validate_unsigned(_source: TransactionSource, call: &Self::Call) -> TransactionValidity {
+      let (heartbeat, signature) = match call {
+        Call::heartbeat(heartbeat, signature) => (heartbeat, signature),
+        _ => return InvalidTransaction::Call.into(),
+      };
+      Self::check_heartbeat(heartbeat, signature).map_err(|e| match e {
+        Error::<T>::StaleHeartbeat | Error::<T>::DuplicateHeartbeat => InvalidTransaction::Stale,
+        Error::<T>::UnknownAuthority => InvalidTransaction::BadSigner,
+        _ => InvalidTransaction::BadProof,
+      })?;
+
+      ValidTransaction::with_tag_prefix("Liveness")
+        .priority(T::UnsignedPriority::get())
+        .and_provides((heartbeat.session_index, heartbeat.authority_index))
+        .longevity(HEARTBEAT_RETRY.into())
+        .propagate(true)
+        .build()
+    }
+  }
+
+  impl<T: Config> Pallet<T> {
+    /// The validator that sent `heartbeat`, if it is valid now.
+    fn check_heartbeat(
+      heartbeat: &Heartbeat<T::BlockNumber>,
+      signature: &<T::AuthorityId as RuntimeAppPublic>::Signature,
+    ) -> Result<T::AccountId, Error<T>> {
+      let session = T::ValidatorSet::session_index();
+      ensure!(heartbeat.session_index == session, Error::<T>::StaleHeartbeat);
+
+      let index = heartbeat.authority_index as usize;
+      let key = Keys::<T>::get().get(index).cloned().ok_or(Error::<T>::UnknownAuthority)?;
+      let validator = Validators::<T>::get().get(index).cloned().ok_or(Error::<T>::UnknownAuthority)?;
+      ensure!(!ReceivedHeartbeats::<T>::contains_key(session, &validator), Error::<T>::DuplicateHeartbeat);
+      ensure!(key.verify(&heartbeat.encode(), signature), Error::<T>::BadSignature);
+      Ok(validator)
+    }
+
+    /// Submit heartbeats for the keys of this node that belong to validators
+    /// without one this session. Retried every `HEARTBEAT_RETRY` blocks.
+    fn send_heartbeats(now: T::BlockNumber) -> Result<(), &'static str> {
+      let since_start = now.saturating_sub(SessionStart::<T>::get());
+      if !(since_start % HEARTBEAT_RETRY.into()).is_zero() {
+        return Ok(())
+      }
+
+      let session_index = T::ValidatorSet::session_index();
+      let validators = Validators::<T>::get();
+      let local_keys = T::AuthorityId::all();
+      for (index, key) in Keys::<T>::get().into_iter().enumerate() {
+        let online = validators.get(index).map_or(true, |v| ReceivedHeartbeats::<T>::contains_key(session_index, v));
+        if online || !local_keys.contains(&key) {
+          continue
+        }
+        let heartbeat = Heartbeat { block_number: now, session_index, authority_index: index as u32 };
+        let signature = key.sign(&heartbeat.encode()).ok_or("unable to sign heartbeat")?;
+        SubmitTransaction::<T, Call<T>>::submit_unsigned_transaction(Call::heartbeat(heartbeat, signature).into())
+          .map_err(|()| "unable to submit heartbeat")?;
+      }
+      Ok(())
+    }
+
+    fn start_session<'a, I>(validators: I)
+    where
+      I: Iterator<Item = (&'a T::AccountId, T::AuthorityId)>,
+      T::AccountId: 'a,
+    {
+      let (validators, keys): (Vec<_>, Vec<_>) = validators.map(|(v, k)| (v.clone(), k)).unzip();
+      Validators::<T>::put(validators);
+      Keys::<T>::put(keys);
+      SessionStart::<T>::put(frame_system::Pallet::<T>::block_number());
+
+      let session = T::ValidatorSet::session_index();
+      if let Some(expired) = session.checked_sub(T::HistoryDepth::get()) {
+        ReceivedHeartbeats::<T>::drain_prefix(expired).for_each(drop);
+      }
+    }
+
+    fn end_session() {
+      let session = T::ValidatorSet::session_index();
+      let length = frame_system::Pallet::<T>::block_number().saturating_sub(SessionStart::<T>::get());
+      if length < T::MinSessionLength::get() {
+        Self::deposit_event(Event::SessionNotJudged { session, length });
+        return
+      }
+
+      let (online, offline): (Vec<_>, Vec<_>) = Validators::<T>::get()
+        .into_iter()
+        .partition(|v| ReceivedHeartbeats::<T>::contains_key(session, v));
+      for validator in &online {
+        OfflineSessions::<T>::remove(validator);
+      }
+
+      let threshold = OfflineThreshold::<T>::get();
+      for validator in &offline {
+        let sessions = OfflineSessions::<T>::mutate(validator, |sessions| {
+          *sessions = sessions.saturating_add(1);
+          *sessions
+        });
+        if threshold == 0 || sessions < threshold {
+          continue
+        }
+        Self::deposit_event(Event::Offline { validator: validator.clone(), sessions });
+        if RemoveOffline::<T>::get() {
+          match T::ValidatorRemoval::remove_validator(validator) {
+            Ok(()) => {
+              OfflineSessions::<T>::remove(validator);
+              Self::deposit_event(Event::ValidatorRemoved { validator: validator.clone() });
+            },
+            Err(error) => Self::deposit_event(Event::RemovalFailed { validator: validator.clone(), error }),
+          }
+        }
+      }
+
+      if offline.is_empty() {
+        Self::deposit_event(Event::AllGood { session });
+      } else {
+        Self::deposit_event(Event::SomeOffline { session, offline });
+      }
+    }
+  }
+
+  impl<T: Config> sp_runtime::BoundToRuntimeAppPublic for Pallet<T> {
+    type Public = T::AuthorityId;
+  }
+
+  impl<T: Config> OneSessionHandler<T::AccountId> for Pallet<T> {
+    type Key = T::AuthorityId;
+
+    fn on_genesis_session<'a, I: 'a>(validators: I)
+    where
+      I: Iterator<Item = (&'a T::AccountId, T::AuthorityId)>,
+    {
+      Self::start_session(validators);
+    }
+
+    fn on_new_session<'a, I: 'a>(_changed: bool, validators: I, _queued_validators: I)
+    where
+      I: Iterator<Item = (&'a T::AccountId, T::AuthorityId)>,
+    {
+      Self::start_session(validators);
+    }
+
+    fn on_before_session_ending() {
+      Self::end_session();
+    }
+
+    fn on_disabled(_validator_index: usize) {}
+  }
+}
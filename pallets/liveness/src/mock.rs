@@ -0,0 +1,149 @@
+use crate as pallet_liveness;
+use std::cell::RefCell;
+use sp_core::H256;
+use frame_support::{parameter_types, traits::{GenesisBuild, ValidatorSet}};
+use frame_system::EnsureRoot;
+use sp_runtime::{
+	traits::{BlakeTwo256, ConvertInto, IdentityLookup}, testing::{Header, TestXt, UintAuthorityId},
+	DispatchError, DispatchResult,
+};
+use sp_staking::SessionIndex;
+use frame_system as system;
+
+type UncheckedExtrinsic = frame_system::mocking::MockUncheckedExtrinsic<Test>;
+type Block = frame_system::mocking::MockBlock<Test>;
+
+// Configure a mock runtime to test the pallet.
+frame_support::construct_runtime!(
+	pub enum Test where
+		Block = Block,
+		NodeBlock = Block,
+		UncheckedExtrinsic = UncheckedExtrinsic,
+	{
+		System: frame_system::{Pallet, Call, Config, Storage, Event<T>},
+		Liveness: pallet_liveness::{Pallet, Call, Storage, Event<T>, Config, ValidateUnsigned},
+	}
+);
+
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+	pub const SS58Prefix: u8 = 42;
+	pub const UnsignedPriority: u64 = 1 << 20;
+	pub const MinSessionLength: u64 = 10;
+	pub const HistoryDepth: SessionIndex = 2;
+}
+
+impl system::Config for Test {
+	type BaseCallFilter = ();
+	type BlockWeights = ();
+	type BlockLength = ();
+	type DbWeight = ();
+	type Origin = Origin;
+	type Call = Call;
+	type Index = u64;
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = Event;
+	type BlockHashCount = BlockHashCount;
+	type Version = ();
+	type PalletInfo = PalletInfo;
+	type AccountData = ();
+	type OnNewAccount = ();
+	type OnKilledAccount = ();
+	type SystemWeightInfo = ();
+	type SS58Prefix = SS58Prefix;
+	type OnSetCode = ();
+}
+
+impl<LocalCall> frame_system::offchain::SendTransactionTypes<LocalCall> for Test where Call: From<LocalCall> {
+	type OverarchingCall = Call;
+	type Extrinsic = TestXt<Call, ()>;
+}
+
+thread_local! {
+	pub static SESSION: RefCell<SessionIndex> = RefCell::new(0);
+	pub static REMOVED: RefCell<Vec<u64>> = RefCell::new(vec![]);
+	pub static REMOVAL_FAILS: RefCell<bool> = RefCell::new(false);
+}
+
+pub struct TestValidatorSet;
+
+impl ValidatorSet<u64> for TestValidatorSet {
+	type ValidatorId = u64;
+	type ValidatorIdOf = ConvertInto;
+
+	fn session_index() -> SessionIndex {
+		SESSION.with(|s| *s.borrow())
+	}
+
+	fn validators() -> Vec<u64> {
+		Liveness::validators()
+	}
+}
+
+pub struct TestRemoval;
+
+impl pallet_liveness::RemoveValidator<u64> for TestRemoval {
+	fn remove_validator(who: &u64) -> DispatchResult {
+		if REMOVAL_FAILS.with(|f| *f.borrow()) {
+			return Err(DispatchError::Other("too few validators"))
+		}
+		REMOVED.with(|r| r.borrow_mut().push(*who));
+		Ok(())
+	}
+}
+
+impl pallet_liveness::Config for Test {
+	type Event = Event;
+	type AuthorityId = UintAuthorityId;
+	type ValidatorSet = TestValidatorSet;
+	type ValidatorRemoval = TestRemoval;
+	type ConfigOrigin = EnsureRoot<u64>;
+	type UnsignedPriority = UnsignedPriority;
+	type MinSessionLength = MinSessionLength;
+	type HistoryDepth = HistoryDepth;
+}
+
+/// Validators 1, 2 and 3, with session keys of the same number.
+pub const VALIDATORS: [u64; 3] = [1, 2, 3];
+
+/// End the current session at block `now` and start the next one with
+/// `VALIDATORS`, the way `pallet_session` drives the handler.
+pub fn rotate_session(now: u64) {
+	use frame_support::traits::OneSessionHandler;
+
+	System::set_block_number(now);
+	Liveness::on_before_session_ending();
+	SESSION.with(|s| *s.borrow_mut() += 1);
+	let keys = VALIDATORS.iter().map(|v| (v, UintAuthorityId(*v)));
+	Liveness::on_new_session(true, keys.clone(), keys);
+}
+
+pub fn removed() -> Vec<u64> {
+	REMOVED.with(|r| r.borrow().clone())
+}
+
+// Build genesis storage according to the mock runtime.
+pub fn new_test_ext() -> sp_io::TestExternalities {
+	use frame_support::traits::OneSessionHandler;
+
+	let mut storage = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+	GenesisBuild::<Test>::assimilate_storage(
+		&pallet_liveness::GenesisConfig { offline_threshold: 2, remove_offline: true },
+		&mut storage,
+	).unwrap();
+	SESSION.with(|s| *s.borrow_mut() = 0);
+	REMOVED.with(|r| r.borrow_mut().clear());
+	REMOVAL_FAILS.with(|f| *f.borrow_mut() = false);
+
+	let mut ext: sp_io::TestExternalities = storage.into();
+	ext.execute_with(|| {
+		System::set_block_number(1);
+		Liveness::on_genesis_session(VALIDATORS.iter().map(|v| (v, UintAuthorityId(*v))));
+	});
+	ext
+}
@@ -0,0 +1,134 @@
+use crate::{mock::*, Error, Event as LivenessEvent, Heartbeat, OfflineSessions, ReceivedHeartbeats};
+use codec::Encode;
+use frame_support::{assert_noop, assert_ok};
+use sp_runtime::{
+	testing::{TestSignature, UintAuthorityId},
+	traits::{BadOrigin, ValidateUnsigned},
+	transaction_validity::{InvalidTransaction, TransactionSource},
+	RuntimeAppPublic,
+};
+
+fn heartbeat(validator: u64, session_index: u32) -> (Heartbeat<u64>, TestSignature) {
+	let heartbeat = Heartbeat {
+		block_number: System::block_number(),
+		session_index,
+		authority_index: validator as u32 - 1,
+	};
+	let signature = UintAuthorityId(validator).sign(&heartbeat.encode()).unwrap();
+	(heartbeat, signature)
+}
+
+fn send_heartbeat(validator: u64) {
+	let (heartbeat, signature) = heartbeat(validator, SESSION.with(|s| *s.borrow()));
+	assert_ok!(Liveness::heartbeat(Origin::none(), heartbeat, signature));
+}
+
+fn liveness_events() -> Vec<LivenessEvent<Test>> {
+	System::events().into_iter().filter_map(|record| match record.event {
+		Event::Liveness(event) => Some(event),
+		_ => None,
+	}).collect()
+}
+
+#[test]
+fn heartbeat_is_recorded_once_per_session() {
+	new_test_ext().execute_with(|| {
+		let (beat, signature) = heartbeat(2, 0);
+		let call = crate::Call::heartbeat(beat.clone(), signature.clone());
+		assert!(Liveness::validate_unsigned(TransactionSource::External, &call).is_ok());
+
+		assert_ok!(Liveness::heartbeat(Origin::none(), beat.clone(), signature.clone()));
+		assert_eq!(ReceivedHeartbeats::<Test>::get(0, 2), Some(1));
+		assert_eq!(liveness_events(), vec![LivenessEvent::HeartbeatReceived { validator: 2, session: 0 }]);
+
+		assert_eq!(
+			Liveness::validate_unsigned(TransactionSource::External, &call),
+			InvalidTransaction::Stale.into(),
+		);
+		assert_noop!(Liveness::heartbeat(Origin::none(), beat, signature), Error::<Test>::DuplicateHeartbeat);
+	});
+}
+
+#[test]
+fn invalid_heartbeats_are_rejected() {
+	new_test_ext().execute_with(|| {
+		let (stale, signature) = heartbeat(1, 1);
+		assert_noop!(Liveness::heartbeat(Origin::none(), stale, signature), Error::<Test>::StaleHeartbeat);
+
+		let (unknown, signature) = heartbeat(4, 0);
+		assert_noop!(Liveness::heartbeat(Origin::none(), unknown, signature), Error::<Test>::UnknownAuthority);
+
+		let (beat, _) = heartbeat(2, 0);
+		let forged = UintAuthorityId(1).sign(&beat.encode()).unwrap();
+		let call = crate::Call::heartbeat(beat.clone(), forged.clone());
+		assert_eq!(
+			Liveness::validate_unsigned(TransactionSource::External, &call),
+			InvalidTransaction::BadProof.into(),
+		);
+		assert_noop!(Liveness::heartbeat(Origin::none(), beat, forged), Error::<Test>::BadSignature);
+
+		let (beat, signature) = heartbeat(2, 0);
+		assert_noop!(Liveness::heartbeat(Origin::signed(2), beat, signature), BadOrigin);
+	});
+}
+
+#[test]
+fn offline_validator_is_removed_after_threshold() {
+	new_test_ext().execute_with(|| {
+		send_heartbeat(1);
+		send_heartbeat(2);
+		rotate_session(11);
+		assert_eq!(OfflineSessions::<Test>::get(3), 1);
+		assert!(liveness_events().contains(&LivenessEvent::SomeOffline { session: 0, offline: vec![3] }));
+		assert!(removed().is_empty());
+
+		// Validator 1 misses one session only; its count restarts once back.
+		send_heartbeat(2);
+		rotate_session(21);
+		assert_eq!(OfflineSessions::<Test>::get(1), 1);
+		assert!(liveness_events().contains(&LivenessEvent::Offline { validator: 3, sessions: 2 }));
+		assert!(liveness_events().contains(&LivenessEvent::ValidatorRemoved { validator: 3 }));
+		assert_eq!(removed(), vec![3]);
+		assert_eq!(OfflineSessions::<Test>::get(3), 0);
+
+		VALIDATORS.iter().for_each(|v| send_heartbeat(*v));
+		rotate_session(31);
+		assert_eq!(OfflineSessions::<Test>::get(1), 0);
+		assert!(liveness_events().contains(&LivenessEvent::AllGood { session: 2 }));
+		// Heartbeats older than `HistoryDepth` sessions are pruned.
+		assert_eq!(ReceivedHeartbeats::<Test>::get(0, 1), None);
+		assert_eq!(ReceivedHeartbeats::<Test>::get(2, 1), Some(21));
+	});
+}
+
+#[test]
+fn short_sessions_are_not_judged() {
+	new_test_ext().execute_with(|| {
+		rotate_session(5);
+		assert_eq!(liveness_events(), vec![LivenessEvent::SessionNotJudged { session: 0, length: 4 }]);
+		assert_eq!(OfflineSessions::<Test>::get(1), 0);
+	});
+}
+
+#[test]
+fn threshold_is_set_by_config_origin_and_failed_removals_are_reported() {
+	new_test_ext().execute_with(|| {
+		assert_noop!(Liveness::set_offline_threshold(Origin::signed(1), 1, true), BadOrigin);
+		assert_ok!(Liveness::set_offline_threshold(Origin::root(), 1, true));
+		assert_eq!(Liveness::offline_threshold(), 1);
+
+		REMOVAL_FAILS.with(|f| *f.borrow_mut() = true);
+		send_heartbeat(1);
+		send_heartbeat(2);
+		rotate_session(11);
+		assert!(liveness_events().contains(&LivenessEvent::RemovalFailed {
+			validator: 3,
+			error: "too few validators".into(),
+		}));
+		assert_eq!(OfflineSessions::<Test>::get(3), 1);
+
+		assert_ok!(Liveness::set_offline_threshold(Origin::root(), 0, false));
+		rotate_session(21);
+		assert!(!liveness_events().contains(&LivenessEvent::Offline { validator: 1, sessions: 1 }));
+	});
+}
@@ -0,0 +1,164 @@
+//! Verification of ColdStack `Balances` storage proofs.
+//!
+//! A `coldstack_balanceProof` RPC call returns a [`BalanceProof`]: the balance
+//! of an ETH address at some block together with the trie nodes needed to
+//! reach it from that block's state root. [`verify_balance_proof`] checks such
+//! a proof against a header the caller already trusts, e.g. one obtained from
+//! `chain_getFinalizedHead` and checked against a GRANDPA justification.
+
+use codec::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+use sp_core::{hashing::{blake2_128, twox_128}, Bytes};
+use sp_rpc::number::NumberOrHex;
+use sp_runtime::traits::Header as HeaderT;
+use sp_trie::StorageProof;
+use std::{convert::TryInto, fmt};
+
+/// Name of the ColdStack pallet in `construct_runtime!`.
+pub const PALLET_PREFIX: &[u8] = b"ColdStack";
+
+/// Name of the ETH address -> balance storage map.
+pub const BALANCES_PREFIX: &[u8] = b"Balances";
+
+/// Balance of an ETH address at a block, along with its storage proof.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BalanceProof<Hash> {
+	/// Hash of the block the proof was generated at.
+	pub at: Hash,
+	/// The ETH address the balance belongs to.
+	pub eth_address: Bytes,
+	/// Value of `ColdStack::Balances` for `eth_address`. Zero if absent.
+	pub balance: NumberOrHex,
+	/// Trie nodes proving `balance` against the state root of `at`.
+	pub proof: Vec<Bytes>,
+}
+
+/// Reasons a balance proof is rejected.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+	/// The proof was generated at a different block than the given header.
+	BlockMismatch,
+	/// The trie nodes don't lead to the header's state root.
+	InvalidProof(String),
+	/// The proven storage value is not a SCALE-encoded `u128`.
+	InvalidValue,
+	/// The proven balance differs from the one claimed in the proof.
+	BalanceMismatch { claimed: u128, proven: u128 },
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Error::BlockMismatch => write!(f, "Proof was not generated at the given header"),
+			Error::InvalidProof(e) => write!(f, "Invalid storage proof: {}", e),
+			Error::InvalidValue => write!(f, "Proven value is not a balance"),
+			Error::BalanceMismatch { claimed, proven } =>
+				write!(f, "Claimed balance {} but proof shows {}", claimed, proven),
+		}
+	}
+}
+
+impl std::error::Error for Error {}
+
+/// Storage key of `ColdStack::Balances` for `eth_address`.
+///
+/// The map is `Blake2_128Concat` over a SCALE-encoded `Vec<u8>`.
+pub fn balance_storage_key(eth_address: &[u8]) -> Vec<u8> {
+	let encoded = eth_address.encode();
+	let mut key = Vec::with_capacity(32 + 16 + encoded.len());
+	key.extend_from_slice(&twox_128(PALLET_PREFIX));
+	key.extend_from_slice(&twox_128(BALANCES_PREFIX));
+	key.extend_from_slice(&blake2_128(&encoded));
+	key.extend_from_slice(&encoded);
+	key
+}
+
+/// Checks `proof` against `header` and returns the proven balance.
+///
+/// The caller is responsible for `header` being finalized; this function only
+/// ties the proof to the header's state root.
+pub fn verify_balance_proof<H>(header: &H, proof: &BalanceProof<H::Hash>) -> Result<u128, Error>
+where
+	H: HeaderT,
+	H::Hash: Encode + Decode + Ord,
+{
+	if header.hash() != proof.at {
+		return Err(Error::BlockMismatch)
+	}
+
+	let key = balance_storage_key(&proof.eth_address);
+	let storage_proof = StorageProof::new(proof.proof.iter().map(|node| node.to_vec()).collect());
+	let mut values = sp_state_machine::read_proof_check::<<H as HeaderT>::Hashing, _>(
+		*header.state_root(),
+		storage_proof,
+		&[&key],
+	).map_err(|e| Error::InvalidProof(e.to_string()))?;
+
+	// `Balances` is a `ValueQuery` map, so a missing entry means zero.
+	let proven = match values.remove(&key).flatten() {
+		Some(raw) => u128::decode(&mut &raw[..]).map_err(|_| Error::InvalidValue)?,
+		None => 0,
+	};
+	let claimed: u128 = proof.balance.try_into().map_err(|_| Error::InvalidValue)?;
+	if claimed != proven {
+		return Err(Error::BalanceMismatch { claimed, proven })
+	}
+
+	Ok(proven)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use sp_runtime::{generic::{Digest, Header}, traits::BlakeTwo256};
+	use sp_state_machine::{prove_read, Backend, InMemoryBackend};
+
+	const ADDRESS: [u8; 20] = [0x44; 20];
+
+	fn backend_with_balance(balance: u128) -> InMemoryBackend<BlakeTwo256> {
+		let entries = vec![(balance_storage_key(&ADDRESS), Some(balance.encode()))];
+		InMemoryBackend::<BlakeTwo256>::from(vec![(None, entries)])
+	}
+
+	fn proof_at(backend: &InMemoryBackend<BlakeTwo256>, balance: u128)
+		-> (Header<u32, BlakeTwo256>, BalanceProof<sp_core::H256>)
+	{
+		let state_root = *backend.as_trie_backend().unwrap().root();
+		let header = Header::new(1, Default::default(), state_root, Default::default(), Digest::default());
+		let proof = prove_read(backend.clone(), &[balance_storage_key(&ADDRESS)]).unwrap();
+		let proof = BalanceProof {
+			at: header.hash(),
+			eth_address: ADDRESS.to_vec().into(),
+			balance: balance.into(),
+			proof: proof.iter_nodes().map(Into::into).collect(),
+		};
+		(header, proof)
+	}
+
+	#[test]
+	fn accepts_valid_proof() {
+		let backend = backend_with_balance(1_000);
+		let (header, proof) = proof_at(&backend, 1_000);
+		assert_eq!(verify_balance_proof(&header, &proof), Ok(1_000));
+	}
+
+	#[test]
+	fn rejects_forged_balance() {
+		let backend = backend_with_balance(1_000);
+		let (header, mut proof) = proof_at(&backend, 1_000);
+		proof.balance = 2_000u128.into();
+		assert_eq!(
+			verify_balance_proof(&header, &proof),
+			Err(Error::BalanceMismatch { claimed: 2_000, proven: 1_000 }),
+		);
+	}
+
+	#[test]
+	fn rejects_proof_from_other_block() {
+		let backend = backend_with_balance(1_000);
+		let (mut header, proof) = proof_at(&backend, 1_000);
+		header.number = 2;
+		assert_eq!(verify_balance_proof(&header, &proof), Err(Error::BlockMismatch));
+	}
+}
@@ -0,0 +1,526 @@
+//! Subscription to ColdStack events, filtered by ETH address.
+
+use std::{convert::TryInto, marker::PhantomData, sync::Arc};
+
+use codec::{Decode, Encode};
+use futures::{future, stream, FutureExt, SinkExt, StreamExt};
+use jsonrpc_core::Result;
+use jsonrpc_derive::rpc;
+use jsonrpc_pubsub::{manager::SubscriptionManager, typed::Subscriber, SubscriptionId};
+use log::warn;
+use pallet_template::AddressRole;
+use sc_client_api::{Backend, BlockchainEvents, StorageProvider};
+use sc_rpc::{DenyUnsafe, SubscriptionTaskExecutor};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sp_blockchain::{tree_route, HeaderBackend, HeaderMetadata};
+use sp_core::{hashing::{blake2_128, twox_128}, storage::StorageKey, Bytes};
+use sp_rpc::number::NumberOrHex;
+use sp_runtime::{
+	generic::BlockId,
+	traits::{Block as BlockT, Header as HeaderT, SaturatedConversion},
+};
+
+use crate::{rpc_error, Error};
+
+/// Which ColdStack events to stream, and from where.
+///
+/// An event matches if it involves any of the listed addresses in the given
+/// role. With all lists empty, every ColdStack event matches.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct EventFilter {
+	pub users: Vec<Bytes>,
+	pub gateways: Vec<Bytes>,
+	pub filenodes: Vec<Bytes>,
+	pub billing: Vec<Bytes>,
+	/// Only report finalized blocks. Otherwise blocks are reported as they
+	/// become best, and reported again with `retracted` set if they leave the
+	/// best chain.
+	pub finalized_only: bool,
+	/// Report blocks from this number on, starting with ones already
+	/// imported. Without it, only blocks imported after subscribing are
+	/// reported. At most `MAX_CATCH_UP` blocks behind the head.
+	pub from_block: Option<u64>,
+}
+
+/// Matching ColdStack events of a block. Blocks without any are skipped.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockEvents<Hash> {
+	pub block_number: u64,
+	pub block_hash: Hash,
+	/// Whether the block, reported before, has left the best chain, so that
+	/// its events no longer apply. Never set for finalized blocks.
+	pub retracted: bool,
+	pub events: Vec<ColdStackEvent>,
+}
+
+/// A decoded ColdStack event.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ColdStackEvent {
+	/// Index of the extrinsic that emitted the event, `None` for events
+	/// emitted outside of extrinsics.
+	pub extrinsic_index: Option<u32>,
+	/// Event name, e.g. `Upload`.
+	pub name: String,
+	/// Event fields by name. Byte fields are hex encoded.
+	pub fields: Value,
+}
+
+/// Blocks with events deposited under a topic.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TopicBlock<Hash> {
+	pub block_number: u64,
+	pub block_hash: Hash,
+	/// Indices of the topic's events among all events of the block.
+	pub event_indices: Vec<u32>,
+}
+
+/// Maximum number of blocks `coldstack_topicBlocks` scans in one call.
+pub const MAX_TOPIC_RANGE: u64 = 10_000;
+
+/// Maximum number of already imported blocks a subscription catches up on,
+/// i.e. how far `EventFilter::from_block` may lie behind the head.
+pub const MAX_CATCH_UP: u64 = 10_000;
+
+/// ColdStack event subscription and lookup.
+#[rpc]
+pub trait ColdStackEventsApi<BlockHash> {
+	/// RPC metadata
+	type Metadata;
+
+	/// Returns the canonical blocks in `from_block..=to_block` (up to the best
+	/// block if `to_block` is omitted) with events deposited under `topic`.
+	/// At most `MAX_TOPIC_RANGE` blocks are scanned per call.
+	///
+	/// Unsafe, since a call reads the state of up to `MAX_TOPIC_RANGE` blocks.
+	#[rpc(name = "coldstack_topicBlocks")]
+	fn topic_blocks(
+		&self,
+		topic: BlockHash,
+		from_block: u64,
+		to_block: Option<u64>,
+	) -> Result<Vec<TopicBlock<BlockHash>>>;
+
+	/// Streams ColdStack events matching `filter`, one notification per block.
+	#[pubsub(subscription = "coldstack_events", subscribe, name = "coldstack_subscribeEvents")]
+	fn subscribe_events(
+		&self,
+		metadata: Self::Metadata,
+		subscriber: Subscriber<BlockEvents<BlockHash>>,
+		filter: Option<EventFilter>,
+	);
+
+	/// Cancels a `coldstack_subscribeEvents` subscription.
+	#[pubsub(subscription = "coldstack_events", unsubscribe, name = "coldstack_unsubscribeEvents")]
+	fn unsubscribe_events(
+		&self,
+		metadata: Option<Self::Metadata>,
+		id: SubscriptionId,
+	) -> Result<bool>;
+}
+
+/// Implementation of the ColdStack event subscription.
+///
+/// `Runtime` is the runtime the node runs; its events are decoded from
+/// `System::Events` storage.
+pub struct ColdStackEvents<C, Block, B, Runtime> {
+	client: Arc<C>,
+	manager: SubscriptionManager,
+	deny_unsafe: DenyUnsafe,
+	_marker: PhantomData<fn() -> (Block, B, Runtime)>,
+}
+
+impl<C, Block, B, Runtime> ColdStackEvents<C, Block, B, Runtime> {
+	/// Create new `ColdStackEvents`, running subscriptions on `executor`.
+	pub fn new(client: Arc<C>, executor: SubscriptionTaskExecutor, deny_unsafe: DenyUnsafe) -> Self {
+		Self {
+			client,
+			manager: SubscriptionManager::new(Arc::new(executor)),
+			deny_unsafe,
+			_marker: Default::default(),
+		}
+	}
+}
+
+/// Storage key of `System::Events`.
+fn events_storage_key() -> StorageKey {
+	let mut key = twox_128(b"System").to_vec();
+	key.extend_from_slice(&twox_128(b"Events"));
+	StorageKey(key)
+}
+
+/// Storage key of `System::EventTopics` for `topic`.
+fn event_topics_storage_key(topic: &impl Encode) -> StorageKey {
+	let encoded = topic.encode();
+	let mut key = twox_128(b"System").to_vec();
+	key.extend_from_slice(&twox_128(b"EventTopics"));
+	key.extend_from_slice(&blake2_128(&encoded));
+	key.extend_from_slice(&encoded);
+	StorageKey(key)
+}
+
+impl EventFilter {
+	fn matches<T: pallet_template::Config>(&self, event: &pallet_template::Event<T>) -> bool {
+		if self.users.is_empty() && self.gateways.is_empty() && self.filenodes.is_empty()
+			&& self.billing.is_empty()
+		{
+			return true
+		}
+		event.eth_addresses().into_iter().any(|(role, address)| {
+			let wanted = match role {
+				AddressRole::User => &self.users,
+				AddressRole::Gateway => &self.gateways,
+				AddressRole::Filenode => &self.filenodes,
+				AddressRole::Billing => &self.billing,
+			};
+			wanted.iter().any(|wanted| &wanted[..] == address)
+		})
+	}
+}
+
+/// Matching ColdStack events of block `number` with hash `hash`, or `None` if
+/// there are none. `retracted` is passed on to the notification.
+fn block_events<C, Block, B, Runtime>(
+	client: &C,
+	filter: &EventFilter,
+	number: u64,
+	hash: Block::Hash,
+	retracted: bool,
+) -> Result<Option<BlockEvents<Block::Hash>>>
+where
+	Block: BlockT,
+	B: Backend<Block>,
+	C: StorageProvider<Block, B>,
+	Runtime: pallet_template::Config,
+	Runtime::AccountId: Serialize,
+	<Runtime as frame_system::Config>::Event: TryInto<pallet_template::Event<Runtime>>,
+{
+	let raw = client.storage(&BlockId::Hash(hash), &events_storage_key())
+		.map_err(|e| rpc_error(Error::UnknownBlock, "Unable to read events; state may be pruned.", e))?;
+	let records = match raw {
+		Some(raw) => Vec::<frame_system::EventRecord<<Runtime as frame_system::Config>::Event, Block::Hash>>
+			::decode(&mut &raw.0[..])
+			.map_err(|e| rpc_error(Error::RuntimeError, "Unable to decode events.", e))?,
+		None => Vec::new(),
+	};
+
+	let events: Vec<_> = records.into_iter()
+		.filter_map(|record| {
+			let event: pallet_template::Event<Runtime> = record.event.try_into().ok()?;
+			if !filter.matches(&event) {
+				return None
+			}
+			let extrinsic_index = match record.phase {
+				frame_system::Phase::ApplyExtrinsic(index) => Some(index),
+				_ => None,
+			};
+			let (name, fields) = event_json(&event);
+			Some(ColdStackEvent { extrinsic_index, name: name.into(), fields })
+		})
+		.collect();
+
+	if events.is_empty() {
+		return Ok(None)
+	}
+	Ok(Some(BlockEvents { block_number: number, block_hash: hash, retracted, events }))
+}
+
+/// Number and hash of `head` and its ancestors down to block `start`, oldest
+/// first.
+fn ancestry<C, Block>(
+	client: &C,
+	head: Block::Hash,
+	head_number: u64,
+	start: u64,
+) -> sp_blockchain::Result<Vec<(u64, Block::Hash)>>
+where
+	Block: BlockT,
+	C: HeaderMetadata<Block, Error = sp_blockchain::Error>,
+{
+	let mut blocks = Vec::new();
+	let mut hash = head;
+	for number in (start..=head_number).rev() {
+		blocks.push((number, hash));
+		if number > start {
+			hash = client.header_metadata(hash)?.parent;
+		}
+	}
+	blocks.reverse();
+	Ok(blocks)
+}
+
+fn hex(bytes: &[u8]) -> Bytes {
+	bytes.to_vec().into()
+}
+
+/// Name and fields of a ColdStack event. Field names are those of the
+/// pallet's `Event`, in camelCase.
+fn event_json<T>(event: &pallet_template::Event<T>) -> (&'static str, Value)
+where
+	T: pallet_template::Config,
+	T::AccountId: Serialize,
+{
+	use pallet_template::Event::*;
+	match event {
+		Upload {
+			user_eth_address,
+			file_name_hash,
+			file_size_bytes,
+			file_contents_hash,
+			gateway_eth_address,
+			filenode_eth_address,
+			file_storage_class,
+			is_forced,
+		} => ("Upload", json!({
+			"userEthAddress": hex(user_eth_address),
+			"fileNameHash": hex(file_name_hash),
+			"fileSizeBytes": NumberOrHex::from(*file_size_bytes),
+			"fileContentsHash": hex(file_contents_hash),
+			"gatewayEthAddress": hex(gateway_eth_address),
+			"filenodeEthAddress": hex(filenode_eth_address),
+			"fileStorageClass": file_storage_class,
+			"isForced": is_forced,
+		})),
+		ChangeStatusGateway { gateway_eth_address, is_active } => ("ChangeStatusGateway", json!({
+			"gatewayEthAddress": hex(gateway_eth_address),
+			"isActive": is_active,
+		})),
+		DeleteGateway { gateway_eth_address } =>
+			("DeleteGateway", json!({ "gatewayEthAddress": hex(gateway_eth_address) })),
+		Download {
+			user_eth_address,
+			file_name_hash,
+			file_size_bytes,
+			file_contents_hash,
+			gateway_eth_address,
+			filenode_eth_address,
+		} => ("Download", json!({
+			"userEthAddress": hex(user_eth_address),
+			"fileNameHash": hex(file_name_hash),
+			"fileSizeBytes": NumberOrHex::from(*file_size_bytes),
+			"fileContentsHash": hex(file_contents_hash),
+			"gatewayEthAddress": hex(gateway_eth_address),
+			"filenodeEthAddress": hex(filenode_eth_address),
+		})),
+		Delete { user_eth_address, file_name_hash, filenode_eth_address } => ("Delete", json!({
+			"userEthAddress": hex(user_eth_address),
+			"fileNameHash": hex(file_name_hash),
+			"filenodeEthAddress": hex(filenode_eth_address),
+		})),
+		Deposit { eth_address, value } => ("Deposit", json!({
+			"ethAddress": hex(eth_address),
+			"value": NumberOrHex::from(*value),
+		})),
+		Withdraw { eth_address, value } => ("Withdraw", json!({
+			"ethAddress": hex(eth_address),
+			"value": NumberOrHex::from(*value),
+		})),
+		Transfer { from, to, value } => ("Transfer", transfer_json(from, to, *value)),
+		StartStaking { from, to, value } => ("StartStaking", transfer_json(from, to, *value)),
+		CancelStaking { from, to, value } => ("CancelStaking", transfer_json(from, to, *value)),
+		EndStaking { from, to, value } => ("EndStaking", transfer_json(from, to, *value)),
+		RewardStaking { from, eth_address, value } => ("RewardStaking", json!({
+			"from": hex(from),
+			"ethAddress": hex(eth_address),
+			"value": NumberOrHex::from(*value),
+		})),
+		FilePermissionGranted { eth_address, account_id, node_url } => ("FilePermissionGranted", json!({
+			"ethAddress": hex(eth_address),
+			"accountId": account_id,
+			"nodeUrl": String::from_utf8_lossy(node_url),
+		})),
+		FilePermissionRevoked { eth_address, account_id } => ("FilePermissionRevoked", json!({
+			"ethAddress": hex(eth_address),
+			"accountId": account_id,
+		})),
+		BillingPermissionGranted { eth_address, account_id, node_url } => ("BillingPermissionGranted", json!({
+			"ethAddress": hex(eth_address),
+			"accountId": account_id,
+			"nodeUrl": String::from_utf8_lossy(node_url),
+		})),
+		BillingPermissionRevoked { eth_address, account_id } => ("BillingPermissionRevoked", json!({
+			"ethAddress": hex(eth_address),
+			"accountId": account_id,
+		})),
+		GatewayNodeRegistered { eth_address, seed_eth_address, storage, node_url, is_active } =>
+			("GatewayNodeRegistered", json!({
+				"ethAddress": hex(eth_address),
+				"seedEthAddress": seed_eth_address.as_ref().map(|seed| hex(seed)),
+				"storage": storage,
+				"nodeUrl": String::from_utf8_lossy(node_url),
+				"isActive": is_active,
+			})),
+		BatchCompleted { succeeded, failed } => ("BatchCompleted", json!({
+			"succeeded": succeeded,
+			"failed": failed,
+		})),
+		BatchInterrupted { index, error } => ("BatchInterrupted", json!({ "index": index, "error": error })),
+		UsageRejected { filenode_eth_address, sequence, error } => ("UsageRejected", json!({
+			"filenodeEthAddress": hex(filenode_eth_address),
+			"sequence": sequence,
+			"error": error,
+		})),
+		KeyChanged { old_key, new_key } => ("KeyChanged", json!({ "oldKey": old_key, "newKey": new_key })),
+		_ => ("Unknown", Value::Null),
+	}
+}
+
+fn transfer_json(from: &[u8], to: &[u8], value: u128) -> Value {
+	json!({ "from": hex(from), "to": hex(to), "value": NumberOrHex::from(value) })
+}
+
+impl<C, Block, B, Runtime> ColdStackEventsApi<Block::Hash> for ColdStackEvents<C, Block, B, Runtime>
+where
+	Block: BlockT,
+	B: Backend<Block> + Send + Sync + 'static,
+	C: HeaderBackend<Block> + HeaderMetadata<Block, Error = sp_blockchain::Error>,
+	C: BlockchainEvents<Block> + StorageProvider<Block, B> + Send + Sync + 'static,
+	Runtime: pallet_template::Config + Send + Sync + 'static,
+	Runtime::AccountId: Serialize,
+	<Runtime as frame_system::Config>::Event: TryInto<pallet_template::Event<Runtime>>,
+{
+	type Metadata = sc_rpc::Metadata;
+
+	fn topic_blocks(
+		&self,
+		topic: Block::Hash,
+		from_block: u64,
+		to_block: Option<u64>,
+	) -> Result<Vec<TopicBlock<Block::Hash>>> {
+		self.deny_unsafe.check_if_safe()?;
+		let best: u64 = self.client.info().best_number.saturated_into();
+		let to_block = to_block.unwrap_or(best).min(best);
+		if to_block >= from_block && to_block - from_block >= MAX_TOPIC_RANGE {
+			return Err(rpc_error(
+				Error::InvalidArguments,
+				"Block range too large.",
+				format!("at most {} blocks per call", MAX_TOPIC_RANGE),
+			))
+		}
+
+		// `System::EventTopics` only holds the topics of the block whose state
+		// it is read at, so look at every block of the range.
+		let key = event_topics_storage_key(&topic);
+		let mut blocks = Vec::new();
+		for number in from_block..=to_block {
+			let hash = self.client.hash(number.saturated_into())
+				.map_err(|e| rpc_error(Error::UnknownBlock, "Unable to fetch block hash.", e))?
+				.ok_or_else(|| rpc_error(Error::UnknownBlock, "Block not found.", number))?;
+			let raw = self.client.storage(&BlockId::Hash(hash), &key)
+				.map_err(|e| rpc_error(Error::UnknownBlock, "Unable to read event topics; state may be pruned.", e))?;
+			let raw = match raw {
+				Some(raw) => raw,
+				None => continue,
+			};
+			let entries = Vec::<(Runtime::BlockNumber, u32)>::decode(&mut &raw.0[..])
+				.map_err(|e| rpc_error(Error::RuntimeError, "Unable to decode event topics.", e))?;
+			blocks.push(TopicBlock {
+				block_number: number,
+				block_hash: hash,
+				event_indices: entries.into_iter().map(|(_, index)| index).collect(),
+			});
+		}
+		Ok(blocks)
+	}
+
+	fn subscribe_events(
+		&self,
+		_metadata: Self::Metadata,
+		subscriber: Subscriber<BlockEvents<Block::Hash>>,
+		filter: Option<EventFilter>,
+	) {
+		let filter = filter.unwrap_or_default();
+		let finalized_only = filter.finalized_only;
+
+		// Subscribe before looking at the chain head, so that no block falls
+		// between catching up and following.
+		let live = if finalized_only {
+			self.client.finality_notification_stream().map(|n| n.hash).boxed()
+		} else {
+			self.client.import_notification_stream()
+				.filter(|n| future::ready(n.is_new_best))
+				.map(|n| n.hash)
+				.boxed()
+		};
+		let info = self.client.info();
+		let (head, head_number): (_, u64) = if finalized_only {
+			(info.finalized_hash, info.finalized_number.saturated_into())
+		} else {
+			(info.best_hash, info.best_number.saturated_into())
+		};
+
+		let start = filter.from_block.unwrap_or(head_number + 1);
+		if start <= head_number && head_number - start >= MAX_CATCH_UP {
+			let _ = subscriber.reject(rpc_error(
+				Error::InvalidArguments,
+				"`fromBlock` too far behind the head.",
+				format!("at most {} blocks of catch-up", MAX_CATCH_UP),
+			));
+			return
+		}
+		let catch_up = match ancestry(&*self.client, head, head_number, start) {
+			Ok(blocks) => blocks,
+			Err(e) => {
+				let _ = subscriber.reject(rpc_error(
+					Error::UnknownBlock,
+					"Unable to fetch the blocks to catch up on.",
+					e,
+				));
+				return
+			},
+		};
+		let catch_up = stream::iter(catch_up.into_iter().map(|(number, hash)| (number, hash, false)));
+
+		// Walk from the last reported head to each new one, reporting the
+		// blocks that left the chain as retracted and the ones that joined it.
+		// Finalized blocks are never retracted.
+		let client = self.client.clone();
+		let mut last = head;
+		let live = live.flat_map(move |hash| {
+			let blocks = match tree_route(&*client, last, hash) {
+				// `hash` is `last` or one of its ancestors, i.e. a notification
+				// that was on its way when the head was read.
+				Ok(route) if route.enacted().is_empty() => Vec::new(),
+				Ok(route) => {
+					last = hash;
+					route.retracted().iter().map(|block| (block, true))
+						.chain(route.enacted().iter().map(|block| (block, false)))
+						.map(|(block, retracted)| (block.number.saturated_into::<u64>(), block.hash, retracted))
+						.filter(|(number, _, _)| *number >= start)
+						.collect()
+				},
+				Err(e) => {
+					warn!("Unable to route from {:?} to new head {:?}: {:?}", last, hash, e);
+					Vec::new()
+				},
+			};
+			stream::iter(blocks)
+		});
+
+		let client = self.client.clone();
+		let notifications = catch_up.chain(live)
+			.map(move |(number, hash, retracted)| {
+				block_events::<_, _, B, Runtime>(&*client, &filter, number, hash, retracted)
+			})
+			.filter_map(|notification| future::ready(notification.transpose()))
+			.map(Ok::<_, ()>);
+
+		self.manager.add(subscriber, |sink| {
+			notifications
+				.forward(sink.sink_map_err(|e| warn!("Error sending ColdStack events: {:?}", e)))
+				.map(|_| ())
+		});
+	}
+
+	fn unsubscribe_events(
+		&self,
+		_metadata: Option<Self::Metadata>,
+		id: SubscriptionId,
+	) -> Result<bool> {
+		Ok(self.manager.cancel(id))
+	}
+}
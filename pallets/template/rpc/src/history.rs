@@ -0,0 +1,499 @@
+//! Per-address file and ledger history, kept in the node's offchain database.
+//!
+//! The node writes the history with `HistoryWriter` as blocks are finalized,
+//! so records never have to be rolled back on a re-org. `ColdStackHistory`
+//! serves it over RPC.
+
+use std::collections::BTreeMap;
+
+use codec::{Decode, Encode};
+use jsonrpc_core::Result;
+use jsonrpc_derive::rpc;
+use serde::{Deserialize, Serialize};
+use sp_core::{offchain::OffchainStorage, Bytes};
+use sp_offchain::STORAGE_PREFIX;
+use sp_rpc::number::NumberOrHex;
+
+use crate::{check_eth_address, rpc_error, Error, File};
+
+pub use pallet_template::{file_key, FileInfo, FILE_PREFIX};
+
+/// Number of records returned when `limit` is omitted.
+pub const DEFAULT_HISTORY_PAGE: u32 = 100;
+
+/// Maximum number of records returned in one call.
+pub const MAX_HISTORY_PAGE: u32 = 1_000;
+
+/// Prefix of the offchain database keys history records are written under.
+pub const HISTORY_PREFIX: &[u8] = b"coldstack::history";
+
+/// A per-ETH-address history log.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Encode, Decode)]
+pub enum HistoryKind {
+	/// Uploads and deletions of the user's files.
+	Files,
+	/// Balance movements of the address.
+	Ledger,
+}
+
+/// Offchain database key of record `index` of the `kind` log of
+/// `eth_address`: the SCALE encoding of
+/// `(HISTORY_PREFIX, kind, eth_address, index)`.
+pub fn history_key(kind: HistoryKind, eth_address: &[u8], index: u64) -> Vec<u8> {
+	(HISTORY_PREFIX, kind, eth_address, index).encode()
+}
+
+/// Offchain database key of the length of the `kind` log of `eth_address`,
+/// stored with the number of the block last written to it.
+fn history_length_key(kind: HistoryKind, eth_address: &[u8]) -> Vec<u8> {
+	(HISTORY_PREFIX, kind, eth_address).encode()
+}
+
+/// Offchain database key of the number and hash of the last block written.
+fn last_block_key() -> Vec<u8> {
+	(HISTORY_PREFIX, &b"last_block"[..]).encode()
+}
+
+/// What happened to a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Encode, Decode)]
+pub enum FileAction {
+	Upload,
+	Delete,
+}
+
+/// Entry of a user's `HistoryKind::Files` log.
+///
+/// For `Delete`, only `file_name_hash` and `filenode_eth_address`, the
+/// deleting filenode, are set; the deleted file is the last `Upload` of the
+/// same name.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct FileHistoryRecord {
+	pub block_number: u64,
+	pub action: FileAction,
+	pub file_name_hash: Vec<u8>,
+	pub file_size_bytes: u128,
+	pub file_contents_hash: Vec<u8>,
+	pub gateway_eth_address: Vec<u8>,
+	pub filenode_eth_address: Vec<u8>,
+	pub file_storage_class: u8,
+}
+
+/// Call that moved a balance.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Encode, Decode)]
+pub enum LedgerAction {
+	Deposit,
+	Withdraw,
+	Transfer,
+	StartStaking,
+	CancelStaking,
+	EndStaking,
+	RewardStaking,
+}
+
+/// Entry of an address's `HistoryKind::Ledger` log. The same movement is
+/// logged for both `from` and `to`, each with its own `balance`.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct LedgerHistoryRecord {
+	pub block_number: u64,
+	pub action: LedgerAction,
+	/// Debited address, `None` for funds leaving `LockedFunds`.
+	pub from: Option<Vec<u8>>,
+	/// Credited address, `None` for funds returning to `LockedFunds`.
+	pub to: Option<Vec<u8>>,
+	pub value: u128,
+	/// Balance of the log's address after the movement.
+	pub balance: u128,
+}
+
+/// Writes the file and ledger history of finalized blocks to an offchain
+/// database.
+///
+/// Blocks must be written in order. A block that was partly written before
+/// the node stopped can be written again: logs already holding its records
+/// are skipped.
+pub struct HistoryWriter<S> {
+	storage: S,
+}
+
+impl<S: OffchainStorage> HistoryWriter<S> {
+	/// Create new `HistoryWriter` writing to `storage`.
+	pub fn new(storage: S) -> Self {
+		Self { storage }
+	}
+
+	/// Number and hash of the last block written, `None` if there is none.
+	pub fn last_block(&self) -> Option<(u64, Vec<u8>)> {
+		last_block(&self.storage)
+	}
+
+	/// Write the history of finalized block `number` with hash `hash`, given
+	/// the ColdStack `events` it deposited. `balance_before` returns the
+	/// `Balances` value of an address in the state of the parent block.
+	pub fn write_block<T, E>(
+		&mut self,
+		number: u64,
+		hash: &[u8],
+		events: impl IntoIterator<Item = pallet_template::Event<T>>,
+		mut balance_before: impl FnMut(&[u8]) -> std::result::Result<u128, E>,
+	) -> std::result::Result<(), E>
+	where
+		T: pallet_template::Config,
+	{
+		use pallet_template::Event::*;
+
+		let mut files = BTreeMap::<Vec<u8>, Vec<Vec<u8>>>::new();
+		let mut movements = Vec::new();
+		for event in events {
+			match event {
+				Upload {
+					user_eth_address,
+					file_name_hash,
+					file_size_bytes,
+					file_contents_hash,
+					gateway_eth_address,
+					filenode_eth_address,
+					file_storage_class,
+					..
+				} => {
+					let file = FileInfo {
+						file_size_bytes,
+						file_contents_hash,
+						gateway_eth_address,
+						filenode_eth_address,
+						file_storage_class,
+						uploaded_at: number,
+					};
+					self.storage.set(STORAGE_PREFIX, &file_key(&user_eth_address, &file_name_hash), &file.encode());
+					files.entry(user_eth_address).or_default().push(FileHistoryRecord {
+						block_number: number,
+						action: FileAction::Upload,
+						file_name_hash,
+						file_size_bytes: file.file_size_bytes,
+						file_contents_hash: file.file_contents_hash,
+						gateway_eth_address: file.gateway_eth_address,
+						filenode_eth_address: file.filenode_eth_address,
+						file_storage_class: file.file_storage_class,
+					}.encode());
+				},
+				Delete { user_eth_address, file_name_hash, filenode_eth_address } => {
+					self.storage.remove(STORAGE_PREFIX, &file_key(&user_eth_address, &file_name_hash));
+					files.entry(user_eth_address).or_default().push(FileHistoryRecord {
+						block_number: number,
+						action: FileAction::Delete,
+						file_name_hash,
+						file_size_bytes: 0,
+						file_contents_hash: Vec::new(),
+						gateway_eth_address: Vec::new(),
+						filenode_eth_address,
+						file_storage_class: 0,
+					}.encode());
+				},
+				Deposit { eth_address, value } =>
+					movements.push((LedgerAction::Deposit, None, Some(eth_address), value)),
+				Withdraw { eth_address, value } =>
+					movements.push((LedgerAction::Withdraw, Some(eth_address), None, value)),
+				Transfer { from, to, value } =>
+					movements.push((LedgerAction::Transfer, Some(from), Some(to), value)),
+				StartStaking { from, to, value } =>
+					movements.push((LedgerAction::StartStaking, Some(from), Some(to), value)),
+				CancelStaking { from, to, value } =>
+					movements.push((LedgerAction::CancelStaking, Some(from), Some(to), value)),
+				EndStaking { from, to, value } =>
+					movements.push((LedgerAction::EndStaking, Some(from), Some(to), value)),
+				// Rewards are paid out of `LockedFunds`; `from` only names the
+				// staking they are for.
+				RewardStaking { eth_address, value, .. } =>
+					movements.push((LedgerAction::RewardStaking, None, Some(eth_address), value)),
+				_ => {},
+			}
+		}
+
+		// Replay the movements on the balances of the parent block to get the
+		// balance after each of them.
+		let mut balances = BTreeMap::<Vec<u8>, u128>::new();
+		let mut ledger = BTreeMap::<Vec<u8>, Vec<Vec<u8>>>::new();
+		for (action, from, to, value) in movements {
+			for eth_address in from.iter().chain(to.iter()) {
+				if !balances.contains_key(eth_address) {
+					balances.insert(eth_address.clone(), balance_before(eth_address)?);
+				}
+			}
+			if let Some(from) = &from {
+				let balance = balances.get_mut(from).expect("inserted above; qed");
+				*balance = balance.saturating_sub(value);
+			}
+			if let Some(to) = &to {
+				let balance = balances.get_mut(to).expect("inserted above; qed");
+				*balance = balance.saturating_add(value);
+			}
+
+			let mut addresses: Vec<_> = from.iter().chain(to.iter()).collect();
+			addresses.dedup();
+			for eth_address in addresses {
+				ledger.entry(eth_address.clone()).or_default().push(LedgerHistoryRecord {
+					block_number: number,
+					action,
+					from: from.clone(),
+					to: to.clone(),
+					value,
+					balance: balances[eth_address],
+				}.encode());
+			}
+		}
+
+		for (eth_address, records) in files {
+			self.append(HistoryKind::Files, &eth_address, number, records);
+		}
+		for (eth_address, records) in ledger {
+			self.append(HistoryKind::Ledger, &eth_address, number, records);
+		}
+		self.storage.set(STORAGE_PREFIX, &last_block_key(), &(number, hash).encode());
+		Ok(())
+	}
+
+	/// Append the encoded `records` of block `block_number` to the `kind` log
+	/// of `eth_address`, unless the log already has them.
+	fn append(&mut self, kind: HistoryKind, eth_address: &[u8], block_number: u64, records: Vec<Vec<u8>>) {
+		let (length, last_written) = history_length(&self.storage, kind, eth_address);
+		if last_written.map_or(false, |last_written| last_written >= block_number) {
+			return
+		}
+		for (index, record) in (length..).zip(&records) {
+			self.storage.set(STORAGE_PREFIX, &history_key(kind, eth_address, index), record);
+		}
+		let length = length + records.len() as u64;
+		self.storage.set(STORAGE_PREFIX, &history_length_key(kind, eth_address), &(length, block_number).encode());
+	}
+}
+
+fn last_block<S: OffchainStorage>(storage: &S) -> Option<(u64, Vec<u8>)> {
+	storage.get(STORAGE_PREFIX, &last_block_key())
+		.and_then(|value| Decode::decode(&mut &value[..]).ok())
+}
+
+/// Length of the `kind` log of `eth_address` and the number of the block
+/// last written to it.
+fn history_length<S: OffchainStorage>(storage: &S, kind: HistoryKind, eth_address: &[u8]) -> (u64, Option<u64>) {
+	storage.get(STORAGE_PREFIX, &history_length_key(kind, eth_address))
+		.and_then(|value| <(u64, u64)>::decode(&mut &value[..]).ok())
+		.map_or((0, None), |(length, block_number)| (length, Some(block_number)))
+}
+
+/// A page of an address's history log, oldest record first.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryPage<Record> {
+	/// Number of records in the log as of `lastBlock`.
+	pub total: u64,
+	/// Number of the last finalized block the history was written for.
+	pub last_block: u64,
+	pub records: Vec<HistoryEntry<Record>>,
+}
+
+/// A record of a history log with its position in the log.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry<Record> {
+	pub index: u64,
+	#[serde(flatten)]
+	pub record: Record,
+}
+
+/// An upload or deletion of a user's file.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileHistory {
+	pub block_number: u64,
+	/// `upload` or `delete`.
+	pub action: String,
+	pub file_name_hash: Bytes,
+	pub file_size_bytes: NumberOrHex,
+	pub file_contents_hash: Bytes,
+	pub gateway_eth_address: Bytes,
+	/// The filenode that reported the upload or deletion.
+	pub filenode_eth_address: Bytes,
+	pub file_storage_class: u8,
+}
+
+impl From<FileHistoryRecord> for FileHistory {
+	fn from(record: FileHistoryRecord) -> Self {
+		let action = match record.action {
+			FileAction::Upload => "upload",
+			FileAction::Delete => "delete",
+		};
+		FileHistory {
+			block_number: record.block_number,
+			action: action.into(),
+			file_name_hash: record.file_name_hash.into(),
+			file_size_bytes: record.file_size_bytes.into(),
+			file_contents_hash: record.file_contents_hash.into(),
+			gateway_eth_address: record.gateway_eth_address.into(),
+			filenode_eth_address: record.filenode_eth_address.into(),
+			file_storage_class: record.file_storage_class,
+		}
+	}
+}
+
+/// A balance movement involving an address.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LedgerHistory {
+	pub block_number: u64,
+	/// Name of the call, e.g. `transfer` or `startStaking`.
+	pub action: String,
+	/// Debited address, `null` for funds leaving the locked funds.
+	pub from: Option<Bytes>,
+	/// Credited address, `null` for funds returning to the locked funds.
+	pub to: Option<Bytes>,
+	pub value: NumberOrHex,
+	/// Balance of the queried address after the movement.
+	pub balance: NumberOrHex,
+}
+
+impl From<LedgerHistoryRecord> for LedgerHistory {
+	fn from(record: LedgerHistoryRecord) -> Self {
+		let action = match record.action {
+			LedgerAction::Deposit => "deposit",
+			LedgerAction::Withdraw => "withdraw",
+			LedgerAction::Transfer => "transfer",
+			LedgerAction::StartStaking => "startStaking",
+			LedgerAction::CancelStaking => "cancelStaking",
+			LedgerAction::EndStaking => "endStaking",
+			LedgerAction::RewardStaking => "rewardStaking",
+		};
+		LedgerHistory {
+			block_number: record.block_number,
+			action: action.into(),
+			from: record.from.map(Into::into),
+			to: record.to.map(Into::into),
+			value: record.value.into(),
+			balance: record.balance.into(),
+		}
+	}
+}
+
+/// ColdStack history RPC methods.
+#[rpc]
+pub trait ColdStackHistoryApi {
+	/// Returns the file `file_name_hash` of `user_eth_address` as of its last
+	/// upload, or `null` if it was deleted or never uploaded.
+	#[rpc(name = "coldstack_fileInfo")]
+	fn file_info(&self, user_eth_address: Bytes, file_name_hash: Bytes) -> Result<Option<File>>;
+
+	/// Returns up to `limit` records of the file history of
+	/// `user_eth_address`, starting with record `start` (0 if omitted).
+	#[rpc(name = "coldstack_fileHistory")]
+	fn file_history(
+		&self,
+		user_eth_address: Bytes,
+		start: Option<u64>,
+		limit: Option<u32>,
+	) -> Result<HistoryPage<FileHistory>>;
+
+	/// Returns up to `limit` records of the ledger history of `eth_address`,
+	/// starting with record `start` (0 if omitted).
+	#[rpc(name = "coldstack_ledgerHistory")]
+	fn ledger_history(
+		&self,
+		eth_address: Bytes,
+		start: Option<u64>,
+		limit: Option<u32>,
+	) -> Result<HistoryPage<LedgerHistory>>;
+}
+
+/// Implementation of the ColdStack history RPC api.
+///
+/// History is read from `storage`, the node's offchain database, where the
+/// node writes it when run with `--coldstack-history`.
+pub struct ColdStackHistory<S> {
+	storage: S,
+}
+
+impl<S> ColdStackHistory<S> {
+	/// Create new `ColdStackHistory` reading from `storage`.
+	pub fn new(storage: S) -> Self {
+		Self { storage }
+	}
+}
+
+impl<S: OffchainStorage> ColdStackHistory<S> {
+	/// Number of the last block written, failing if the node does not write
+	/// history.
+	fn last_block(&self) -> Result<u64> {
+		last_block(&self.storage).map(|(number, _)| number).ok_or_else(|| rpc_error(
+			Error::HistoryUnavailable,
+			"History is not written on this node; \
+			run it with `--coldstack-history` and `--pruning archive`.",
+			"no block written",
+		))
+	}
+
+	fn page<Record: Decode, Entry: From<Record>>(
+		&self,
+		kind: HistoryKind,
+		eth_address: Bytes,
+		start: Option<u64>,
+		limit: Option<u32>,
+	) -> Result<HistoryPage<Entry>> {
+		check_eth_address(&eth_address)?;
+		let start = start.unwrap_or(0);
+		let limit = limit.unwrap_or(DEFAULT_HISTORY_PAGE).min(MAX_HISTORY_PAGE);
+
+		let last_block = self.last_block()?;
+		let (total, _) = history_length(&self.storage, kind, &eth_address);
+
+		let records = (start..total.min(start.saturating_add(limit as u64)))
+			.map(|index| {
+				let key = history_key(kind, &eth_address, index);
+				let value = self.storage.get(STORAGE_PREFIX, &key).ok_or_else(|| rpc_error(
+					Error::HistoryUnavailable,
+					"History record is missing from the offchain database.",
+					index,
+				))?;
+				let record = Record::decode(&mut &value[..]).map_err(|e| rpc_error(
+					Error::HistoryUnavailable,
+					"Unable to decode history record.",
+					e,
+				))?;
+				Ok(HistoryEntry { index, record: record.into() })
+			})
+			.collect::<Result<Vec<_>>>()?;
+
+		Ok(HistoryPage { total, last_block, records })
+	}
+}
+
+impl<S: OffchainStorage + 'static> ColdStackHistoryApi for ColdStackHistory<S> {
+	fn file_info(&self, user_eth_address: Bytes, file_name_hash: Bytes) -> Result<Option<File>> {
+		check_eth_address(&user_eth_address)?;
+		self.last_block()?;
+		let value = match self.storage.get(STORAGE_PREFIX, &file_key(&user_eth_address, &file_name_hash)) {
+			Some(value) => value,
+			None => return Ok(None),
+		};
+		let file = FileInfo::decode(&mut &value[..]).map_err(|e| rpc_error(
+			Error::HistoryUnavailable,
+			"Unable to decode file.",
+			e,
+		))?;
+		Ok(Some(file.into()))
+	}
+
+	fn file_history(
+		&self,
+		user_eth_address: Bytes,
+		start: Option<u64>,
+		limit: Option<u32>,
+	) -> Result<HistoryPage<FileHistory>> {
+		self.page::<FileHistoryRecord, _>(HistoryKind::Files, user_eth_address, start, limit)
+	}
+
+	fn ledger_history(
+		&self,
+		eth_address: Bytes,
+		start: Option<u64>,
+		limit: Option<u32>,
+	) -> Result<HistoryPage<LedgerHistory>> {
+		self.page::<LedgerHistoryRecord, _>(HistoryKind::Ledger, eth_address, start, limit)
+	}
+}
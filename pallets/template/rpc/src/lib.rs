@@ -0,0 +1,356 @@
+//! RPC interface for the ColdStack pallet.
+
+use std::{convert::TryInto, marker::PhantomData, sync::Arc};
+
+use codec::Codec;
+use jsonrpc_core::{Error as RpcError, ErrorCode, Result};
+use jsonrpc_derive::rpc;
+use pallet_template_proof::{balance_storage_key, verify_balance_proof, BalanceProof};
+use sc_client_api::ProofProvider;
+use serde::{Deserialize, Serialize};
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_core::Bytes;
+use sp_rpc::number::NumberOrHex;
+use sp_runtime::{generic::BlockId, traits::Block as BlockT};
+
+mod events;
+mod history;
+
+pub use events::{
+	BlockEvents, ColdStackEvent, ColdStackEvents, ColdStackEventsApi, EventFilter, TopicBlock,
+	MAX_CATCH_UP, MAX_TOPIC_RANGE,
+};
+pub use history::{
+	file_key, history_key, ColdStackHistory, ColdStackHistoryApi, FileAction, FileHistory,
+	FileHistoryRecord, FileInfo, HistoryEntry, HistoryKind, HistoryPage, HistoryWriter,
+	LedgerAction, LedgerHistory, LedgerHistoryRecord, DEFAULT_HISTORY_PAGE, FILE_PREFIX,
+	HISTORY_PREFIX, MAX_HISTORY_PAGE,
+};
+pub use pallet_template_proof as proof;
+pub use pallet_template_rpc_runtime_api::ColdStackApi as ColdStackRuntimeApi;
+use pallet_template_rpc_runtime_api::{GatewayInfo, Permissions, RequestId, Stats};
+
+/// A registered gateway.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Gateway {
+	pub address: Bytes,
+	pub seed_address: Option<Bytes>,
+	pub storage: u8,
+	pub is_active: bool,
+	/// URL from `NodeURLs`, empty if none was registered.
+	pub node_url: String,
+}
+
+impl From<GatewayInfo> for Gateway {
+	fn from(gateway: GatewayInfo) -> Self {
+		Gateway {
+			address: gateway.address.into(),
+			seed_address: gateway.seed_address.map(Into::into),
+			storage: gateway.storage,
+			is_active: gateway.is_active,
+			node_url: String::from_utf8_lossy(&gateway.node_url).into_owned(),
+		}
+	}
+}
+
+/// A stored file, as of its last upload.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct File {
+	pub file_size_bytes: NumberOrHex,
+	pub file_contents_hash: Bytes,
+	pub gateway_eth_address: Bytes,
+	pub filenode_eth_address: Bytes,
+	pub file_storage_class: u8,
+	/// Number of the block the file was uploaded in.
+	pub uploaded_at: NumberOrHex,
+}
+
+impl From<FileInfo> for File {
+	fn from(file: FileInfo) -> Self {
+		File {
+			file_size_bytes: file.file_size_bytes.into(),
+			file_contents_hash: file.file_contents_hash.into(),
+			gateway_eth_address: file.gateway_eth_address.into(),
+			filenode_eth_address: file.filenode_eth_address.into(),
+			file_storage_class: file.file_storage_class,
+			uploaded_at: file.uploaded_at.into(),
+		}
+	}
+}
+
+/// Global file and issuance totals.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ColdStackStats {
+	pub total_file_count: NumberOrHex,
+	pub total_file_size: NumberOrHex,
+	pub total_issuance: NumberOrHex,
+	pub locked_funds: NumberOrHex,
+}
+
+impl From<Stats> for ColdStackStats {
+	fn from(stats: Stats) -> Self {
+		ColdStackStats {
+			total_file_count: stats.total_file_count.into(),
+			total_file_size: stats.total_file_size.into(),
+			total_issuance: stats.total_issuance.into(),
+			locked_funds: stats.locked_funds.into(),
+		}
+	}
+}
+
+/// Permissions held by an account.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountPermissions {
+	pub is_admin: bool,
+	/// ETH address the account holds file permission for.
+	pub file: Option<Bytes>,
+	/// ETH address the account holds billing permission for.
+	pub billing: Option<Bytes>,
+}
+
+impl From<Permissions> for AccountPermissions {
+	fn from(permissions: Permissions) -> Self {
+		AccountPermissions {
+			is_admin: permissions.is_admin,
+			file: permissions.file.map(Into::into),
+			billing: permissions.billing.map(Into::into),
+		}
+	}
+}
+
+/// ColdStack RPC methods.
+#[rpc]
+pub trait ColdStackApi<BlockHash, AccountId> {
+	/// Returns the `Balances` value of `eth_address` at block `at` (best block
+	/// if omitted), with a storage proof against that block's state root.
+	#[rpc(name = "coldstack_balanceProof")]
+	fn balance_proof(&self, eth_address: Bytes, at: Option<BlockHash>) -> Result<BalanceProof<BlockHash>>;
+
+	/// Returns those of `request_ids` that `filenode_eth_address` already got
+	/// on-chain, i.e. that would be rejected as duplicates if it resubmitted
+	/// them.
+	#[rpc(name = "coldstack_processedRequests")]
+	fn processed_requests(
+		&self,
+		filenode_eth_address: Bytes,
+		request_ids: Vec<Bytes>,
+		at: Option<BlockHash>,
+	) -> Result<Vec<Bytes>>;
+
+	/// Returns the balance of `eth_address`, zero if it has none.
+	#[rpc(name = "coldstack_balanceOf")]
+	fn balance_of(&self, eth_address: Bytes, at: Option<BlockHash>) -> Result<NumberOrHex>;
+
+	/// Returns all registered gateways with their node URLs.
+	#[rpc(name = "coldstack_gateways")]
+	fn gateways(&self, at: Option<BlockHash>) -> Result<Vec<Gateway>>;
+
+	/// Returns global file and issuance totals.
+	#[rpc(name = "coldstack_stats")]
+	fn stats(&self, at: Option<BlockHash>) -> Result<ColdStackStats>;
+
+	/// Returns the account holding file permission for `eth_address`.
+	#[rpc(name = "coldstack_filePermissionOwner")]
+	fn file_permission_owner(&self, eth_address: Bytes, at: Option<BlockHash>) -> Result<Option<AccountId>>;
+
+	/// Returns the account holding billing permission for `eth_address`.
+	#[rpc(name = "coldstack_billingPermissionOwner")]
+	fn billing_permission_owner(&self, eth_address: Bytes, at: Option<BlockHash>) -> Result<Option<AccountId>>;
+
+	/// Returns whether `account` may report uploads.
+	#[rpc(name = "coldstack_canUpload")]
+	fn can_upload(&self, account: AccountId, at: Option<BlockHash>) -> Result<bool>;
+
+	/// Returns the permissions held by `account`.
+	#[rpc(name = "coldstack_permissionsOf")]
+	fn permissions_of(&self, account: AccountId, at: Option<BlockHash>) -> Result<AccountPermissions>;
+}
+
+/// Error type of this RPC api.
+pub enum Error {
+	/// The requested block or its state is not available.
+	UnknownBlock,
+	/// The arguments are malformed.
+	InvalidArguments,
+	/// Building or checking a storage proof failed.
+	ProofError,
+	/// The call into the runtime failed.
+	RuntimeError,
+	/// A history record is missing from the offchain index or malformed.
+	HistoryUnavailable,
+}
+
+impl From<Error> for i64 {
+	fn from(e: Error) -> i64 {
+		match e {
+			Error::UnknownBlock => 1,
+			Error::InvalidArguments => 2,
+			Error::ProofError => 3,
+			Error::RuntimeError => 4,
+			Error::HistoryUnavailable => 5,
+		}
+	}
+}
+
+fn rpc_error(code: Error, message: &str, data: impl std::fmt::Debug) -> RpcError {
+	RpcError {
+		code: ErrorCode::ServerError(code.into()),
+		message: message.into(),
+		data: Some(format!("{:?}", data).into()),
+	}
+}
+
+fn runtime_error(e: impl std::fmt::Debug) -> RpcError {
+	rpc_error(Error::RuntimeError, "Unable to query ColdStack state.", e)
+}
+
+fn check_eth_address(eth_address: &Bytes) -> Result<()> {
+	if eth_address.len() != 20 {
+		return Err(rpc_error(Error::InvalidArguments, "ETH address must be 20 bytes.", eth_address))
+	}
+	Ok(())
+}
+
+/// Implementation of the ColdStack RPC api.
+pub struct ColdStack<C, Block> {
+	client: Arc<C>,
+	_marker: PhantomData<Block>,
+}
+
+impl<C, Block> ColdStack<C, Block> {
+	/// Create new `ColdStack` with the given reference to the client.
+	pub fn new(client: Arc<C>) -> Self {
+		Self { client, _marker: Default::default() }
+	}
+}
+
+impl<C, Block> ColdStack<C, Block>
+where
+	Block: BlockT,
+	C: HeaderBackend<Block>,
+{
+	/// `at`, or the best block if omitted.
+	fn at(&self, at: Option<<Block as BlockT>::Hash>) -> BlockId<Block> {
+		BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash))
+	}
+}
+
+impl<C, Block, AccountId> ColdStackApi<<Block as BlockT>::Hash, AccountId> for ColdStack<C, Block>
+where
+	Block: BlockT,
+	AccountId: Codec,
+	C: HeaderBackend<Block> + ProofProvider<Block> + ProvideRuntimeApi<Block> + Send + Sync + 'static,
+	C::Api: ColdStackRuntimeApi<Block, AccountId>,
+{
+	fn balance_proof(
+		&self,
+		eth_address: Bytes,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<BalanceProof<<Block as BlockT>::Hash>> {
+		check_eth_address(&eth_address)?;
+
+		let at = at.unwrap_or_else(|| self.client.info().best_hash);
+		let header = self.client.header(BlockId::Hash(at))
+			.map_err(|e| rpc_error(Error::UnknownBlock, "Unable to fetch header.", e))?
+			.ok_or_else(|| rpc_error(Error::UnknownBlock, "Block not found.", at))?;
+
+		let key = balance_storage_key(&eth_address);
+		let storage_proof = self.client
+			.read_proof(&BlockId::Hash(at), &mut std::iter::once(key.as_slice()))
+			.map_err(|e| rpc_error(Error::ProofError, "Unable to build storage proof.", e))?;
+
+		// Read the balance back out of the proof itself, so the value we return is
+		// exactly the one a client will be able to verify.
+		let mut proof = BalanceProof {
+			at,
+			eth_address,
+			balance: 0u128.into(),
+			proof: storage_proof.iter_nodes().map(Into::into).collect(),
+		};
+		let balance = match verify_balance_proof(&header, &proof) {
+			Ok(balance) => balance,
+			Err(pallet_template_proof::Error::BalanceMismatch { proven, .. }) => proven,
+			Err(e) => return Err(rpc_error(Error::ProofError, "Generated proof does not verify.", e)),
+		};
+		proof.balance = balance.into();
+
+		Ok(proof)
+	}
+
+	fn processed_requests(
+		&self,
+		filenode_eth_address: Bytes,
+		request_ids: Vec<Bytes>,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<Vec<Bytes>> {
+		check_eth_address(&filenode_eth_address)?;
+		let request_ids = request_ids.into_iter()
+			.map(|id| id.to_vec().try_into())
+			.collect::<std::result::Result<Vec<RequestId>, _>>()
+			.map_err(|id| rpc_error(Error::InvalidArguments, "Request ID must be 16 bytes.", id))?;
+
+		let processed = self.client.runtime_api().processed_requests(&self.at(at), filenode_eth_address.to_vec(), request_ids)
+			.map_err(runtime_error)?;
+
+		Ok(processed.into_iter().map(|id| id.to_vec().into()).collect())
+	}
+
+	fn balance_of(&self, eth_address: Bytes, at: Option<<Block as BlockT>::Hash>) -> Result<NumberOrHex> {
+		check_eth_address(&eth_address)?;
+		let balance = self.client.runtime_api().balance_of(&self.at(at), eth_address.to_vec())
+			.map_err(runtime_error)?;
+		Ok(balance.into())
+	}
+
+	fn gateways(&self, at: Option<<Block as BlockT>::Hash>) -> Result<Vec<Gateway>> {
+		let gateways = self.client.runtime_api().gateways(&self.at(at))
+			.map_err(runtime_error)?;
+		Ok(gateways.into_iter().map(Into::into).collect())
+	}
+
+	fn stats(&self, at: Option<<Block as BlockT>::Hash>) -> Result<ColdStackStats> {
+		let stats = self.client.runtime_api().stats(&self.at(at))
+			.map_err(runtime_error)?;
+		Ok(stats.into())
+	}
+
+	fn file_permission_owner(
+		&self,
+		eth_address: Bytes,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<Option<AccountId>> {
+		check_eth_address(&eth_address)?;
+		self.client.runtime_api().file_permission_owner(&self.at(at), eth_address.to_vec())
+			.map_err(runtime_error)
+	}
+
+	fn billing_permission_owner(
+		&self,
+		eth_address: Bytes,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<Option<AccountId>> {
+		check_eth_address(&eth_address)?;
+		self.client.runtime_api().billing_permission_owner(&self.at(at), eth_address.to_vec())
+			.map_err(runtime_error)
+	}
+
+	fn can_upload(&self, account: AccountId, at: Option<<Block as BlockT>::Hash>) -> Result<bool> {
+		self.client.runtime_api().can_upload(&self.at(at), account)
+			.map_err(runtime_error)
+	}
+
+	fn permissions_of(
+		&self,
+		account: AccountId,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<AccountPermissions> {
+		let permissions = self.client.runtime_api().permissions_of(&self.at(at), account)
+			.map_err(runtime_error)?;
+		Ok(permissions.into())
+	}
+}
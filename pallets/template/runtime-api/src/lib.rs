@@ -0,0 +1,44 @@
+//! Runtime API definition for the ColdStack pallet.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use codec::Codec;
+use sp_std::vec::Vec;
+
+pub use pallet_template::{FileInfo, GatewayInfo, Permissions, RequestId, Stats};
+
+sp_api::decl_runtime_apis! {
+	/// Read-only queries against ColdStack pallet state.
+	pub trait ColdStackApi<AccountId> where AccountId: Codec {
+		/// Those of `request_ids` that `filenode_eth_address` had processed
+		/// within the retention window, i.e. that it must not resubmit.
+		fn processed_requests(filenode_eth_address: Vec<u8>, request_ids: Vec<RequestId>) -> Vec<RequestId>;
+
+		/// Balance of `eth_address`, zero if it has none.
+		fn balance_of(eth_address: Vec<u8>) -> u128;
+
+		/// All registered gateways with their node URLs.
+		fn gateways() -> Vec<GatewayInfo>;
+
+		/// Global file and issuance totals.
+		fn stats() -> Stats;
+
+		/// File `file_name_hash` of `user_eth_address` as of its last upload,
+		/// read from the offchain database of the node answering the call.
+		/// `None` if it was deleted, never uploaded, or the node does not write
+		/// history.
+		fn file_info(user_eth_address: Vec<u8>, file_name_hash: Vec<u8>) -> Option<FileInfo>;
+
+		/// Account holding file permission for `eth_address`.
+		fn file_permission_owner(eth_address: Vec<u8>) -> Option<AccountId>;
+
+		/// Account holding billing permission for `eth_address`.
+		fn billing_permission_owner(eth_address: Vec<u8>) -> Option<AccountId>;
+
+		/// Whether `account` may report uploads.
+		fn can_upload(account: AccountId) -> bool;
+
+		/// Permissions held by `account`.
+		fn permissions_of(account: AccountId) -> Permissions;
+	}
+}
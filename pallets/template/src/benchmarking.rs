@@ -2,18 +2,105 @@
 
 use super::*;
 
+use sp_std::{convert::TryInto, vec, vec::Vec};
 use frame_system::RawOrigin;
 use frame_benchmarking::{benchmarks, whitelisted_caller, impl_benchmark_test_suite};
 #[allow(unused)]
 use crate::Pallet as Template;
 
+fn address(i: u32) -> Vec<u8> {
+	let mut address = vec![0u8; 20];
+	address[..4].copy_from_slice(&i.to_le_bytes());
+	address
+}
+
+fn hash(i: u32) -> Vec<u8> {
+	let mut hash = vec![0u8; 32];
+	hash[..4].copy_from_slice(&i.to_le_bytes());
+	hash
+}
+
+fn request_id(i: u32) -> RequestId {
+	let mut request_id = [0u8; 16];
+	request_id[..4].copy_from_slice(&i.to_le_bytes());
+	request_id
+}
+
+fn assert_last_event<T: Config>(event: Event<T>) {
+	frame_system::Pallet::<T>::assert_last_event(<T as Config>::Event::from(event).into());
+}
+
+fn filenode<T: Config>() -> T::AccountId {
+	let caller: T::AccountId = whitelisted_caller();
+	FilePermissionOwnersByETHAddress::<T>::insert(address(u32::MAX), &caller);
+	FilePermissionOwnersByAccountId::<T>::insert(&caller, address(u32::MAX));
+	caller
+}
+
 benchmarks! {
-	do_something {
-		let s in 0 .. 100;
-		let caller: T::AccountId = whitelisted_caller();
-	}: _(RawOrigin::Signed(caller), s)
+	upload_batch {
+		let n in 1 .. T::MaxBatchSize::get();
+		let caller = filenode::<T>();
+		let records: Vec<_> = (0..n).map(|i| UploadRecord {
+			user_eth_address: address(i),
+			file_name_hash: hash(i),
+			file_size_bytes: 1_000,
+			file_contents_hash: hash(i),
+			gateway_eth_address: address(i),
+			file_storage_class: 0,
+			is_forced: false,
+			request_id: Some(request_id(i)),
+		}).collect();
+	}: _(RawOrigin::Signed(caller), records.try_into().unwrap(), BatchMode::AllOrNothing)
+	verify {
+		assert_eq!(TotalFileCount::<T>::get(), n as u128);
+	}
+
+	download_batch {
+		let n in 1 .. T::MaxBatchSize::get();
+		let caller = filenode::<T>();
+		let records: Vec<_> = (0..n).map(|i| DownloadRecord {
+			user_eth_address: address(i),
+			file_name_hash: hash(i),
+			file_size_bytes: 1_000,
+			file_contents_hash: hash(i),
+			gateway_eth_address: address(i),
+			request_id: Some(request_id(i)),
+		}).collect();
+	}: _(RawOrigin::Signed(caller), records.try_into().unwrap(), BatchMode::AllOrNothing)
+
+	delete_batch {
+		let n in 1 .. T::MaxBatchSize::get();
+		let caller = filenode::<T>();
+		frame_system::Pallet::<T>::set_block_number(1u32.into());
+		// Upload the files first, with request IDs the deletes don't reuse.
+		let uploads: Vec<_> = (0..n).map(|i| UploadRecord {
+			user_eth_address: address(i),
+			file_name_hash: hash(i),
+			file_size_bytes: 1_000,
+			file_contents_hash: hash(i),
+			gateway_eth_address: address(i),
+			file_storage_class: 0,
+			is_forced: false,
+			request_id: Some(request_id(n + i)),
+		}).collect();
+		Template::<T>::upload_batch(
+			RawOrigin::Signed(caller.clone()).into(),
+			uploads.try_into().unwrap(),
+			BatchMode::AllOrNothing,
+		)?;
+		assert_eq!(TotalFileCount::<T>::get(), n as u128);
+		let records: Vec<_> = (0..n).map(|i| DeleteRecord {
+			user_eth_address: address(i),
+			file_name_hash: hash(i),
+			request_id: Some(request_id(i)),
+		}).collect();
+	}: _(RawOrigin::Signed(caller), records.try_into().unwrap(), BatchMode::AllOrNothing)
 	verify {
-		assert_eq!(Something::<T>::get(), Some(s));
+		assert_last_event::<T>(Event::BatchCompleted { succeeded: n, failed: Vec::new() });
+		for i in 0..n {
+			assert!(ProcessedRequests::<T>::contains_key(address(u32::MAX), request_id(i)));
+		}
 	}
 }
 
@@ -8,22 +8,67 @@ mod mock;
 #[cfg(test)]
 mod tests;
 
+#[cfg(feature = "runtime-benchmarks")]
+mod benchmarking;
+
+pub mod weights;
+
+mod signed_extension;
+pub use signed_extension::CheckColdStackPermission;
+
 #[frame_support::pallet]
 pub mod pallet {
   use frame_support::{
     dispatch::DispatchResultWithPostInfo,
     pallet_prelude::*,
+    storage::{bounded_vec::BoundedVec, with_transaction, TransactionOutcome},
     weights::{Pays},
   };
   use frame_system::pallet_prelude::*;
   use codec::{Encode, Decode};
-  use sp_std::vec::Vec;
+  use sp_runtime::traits::{Hash as HashT, IdentifyAccount, Verify, Zero};
+  use sp_std::{fmt::Debug, vec, vec::Vec};
+  use crate::weights::WeightInfo;
 
   #[pallet::config]
   pub trait Config: frame_system::Config {
     type Event: From<Event<Self>> + IsType<<Self as frame_system::Config>::Event>;
+
+    /// Maximum number of records in a single `*_batch` call.
+    #[pallet::constant]
+    type MaxBatchSize: Get<u32>;
+
+    /// Weight information for extrinsics in this pallet.
+    type WeightInfo: WeightInfo;
+
+    /// For how many blocks a client request ID is remembered and rejected
+    /// as a duplicate.
+    #[pallet::constant]
+    type RequestIdRetention: Get<Self::BlockNumber>;
+
+    /// Key a filenode signs unsigned usage reports with.
+    type Public: IdentifyAccount<AccountId = Self::AccountId>
+      + Clone + PartialEq + Eq + Encode + Decode + Debug;
+
+    /// Signature over an unsigned usage report.
+    type Signature: Verify<Signer = Self::Public> + Clone + PartialEq + Eq + Encode + Decode + Debug;
+
+    /// Transaction pool priority and longevity of unsigned usage reports.
+    type UnsignedValidity: UnsignedValidity;
+
+    /// How many ColdStack calls a single account may have included per
+    /// block. Enforced by `CheckColdStackPermission`.
+    #[pallet::constant]
+    type MaxCallsPerBlock: Get<u32>;
+
+    /// Origin that manages permissions and gateways like the admin `Key`,
+    /// and may replace it.
+    type AdminOrigin: EnsureOrigin<Self::Origin>;
   }
 
+  /// Client-supplied ID of a usage report, used to deduplicate resubmissions.
+  pub type RequestId = [u8; 16];
+
   #[pallet::pallet]
   #[pallet::generate_store(pub(super) trait Store)]
   pub struct Pallet<T>(_);
@@ -115,6 +160,166 @@ pub mod pallet {
 	is_active: bool
   }
 
+  /// A gateway together with its `NodeURLs` entry.
+  #[derive(Clone, PartialEq, Eq, Encode, Decode, RuntimeDebug)]
+  #[cfg_attr(feature = "std", derive(serde::Serialize, serde::Deserialize))]
+  #[cfg_attr(feature = "std", serde(rename_all = "camelCase", deny_unknown_fields))]
+  pub struct GatewayInfo {
+    pub address: Vec<u8>,
+    pub seed_address: Option<Vec<u8>>,
+    pub storage: u8,
+    pub is_active: bool,
+    pub node_url: Vec<u8>,
+  }
+
+  /// Global ColdStack totals.
+  #[derive(Clone, Default, PartialEq, Eq, Encode, Decode, RuntimeDebug)]
+  pub struct Stats {
+    pub total_file_count: u128,
+    pub total_file_size: u128,
+    pub total_issuance: u128,
+    pub locked_funds: u128,
+  }
+
+  /// A stored file, as of its last upload.
+  #[derive(Clone, PartialEq, Eq, Encode, Decode, RuntimeDebug)]
+  pub struct FileInfo {
+    pub file_size_bytes: u128,
+    pub file_contents_hash: Vec<u8>,
+    pub gateway_eth_address: Vec<u8>,
+    pub filenode_eth_address: Vec<u8>,
+    pub file_storage_class: u8,
+    pub uploaded_at: u64,
+  }
+
+  /// Prefix of the offchain database keys stored files are written under.
+  pub const FILE_PREFIX: &[u8] = b"coldstack::file";
+
+  /// Offchain database key of the `FileInfo` of file `file_name_hash` of
+  /// `user_eth_address`: the SCALE encoding of
+  /// `(FILE_PREFIX, user_eth_address, file_name_hash)`.
+  pub fn file_key(user_eth_address: &[u8], file_name_hash: &[u8]) -> Vec<u8> {
+    (FILE_PREFIX, user_eth_address, file_name_hash).encode()
+  }
+
+  /// Permissions held by an account.
+  #[derive(Clone, Default, PartialEq, Eq, Encode, Decode, RuntimeDebug)]
+  pub struct Permissions {
+    pub is_admin: bool,
+    /// ETH address the account holds file permission for.
+    pub file: Option<Vec<u8>>,
+    /// ETH address the account holds billing permission for.
+    pub billing: Option<Vec<u8>>,
+  }
+
+  /// A file or billing permission set up at genesis.
+  #[derive(Clone, PartialEq, Eq, Encode, Decode, RuntimeDebug)]
+  #[cfg_attr(feature = "std", derive(serde::Serialize, serde::Deserialize))]
+  #[cfg_attr(feature = "std", serde(rename_all = "camelCase", deny_unknown_fields))]
+  pub struct GenesisPermission<AccountId> {
+    pub eth_address: Vec<u8>,
+    pub account_id: AccountId,
+    pub node_url: Vec<u8>,
+  }
+
+  /// Arguments of a single `upload`, as passed to `upload_batch`.
+  #[derive(Clone, PartialEq, Eq, Encode, Decode, RuntimeDebug)]
+  pub struct UploadRecord {
+    pub user_eth_address: Vec<u8>,
+    pub file_name_hash: Vec<u8>,
+    pub file_size_bytes: u128,
+    pub file_contents_hash: Vec<u8>,
+    pub gateway_eth_address: Vec<u8>,
+    pub file_storage_class: u8,
+    pub is_forced: bool,
+    pub request_id: Option<RequestId>,
+  }
+
+  /// Arguments of a single `download`, as passed to `download_batch`.
+  #[derive(Clone, PartialEq, Eq, Encode, Decode, RuntimeDebug)]
+  pub struct DownloadRecord {
+    pub user_eth_address: Vec<u8>,
+    pub file_name_hash: Vec<u8>,
+    pub file_size_bytes: u128,
+    pub file_contents_hash: Vec<u8>,
+    pub gateway_eth_address: Vec<u8>,
+    pub request_id: Option<RequestId>,
+  }
+
+  /// Arguments of a single `delete`, as passed to `delete_batch`.
+  #[derive(Clone, PartialEq, Eq, Encode, Decode, RuntimeDebug)]
+  pub struct DeleteRecord {
+    pub user_eth_address: Vec<u8>,
+    pub file_name_hash: Vec<u8>,
+    pub request_id: Option<RequestId>,
+  }
+
+  /// How a batch call treats records that fail.
+  #[derive(Clone, Copy, PartialEq, Eq, Encode, Decode, RuntimeDebug)]
+  pub enum BatchMode {
+    /// Stop at the first failing record and roll back the whole batch.
+    AllOrNothing,
+    /// Apply every valid record and report the failing ones.
+    BestEffort,
+  }
+
+  /// A usage report submitted through `submit_usage_unsigned`.
+  #[derive(Clone, PartialEq, Eq, Encode, Decode, RuntimeDebug)]
+  pub enum Usage {
+    Upload(UploadRecord),
+    Download(DownloadRecord),
+    Delete(DeleteRecord),
+  }
+
+  /// Kind of a `Usage`, used to pick its transaction pool parameters.
+  #[derive(Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+  pub enum UsageKind {
+    Upload,
+    Download,
+    Delete,
+  }
+
+  impl Usage {
+    pub fn kind(&self) -> UsageKind {
+      match self {
+        Usage::Upload(_) => UsageKind::Upload,
+        Usage::Download(_) => UsageKind::Download,
+        Usage::Delete(_) => UsageKind::Delete,
+      }
+    }
+  }
+
+  /// A usage report signed by a filenode's permissioned key.
+  ///
+  /// The filenode signs `usage_signing_payload` of it, which ties the
+  /// signature to one chain. `sequence` must grow with every payload of the
+  /// same key; the transaction pool holds back payloads until the ones before
+  /// them are in.
+  #[derive(Clone, PartialEq, Eq, Encode, Decode, RuntimeDebug)]
+  pub struct UsagePayload<Public> {
+    pub public: Public,
+    pub sequence: u64,
+    pub usage: Usage,
+  }
+
+  /// Domain tag prepended to usage payloads before signing.
+  pub const USAGE_SIGNING_DOMAIN: &[u8] = b"coldstack::usage";
+
+  /// What the filenode signs for `payload` on the chain with `genesis_hash`:
+  /// the SCALE encoding of `(USAGE_SIGNING_DOMAIN, genesis_hash, payload)`.
+  pub fn usage_signing_payload<Hash: Encode, Public: Encode>(
+    genesis_hash: &Hash,
+    payload: &UsagePayload<Public>,
+  ) -> Vec<u8> {
+    (USAGE_SIGNING_DOMAIN, genesis_hash, payload).encode()
+  }
+
+  /// Transaction pool parameters of unsigned usage reports, per kind.
+  pub trait UnsignedValidity {
+    fn priority(kind: UsageKind) -> TransactionPriority;
+    fn longevity(kind: UsageKind) -> TransactionLongevity;
+  }
+
   /*
     Map gateway node address -> gateway
   */
@@ -127,10 +332,79 @@ pub mod pallet {
     ValueQuery
   >;
 
+  /*
+    Map filenode ETH address, request id -> block it was processed in
+  */
+  #[pallet::storage]
+  pub type ProcessedRequests<T: Config> = StorageDoubleMap<
+    _,
+    Blake2_128Concat,
+    Vec<u8>,
+    Blake2_128Concat,
+    RequestId,
+    T::BlockNumber,
+    OptionQuery
+  >;
+
+  /*
+    Map block number -> (filenode ETH address, request id) to forget at that block
+  */
+  #[pallet::storage]
+  pub type RequestExpiry<T: Config> = StorageDoubleMap<
+    _,
+    Twox64Concat,
+    T::BlockNumber,
+    Blake2_128Concat,
+    (Vec<u8>, RequestId),
+    (),
+    OptionQuery
+  >;
+
+  /*
+    Map filenode account -> sequence of its last unsigned usage report
+  */
+  #[pallet::storage]
+  pub type UsageSequence<T: Config> = StorageMap<
+    _,
+    Blake2_128Concat,
+    T::AccountId,
+    u64,
+    ValueQuery
+  >;
+
+  /*
+    Map account -> (block, number of ColdStack calls it had included in it)
+  */
+  #[pallet::storage]
+  pub type CallsInBlock<T: Config> = StorageMap<
+    _,
+    Blake2_128Concat,
+    T::AccountId,
+    (T::BlockNumber, u32),
+    ValueQuery
+  >;
+
   #[pallet::genesis_config]
   pub struct GenesisConfig<T: Config> {
     pub key: T::AccountId,
     pub total_issuance: u128,
+    /// Part of `total_issuance` not held by any ETH address. Defaults to
+    /// what `balances` leave of it.
+    #[cfg_attr(feature = "std", serde(default))]
+    pub locked_funds: Option<u128>,
+    /// Initial balances of ETH addresses.
+    #[cfg_attr(feature = "std", serde(default))]
+    pub balances: Vec<(Vec<u8>, u128)>,
+    #[cfg_attr(feature = "std", serde(default))]
+    pub file_permissions: Vec<GenesisPermission<T::AccountId>>,
+    #[cfg_attr(feature = "std", serde(default))]
+    pub billing_permissions: Vec<GenesisPermission<T::AccountId>>,
+    #[cfg_attr(feature = "std", serde(default))]
+    pub gateways: Vec<GatewayInfo>,
+    #[cfg_attr(feature = "std", serde(default))]
+    pub total_file_count: u128,
+    #[cfg_attr(feature = "std", serde(default))]
+    pub total_file_size: u128,
   }
 
   #[cfg(feature = "std")]
@@ -139,67 +413,298 @@ pub mod pallet {
       Self {
         key: Default::default(),
         total_issuance: Default::default(),
+        locked_funds: None,
+        balances: Vec::new(),
+        file_permissions: Vec::new(),
+        billing_permissions: Vec::new(),
+        gateways: Vec::new(),
+        total_file_count: 0,
+        total_file_size: 0,
+      }
+    }
+  }
+
+  #[cfg(feature = "std")]
+  impl<T: Config> GenesisConfig<T> {
+    /// Checks that the config describes a state the calls could have reached:
+    /// well-formed and unique addresses, and `balances` plus `locked_funds`
+    /// adding up to `total_issuance`.
+    pub fn validate(&self) -> Result<(), String> {
+      use sp_std::collections::btree_set::BTreeSet;
+
+      fn check_len(what: &str, value: &[u8], len: usize) -> Result<(), String> {
+        if value.len() == len {
+          Ok(())
+        } else {
+          Err(format!("{} 0x{} is {} bytes long, expected {}", what, hex(value), value.len(), len))
+        }
+      }
+      fn hex(value: &[u8]) -> String {
+        value.iter().map(|b| format!("{:02x}", b)).collect()
+      }
+
+      let mut held = 0u128;
+      let mut addresses = BTreeSet::new();
+      for (eth_address, balance) in &self.balances {
+        check_len("Balance address", eth_address, 20)?;
+        if !addresses.insert(eth_address) {
+          return Err(format!("Duplicate balance of 0x{}", hex(eth_address)))
+        }
+        held = held.checked_add(*balance).ok_or("Genesis balances overflow")?;
+      }
+      let locked_funds = match self.locked_funds {
+        Some(locked_funds) => locked_funds,
+        None => self.total_issuance.checked_sub(held).ok_or_else(|| format!(
+          "Genesis balances total {} but total issuance is {}", held, self.total_issuance,
+        ))?,
+      };
+      if held.checked_add(locked_funds) != Some(self.total_issuance) {
+        return Err(format!(
+          "Genesis balances ({}) plus locked funds ({}) must equal total issuance ({})",
+          held, locked_funds, self.total_issuance,
+        ))
+      }
+
+      for (kind, permissions) in &[("File", &self.file_permissions), ("Billing", &self.billing_permissions)] {
+        let mut addresses = BTreeSet::new();
+        let mut accounts = BTreeSet::new();
+        for permission in permissions.iter() {
+          check_len(&format!("{} permission address", kind), &permission.eth_address, 20)?;
+          if !addresses.insert(&permission.eth_address) {
+            return Err(format!("Duplicate {} permission of 0x{}", kind.to_lowercase(), hex(&permission.eth_address)))
+          }
+          if !accounts.insert(&permission.account_id) {
+            return Err(format!("Duplicate {} permission owner {:?}", kind.to_lowercase(), permission.account_id))
+          }
+        }
+      }
+
+      let mut addresses = BTreeSet::new();
+      for gateway in &self.gateways {
+        check_len("Gateway address", &gateway.address, 20)?;
+        if let Some(seed_address) = &gateway.seed_address {
+          check_len("Gateway seed address", seed_address, 20)?;
+        }
+        if !addresses.insert(&gateway.address) {
+          return Err(format!("Duplicate gateway 0x{}", hex(&gateway.address)))
+        }
       }
+      Ok(())
     }
   }
 
   #[pallet::genesis_build]
   impl<T: Config> GenesisBuild<T> for GenesisConfig<T> {
     fn build(&self) {
+      if let Err(e) = self.validate() {
+        panic!("Invalid ColdStack genesis: {}", e);
+      }
+
       <Key<T>>::put(&self.key);
-      <TotalFileCount<T>>::put(0);
-      <TotalFileSize<T>>::put(0);
       <TotalIssuance<T>>::put(&self.total_issuance);
-      <LockedFunds<T>>::put(&self.total_issuance);
+
+      let held = self.balances.iter().fold(0u128, |held, (_, balance)| held + balance);
+      <LockedFunds<T>>::put(self.locked_funds.unwrap_or(self.total_issuance - held));
+      for (eth_address, balance) in &self.balances {
+        Balances::<T>::insert(eth_address, balance);
+      }
+
+      for permission in &self.file_permissions {
+        FilePermissionOwnersByETHAddress::<T>::insert(&permission.eth_address, &permission.account_id);
+        FilePermissionOwnersByAccountId::<T>::insert(&permission.account_id, &permission.eth_address);
+        NodeURLs::<T>::insert(&permission.eth_address, &permission.node_url);
+      }
+      for permission in &self.billing_permissions {
+        BillingPermissionOwnersByETHAddress::<T>::insert(&permission.eth_address, &permission.account_id);
+        BillingPermissionOwnersByAccountId::<T>::insert(&permission.account_id, &permission.eth_address);
+        NodeURLs::<T>::insert(&permission.eth_address, &permission.node_url);
+      }
+
+      for gateway in &self.gateways {
+        Gateways::<T>::insert(&gateway.address, Gateway {
+          address: gateway.address.clone(),
+          seed_address: gateway.seed_address.clone(),
+          storage: gateway.storage,
+          is_active: gateway.is_active,
+        });
+        NodeURLs::<T>::insert(&gateway.address, &gateway.node_url);
+      }
+
+      <TotalFileCount<T>>::put(self.total_file_count);
+      <TotalFileSize<T>>::put(self.total_file_size);
     }
   }
 
+  /// Version of the `Event` layout, exposed as the `EventSchemaVersion`
+  /// constant. Bumped whenever a variant gains, loses or reorders fields.
+  /// See docs/events.md for the layout of each version.
+  pub const EVENT_SCHEMA_VERSION: u32 = 1;
+
   #[pallet::event]
   #[pallet::metadata(T::AccountId = "AccountId")]
-  #[pallet::generate_deposit(pub(super) fn deposit_event)]
   pub enum Event<T: Config> {
-    Upload(
-      /*user_eth_address*/     Vec<u8>,
-      /*file_name_hash:*/      Vec<u8>,
-      /*file_size_bytes:*/     u128,
-      /*file_contents_hash:*/  Vec<u8>,
-      /*gateway_eth_address:*/ Vec<u8>,
-      /*filenode_eth_address:*/Vec<u8>,
-	  /*file_storage_class:*/u8,
-	  /*is_forced:*/bool,
-    ),
-	ChangeStatusGateway(
-	/*gateway_eth_address*/				Vec<u8>,
-	/*is_active*/				bool
-	),
-	DeleteGateway(
-		/*gateway_eth_address*/				Vec<u8>,
-	),
-    Download(
-      /*user_eth_address*/     Vec<u8>,
-      /*file_name_hash:*/      Vec<u8>,
-      /*file_size_bytes:*/     u128,
-      /*file_contents_hash:*/  Vec<u8>,
-      /*gateway_eth_address:*/ Vec<u8>,
-      /*filenode_eth_address:*/Vec<u8>,
-    ),
-    Delete(
-      Vec<u8>, /* user_eth_address */
-      Vec<u8>, /* file_name_hash */
-      Vec<u8>, /* filenode_eth_address */
-    ),
-    Deposit(Vec<u8>, u128),
-    Withdraw(Vec<u8>, u128),
-    Transfer(Vec<u8>, Vec<u8>, u128),
-    StartStaking(Vec<u8>, Vec<u8>, u128),
-    CancelStaking(Vec<u8>, Vec<u8>, u128),
-    EndStaking(Vec<u8>, Vec<u8>, u128),
-    RewardStaking(Vec<u8>, Vec<u8>, u128),
-    FilePermissionGranted(Vec<u8>, T::AccountId, Vec<u8>),
-    FilePermissionRevoked(Vec<u8>, T::AccountId),
-    BillingPermissionGranted(Vec<u8>, T::AccountId, Vec<u8>),
-    BillingPermissionRevoked(Vec<u8>, T::AccountId),
-    GatewayNodeRegistered(Vec<u8>, Option<Vec<u8>>, u8, Vec<u8>, bool),
+    /// A file was uploaded. \[user_eth_address, file_name_hash, file_size_bytes,
+    /// file_contents_hash, gateway_eth_address, filenode_eth_address,
+    /// file_storage_class, is_forced\]
+    Upload {
+      user_eth_address: Vec<u8>,
+      file_name_hash: Vec<u8>,
+      file_size_bytes: u128,
+      file_contents_hash: Vec<u8>,
+      gateway_eth_address: Vec<u8>,
+      filenode_eth_address: Vec<u8>,
+      file_storage_class: u8,
+      is_forced: bool,
+    },
+    /// A gateway was enabled or disabled. \[gateway_eth_address, is_active\]
+    ChangeStatusGateway {
+      gateway_eth_address: Vec<u8>,
+      is_active: bool,
+    },
+    /// A gateway was removed. \[gateway_eth_address\]
+    DeleteGateway {
+      gateway_eth_address: Vec<u8>,
+    },
+    /// A file was downloaded. \[user_eth_address, file_name_hash,
+    /// file_size_bytes, file_contents_hash, gateway_eth_address,
+    /// filenode_eth_address\]
+    Download {
+      user_eth_address: Vec<u8>,
+      file_name_hash: Vec<u8>,
+      file_size_bytes: u128,
+      file_contents_hash: Vec<u8>,
+      gateway_eth_address: Vec<u8>,
+      filenode_eth_address: Vec<u8>,
+    },
+    /// A file was deleted. \[user_eth_address, file_name_hash,
+    /// filenode_eth_address\]
+    Delete {
+      user_eth_address: Vec<u8>,
+      file_name_hash: Vec<u8>,
+      filenode_eth_address: Vec<u8>,
+    },
+    /// Tokens were released from locked funds. \[eth_address, value\]
+    Deposit { eth_address: Vec<u8>, value: u128 },
+    /// Tokens were returned to locked funds. \[eth_address, value\]
+    Withdraw { eth_address: Vec<u8>, value: u128 },
+    /// \[from, to, value\]
+    Transfer { from: Vec<u8>, to: Vec<u8>, value: u128 },
+    /// \[from, to, value\]
+    StartStaking { from: Vec<u8>, to: Vec<u8>, value: u128 },
+    /// \[from, to, value\]
+    CancelStaking { from: Vec<u8>, to: Vec<u8>, value: u128 },
+    /// \[from, to, value\]
+    EndStaking { from: Vec<u8>, to: Vec<u8>, value: u128 },
+    /// Staking reward paid from locked funds. \[from, eth_address, value\]
+    RewardStaking { from: Vec<u8>, eth_address: Vec<u8>, value: u128 },
+    /// \[eth_address, account_id, node_url\]
+    FilePermissionGranted { eth_address: Vec<u8>, account_id: T::AccountId, node_url: Vec<u8> },
+    /// \[eth_address, account_id\]
+    FilePermissionRevoked { eth_address: Vec<u8>, account_id: T::AccountId },
+    /// \[eth_address, account_id, node_url\]
+    BillingPermissionGranted { eth_address: Vec<u8>, account_id: T::AccountId, node_url: Vec<u8> },
+    /// \[eth_address, account_id\]
+    BillingPermissionRevoked { eth_address: Vec<u8>, account_id: T::AccountId },
+    /// \[eth_address, seed_eth_address, storage, node_url, is_active\]
+    GatewayNodeRegistered {
+      eth_address: Vec<u8>,
+      seed_eth_address: Option<Vec<u8>>,
+      storage: u8,
+      node_url: Vec<u8>,
+      is_active: bool,
+    },
+    /// A batch was applied. \[succeeded, failed: \[(index, error)\]\]
+    BatchCompleted { succeeded: u32, failed: Vec<(u32, DispatchError)> },
+    /// An all-or-nothing batch was rolled back. \[index, error\]
+    BatchInterrupted { index: u32, error: DispatchError },
+    /// An unsigned usage report was rejected; its sequence number is spent.
+    /// \[filenode_eth_address, sequence, error\]
+    UsageRejected { filenode_eth_address: Vec<u8>, sequence: u64, error: DispatchError },
+    /// `AdminOrigin` replaced the admin key. \[old_key, new_key\]
+    KeyChanged { old_key: T::AccountId, new_key: T::AccountId },
+  }
+
+  #[pallet::extra_constants]
+  impl<T: Config> Pallet<T> {
+    /// Version of the event layout. See docs/events.md.
+    #[allow(non_snake_case)]
+    fn EventSchemaVersion() -> u32 {
+      EVENT_SCHEMA_VERSION
+    }
+  }
+
+  /// Role an ETH address plays in an event.
+  #[derive(Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+  pub enum AddressRole {
+    /// A user whose files or balance the event concerns.
+    User,
+    Gateway,
+    Filenode,
+    /// A billing service, holding billing permission.
+    Billing,
+  }
+
+  impl<T: Config> Event<T> {
+    /// ETH addresses this event involves, with the role each plays.
+    pub fn eth_addresses(&self) -> Vec<(AddressRole, &[u8])> {
+      use AddressRole::*;
+      match self {
+        Event::Upload { user_eth_address, gateway_eth_address, filenode_eth_address, .. }
+        | Event::Download { user_eth_address, gateway_eth_address, filenode_eth_address, .. } => vec![
+          (User, &user_eth_address[..]),
+          (Gateway, &gateway_eth_address[..]),
+          (Filenode, &filenode_eth_address[..]),
+        ],
+        Event::Delete { user_eth_address, filenode_eth_address, .. } =>
+          vec![(User, &user_eth_address[..]), (Filenode, &filenode_eth_address[..])],
+        Event::Deposit { eth_address, .. } | Event::Withdraw { eth_address, .. } =>
+          vec![(User, &eth_address[..])],
+        Event::Transfer { from, to, .. }
+        | Event::StartStaking { from, to, .. }
+        | Event::CancelStaking { from, to, .. }
+        | Event::EndStaking { from, to, .. }
+        | Event::RewardStaking { from, eth_address: to, .. } => vec![(User, &from[..]), (User, &to[..])],
+        Event::ChangeStatusGateway { gateway_eth_address, .. }
+        | Event::DeleteGateway { gateway_eth_address } => vec![(Gateway, &gateway_eth_address[..])],
+        Event::GatewayNodeRegistered { eth_address, seed_eth_address, .. } => {
+          let mut addresses = vec![(Gateway, &eth_address[..])];
+          if let Some(seed) = seed_eth_address {
+            addresses.push((Gateway, &seed[..]));
+          }
+          addresses
+        }
+        Event::FilePermissionGranted { eth_address: filenode_eth_address, .. }
+        | Event::FilePermissionRevoked { eth_address: filenode_eth_address, .. }
+        | Event::UsageRejected { filenode_eth_address, .. } => vec![(Filenode, &filenode_eth_address[..])],
+        Event::BillingPermissionGranted { eth_address, .. }
+        | Event::BillingPermissionRevoked { eth_address, .. } => vec![(Billing, &eth_address[..])],
+        Event::BatchCompleted { .. }
+        | Event::BatchInterrupted { .. }
+        | Event::KeyChanged { .. } => Vec::new(),
+      }
+    }
+
+    /// Name hash of the file this event concerns, if any.
+    pub fn file_name_hash(&self) -> Option<&[u8]> {
+      match self {
+        Event::Upload { file_name_hash, .. }
+        | Event::Download { file_name_hash, .. }
+        | Event::Delete { file_name_hash, .. } => Some(&file_name_hash[..]),
+        _ => None,
+      }
+    }
+
+    /// Topics this event is deposited with: one per ETH address it
+    /// involves, whatever the role, and one for its file name hash.
+    pub fn topics(&self) -> Vec<T::Hash> {
+      let mut topics: Vec<T::Hash> = self.eth_addresses().into_iter()
+        .map(|(_, address)| Pallet::<T>::eth_address_topic(address))
+        .collect();
+      topics.extend(self.file_name_hash().map(Pallet::<T>::file_topic));
+      topics.sort();
+      topics.dedup();
+      topics
+    }
   }
 
   #[pallet::error]
@@ -208,15 +713,28 @@ pub mod pallet {
     Unauthorized,
     InsufficientIssuance,
     InsufficientFunds,
+    /// The request ID was already processed within the retention window.
+    DuplicateRequest,
+    /// The usage report's sequence number was already used.
+    StaleSequence,
   }
 
   #[pallet::hooks]
-  impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {}
+  impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
+    fn on_initialize(n: T::BlockNumber) -> Weight {
+      let mut expired: Weight = 0;
+      for ((filenode_eth_address, request_id), ()) in RequestExpiry::<T>::drain_prefix(n) {
+        ProcessedRequests::<T>::remove(filenode_eth_address, request_id);
+        expired += 1;
+      }
+      T::DbWeight::get().reads_writes(expired + 1, expired * 2)
+    }
+  }
 
   #[pallet::call]
   impl<T: Config> Pallet<T> {
 
-    #[pallet::weight((0, Pays::No))]
+    #[pallet::weight((T::WeightInfo::upload_batch(1), Pays::No))]
     pub fn upload(origin: OriginFor<T>,
       user_eth_address: Vec<u8>,
       file_name_hash: Vec<u8>,
@@ -227,37 +745,20 @@ pub mod pallet {
       is_forced: bool,
     ) -> DispatchResultWithPostInfo {
       let sender = ensure_signed(origin)?;
-
-      let has_permission =
-        // is admin
-        sender == Self::key()
-        ||
-        FilePermissionOwnersByAccountId::<T>::contains_key(&sender);
+      ensure!(Self::has_file_permission(&sender), Error::<T>::Unauthorized);
 
       let filenode_eth_address = FilePermissionOwnersByAccountId::<T>::get(&sender);
 
-      ensure!(has_permission, Error::<T>::Unauthorized);
-
-      ensure!(user_eth_address.len() == 20, Error::<T>::InvalidArguments);
-      ensure!(gateway_eth_address.len() == 20, Error::<T>::InvalidArguments);
-      ensure!(file_contents_hash.len() == 32, Error::<T>::InvalidArguments);
-      ensure!(file_name_hash.len() == 32, Error::<T>::InvalidArguments);
-	  ensure!(u8::MIN <= file_storage_class && file_storage_class <= u8::MAX, Error::<T>::InvalidArguments);
-	  ensure!(is_forced == false || is_forced == true, Error::<T>::InvalidArguments);
-
-	  <TotalFileCount<T>>::put(Self::total_file_count() + 1);
-      <TotalFileSize<T>>::put(Self::total_file_size() + file_size_bytes);
-
-      Self::deposit_event(Event::Upload(
+      Self::do_upload(&filenode_eth_address, UploadRecord {
         user_eth_address,
         file_name_hash,
         file_size_bytes,
         file_contents_hash,
         gateway_eth_address,
-        filenode_eth_address,
         file_storage_class,
         is_forced,
-      ));
+        request_id: None,
+      })?;
 
       Ok(().into())
     }
@@ -287,10 +788,10 @@ pub mod pallet {
 		  };
 		  Gateways::<T>::remove(gateway_eth_address.clone());
 		  Gateways::<T>::insert(gateway_eth_address.clone(), &g);
-		  Self::deposit_event(Event::ChangeStatusGateway(
+		  Self::deposit_event(Event::ChangeStatusGateway {
 			  gateway_eth_address,
 			  is_active
-		  ));
+		  });
 
 		  Ok(().into())
 	  }
@@ -311,15 +812,15 @@ pub mod pallet {
 
 		  Gateways::<T>::remove(gateway_eth_address.clone());
 		  NodeURLs::<T>::remove(gateway_eth_address.clone());
-		  Self::deposit_event(Event::DeleteGateway(
+		  Self::deposit_event(Event::DeleteGateway {
 			  gateway_eth_address
-		  ));
+		  });
 
 		  Ok(().into())
 	  }
 
 
-    #[pallet::weight((0, Pays::No))]
+    #[pallet::weight((T::WeightInfo::download_batch(1), Pays::No))]
     pub fn download(origin: OriginFor<T>,
       user_eth_address: Vec<u8>,
       file_name_hash: Vec<u8>,
@@ -328,60 +829,38 @@ pub mod pallet {
       gateway_eth_address: Vec<u8>,
     ) -> DispatchResultWithPostInfo {
       let sender = ensure_signed(origin)?;
-
-      let has_permission =
-        // is admin
-        sender == Self::key()
-        ||
-        FilePermissionOwnersByAccountId::<T>::contains_key(&sender);
+      ensure!(Self::has_file_permission(&sender), Error::<T>::Unauthorized);
 
       let filenode_eth_address = FilePermissionOwnersByAccountId::<T>::get(&sender);
 
-      ensure!(has_permission, Error::<T>::Unauthorized);
-
-      ensure!(user_eth_address.len() == 20, Error::<T>::InvalidArguments);
-      ensure!(gateway_eth_address.len() == 20, Error::<T>::InvalidArguments);
-      ensure!(file_contents_hash.len() == 32, Error::<T>::InvalidArguments);
-      ensure!(file_name_hash.len() == 32, Error::<T>::InvalidArguments);
-
-      Self::deposit_event(Event::Download(
+      Self::do_download(&filenode_eth_address, DownloadRecord {
         user_eth_address,
         file_name_hash,
         file_size_bytes,
         file_contents_hash,
         gateway_eth_address,
-        filenode_eth_address,
-      ));
+        request_id: None,
+      })?;
 
       Ok(().into())
     }
 
-    #[pallet::weight((0, Pays::No))]
+    #[pallet::weight((T::WeightInfo::delete_batch(1), Pays::No))]
     pub fn delete(origin: OriginFor<T>,
       user_eth_address: Vec<u8>,
       file_name_hash: Vec<u8>,
     ) -> DispatchResultWithPostInfo {
       let sender = ensure_signed(origin)?;
-
-      let has_permission =
-        // is admin
-        sender == Self::key()
-        ||
-        FilePermissionOwnersByAccountId::<T>::contains_key(&sender);
-      ensure!(has_permission, Error::<T>::Unauthorized);
+      ensure!(Self::has_file_permission(&sender), Error::<T>::Unauthorized);
 
       let filenode_eth_address = FilePermissionOwnersByAccountId::<T>::get(&sender);
 
-      ensure!(user_eth_address.len() == 20, Error::<T>::InvalidArguments);
-      ensure!(file_name_hash.len() == 32, Error::<T>::InvalidArguments);
-
-      // TODO total_file_count, total_file_size
-
-      Self::deposit_event(Event::Delete(
+      Self::do_delete(&filenode_eth_address, DeleteRecord {
         user_eth_address,
         file_name_hash,
-        filenode_eth_address
-      ));
+        request_id: None,
+      })?;
+
       Ok(().into())
     }
 
@@ -415,7 +894,7 @@ pub mod pallet {
       }
       // `locked_funds` is more than `value` (see check earlier)
       LockedFunds::<T>::put(locked_funds - value);
-      Self::deposit_event(Event::Deposit(account, value));
+      Self::deposit_event(Event::Deposit { eth_address: account, value });
       Ok(().into())
     }
 
@@ -443,7 +922,7 @@ pub mod pallet {
       // `locked_funds + value` cannot overflow because it cannot be greater
       // than total_issuance
       LockedFunds::<T>::put(LockedFunds::<T>::get() + value);
-      Self::deposit_event(Event::Withdraw(account, value));
+      Self::deposit_event(Event::Withdraw { eth_address: account, value });
       Ok(().into())
     }
 
@@ -480,7 +959,7 @@ pub mod pallet {
         Balances::<T>::insert(&to, balance + value);
       }
 
-      Self::deposit_event(Event::Transfer(from, to, value));
+      Self::deposit_event(Event::Transfer { from, to, value });
       Ok(().into())
     }
     #[pallet::weight((0, Pays::No))]
@@ -516,7 +995,7 @@ pub mod pallet {
         Balances::<T>::insert(&to, balance + value);
       }
 
-      Self::deposit_event(Event::StartStaking(from, to, value));
+      Self::deposit_event(Event::StartStaking { from, to, value });
       Ok(().into())
     }
 
@@ -553,7 +1032,7 @@ pub mod pallet {
         Balances::<T>::insert(&to, balance + value);
       }
 
-      Self::deposit_event(Event::CancelStaking(from, to, value));
+      Self::deposit_event(Event::CancelStaking { from, to, value });
       Ok(().into())
     }
 
@@ -590,7 +1069,7 @@ pub mod pallet {
         Balances::<T>::insert(&to, balance + value);
       }
 
-      Self::deposit_event(Event::EndStaking(from, to, value));
+      Self::deposit_event(Event::EndStaking { from, to, value });
       Ok(().into())
     }
     #[pallet::weight((0, Pays::No))]
@@ -625,7 +1104,7 @@ pub mod pallet {
       }
       // `locked_funds` is more than `value` (see check earlier)
       LockedFunds::<T>::put(locked_funds - value);
-      Self::deposit_event(Event::RewardStaking(from, account, value));
+      Self::deposit_event(Event::RewardStaking { from, eth_address: account, value });
       Ok(().into())
     }
 
@@ -635,8 +1114,7 @@ pub mod pallet {
       account_id: T::AccountId,
       node_url: Vec<u8>,
     ) -> DispatchResultWithPostInfo {
-      let sender = ensure_signed(origin)?;
-      ensure!(sender == Self::key(), Error::<T>::Unauthorized);
+      Self::ensure_admin(origin)?;
 
       ensure!(eth_address.len() == 20, Error::<T>::InvalidArguments);
 
@@ -647,7 +1125,7 @@ pub mod pallet {
       FilePermissionOwnersByETHAddress::<T>::insert(&eth_address, &account_id);
       FilePermissionOwnersByAccountId::<T>::insert(&account_id, &eth_address);
       NodeURLs::<T>::insert(&eth_address, &node_url);
-      Self::deposit_event(Event::FilePermissionGranted(eth_address, account_id, node_url));
+      Self::deposit_event(Event::FilePermissionGranted { eth_address, account_id, node_url });
       Ok(().into())
     }
 
@@ -657,8 +1135,7 @@ pub mod pallet {
       account_id: T::AccountId,
       node_url: Vec<u8>,
     ) -> DispatchResultWithPostInfo {
-      let sender = ensure_signed(origin)?;
-      ensure!(sender == Self::key(), Error::<T>::Unauthorized);
+      Self::ensure_admin(origin)?;
 
       ensure!(eth_address.len() == 20, Error::<T>::InvalidArguments);
 
@@ -669,7 +1146,7 @@ pub mod pallet {
       BillingPermissionOwnersByETHAddress::<T>::insert(&eth_address, &account_id);
       BillingPermissionOwnersByAccountId::<T>::insert(&account_id, &eth_address);
       NodeURLs::<T>::insert(&eth_address, &node_url);
-      Self::deposit_event(Event::BillingPermissionGranted(eth_address, account_id, node_url));
+      Self::deposit_event(Event::BillingPermissionGranted { eth_address, account_id, node_url });
       Ok(().into())
     }
 
@@ -677,14 +1154,13 @@ pub mod pallet {
     pub fn revoke_file_permission(origin: OriginFor<T>,
       eth_address: Vec<u8>,
     ) -> DispatchResultWithPostInfo {
-      let sender = ensure_signed(origin)?;
-      ensure!(sender == Self::key(), Error::<T>::Unauthorized);
+      Self::ensure_admin(origin)?;
       ensure!(eth_address.len() == 20, Error::<T>::InvalidArguments);
       ensure!(FilePermissionOwnersByETHAddress::<T>::contains_key(&eth_address),
                                         Error::<T>::InvalidArguments);
       let account_id = FilePermissionOwnersByETHAddress::<T>::take(&eth_address);
       FilePermissionOwnersByAccountId::<T>::take(&account_id);
-      Self::deposit_event(Event::FilePermissionRevoked(eth_address, account_id));
+      Self::deposit_event(Event::FilePermissionRevoked { eth_address, account_id });
       Ok(().into())
     }
 
@@ -692,14 +1168,13 @@ pub mod pallet {
     pub fn revoke_billing_permission(origin: OriginFor<T>,
       eth_address: Vec<u8>,
     ) -> DispatchResultWithPostInfo {
-      let sender = ensure_signed(origin)?;
-      ensure!(sender == Self::key(), Error::<T>::Unauthorized);
+      Self::ensure_admin(origin)?;
       ensure!(eth_address.len() == 20, Error::<T>::InvalidArguments);
       ensure!(BillingPermissionOwnersByETHAddress::<T>::contains_key(&eth_address),
                                         Error::<T>::InvalidArguments);
       let account_id = BillingPermissionOwnersByETHAddress::<T>::take(&eth_address);
       BillingPermissionOwnersByAccountId::<T>::take(&account_id);
-      Self::deposit_event(Event::BillingPermissionRevoked(eth_address, account_id));
+      Self::deposit_event(Event::BillingPermissionRevoked { eth_address, account_id });
       Ok(().into())
     }
 
@@ -711,8 +1186,7 @@ pub mod pallet {
       node_url: Vec<u8>,
 		is_active:bool
     ) -> DispatchResultWithPostInfo {
-      let sender = ensure_signed(origin)?;
-      ensure!(sender == Self::key(), Error::<T>::Unauthorized);
+      Self::ensure_admin(origin)?;
       ensure!(eth_address.len() == 20, Error::<T>::InvalidArguments);
       if let Some(ref addr) = seed_eth_address {
         ensure!(addr.len() == 20, Error::<T>::InvalidArguments);
@@ -725,15 +1199,508 @@ pub mod pallet {
       };
       Gateways::<T>::insert(eth_address.clone(), &gateway);
       NodeURLs::<T>::insert(eth_address.clone(), &node_url);
-      Self::deposit_event(Event::GatewayNodeRegistered(
+      Self::deposit_event(Event::GatewayNodeRegistered {
         eth_address,
         seed_eth_address,
         storage,
         node_url,
 		  is_active
-      ));
+      });
+      Ok(().into())
+    }
+
+    /// Apply several `upload`s in one extrinsic. See `BatchMode` for how
+    /// failing records are handled.
+    #[pallet::weight((T::WeightInfo::upload_batch(records.len() as u32), Pays::No))]
+    pub fn upload_batch(origin: OriginFor<T>,
+      records: BoundedVec<UploadRecord, T::MaxBatchSize>,
+      mode: BatchMode,
+    ) -> DispatchResultWithPostInfo {
+      let sender = ensure_signed(origin)?;
+      ensure!(Self::has_file_permission(&sender), Error::<T>::Unauthorized);
+
+      let filenode_eth_address = FilePermissionOwnersByAccountId::<T>::get(&sender);
+
+      Self::process_batch(records.into_inner(), mode, |record| {
+        Self::do_upload(&filenode_eth_address, record)
+      })
+    }
+
+    /// Apply several `download`s in one extrinsic. See `BatchMode` for how
+    /// failing records are handled.
+    #[pallet::weight((T::WeightInfo::download_batch(records.len() as u32), Pays::No))]
+    pub fn download_batch(origin: OriginFor<T>,
+      records: BoundedVec<DownloadRecord, T::MaxBatchSize>,
+      mode: BatchMode,
+    ) -> DispatchResultWithPostInfo {
+      let sender = ensure_signed(origin)?;
+      ensure!(Self::has_file_permission(&sender), Error::<T>::Unauthorized);
+
+      let filenode_eth_address = FilePermissionOwnersByAccountId::<T>::get(&sender);
+
+      Self::process_batch(records.into_inner(), mode, |record| {
+        Self::do_download(&filenode_eth_address, record)
+      })
+    }
+
+    /// Apply several `delete`s in one extrinsic. See `BatchMode` for how
+    /// failing records are handled.
+    #[pallet::weight((T::WeightInfo::delete_batch(records.len() as u32), Pays::No))]
+    pub fn delete_batch(origin: OriginFor<T>,
+      records: BoundedVec<DeleteRecord, T::MaxBatchSize>,
+      mode: BatchMode,
+    ) -> DispatchResultWithPostInfo {
+      let sender = ensure_signed(origin)?;
+      ensure!(Self::has_file_permission(&sender), Error::<T>::Unauthorized);
+
+      let filenode_eth_address = FilePermissionOwnersByAccountId::<T>::get(&sender);
+
+      Self::process_batch(records.into_inner(), mode, |record| {
+        Self::do_delete(&filenode_eth_address, record)
+      })
+    }
+
+    /// Report usage without a signed extrinsic, so that a filenode's reports
+    /// don't compete for its account nonce. The payload signature is checked
+    /// in `validate_unsigned`.
+    // An upload is the heaviest kind of usage report.
+    #[pallet::weight((T::WeightInfo::upload_batch(1), Pays::No))]
+    pub fn submit_usage_unsigned(origin: OriginFor<T>,
+      payload: UsagePayload<T::Public>,
+      _signature: T::Signature,
+    ) -> DispatchResultWithPostInfo {
+      ensure_none(origin)?;
+
+      let sender = payload.public.into_account();
+      ensure!(Self::has_file_permission(&sender), Error::<T>::Unauthorized);
+      ensure!(payload.sequence > UsageSequence::<T>::get(&sender), Error::<T>::StaleSequence);
+
+      let filenode_eth_address = FilePermissionOwnersByAccountId::<T>::get(&sender);
+
+      // Spend the sequence number whatever the outcome, so that payloads
+      // queued behind this one stay valid.
+      UsageSequence::<T>::insert(&sender, payload.sequence);
+      let result = with_transaction(|| {
+        let result = match payload.usage {
+          Usage::Upload(record) => Self::do_upload(&filenode_eth_address, record),
+          Usage::Download(record) => Self::do_download(&filenode_eth_address, record),
+          Usage::Delete(record) => Self::do_delete(&filenode_eth_address, record),
+        };
+        match result {
+          Ok(()) => TransactionOutcome::Commit(result),
+          Err(_) => TransactionOutcome::Rollback(result),
+        }
+      });
+      if let Err(e) = result {
+        Self::deposit_event(Event::UsageRejected {
+          filenode_eth_address,
+          sequence: payload.sequence,
+          error: e,
+        });
+      }
+
+      Ok(().into())
+    }
+
+    /// Replace the admin key.
+    #[pallet::weight(T::DbWeight::get().reads_writes(1, 1))]
+    pub fn set_key(origin: OriginFor<T>, new_key: T::AccountId) -> DispatchResultWithPostInfo {
+      T::AdminOrigin::ensure_origin(origin)?;
+      let old_key = Key::<T>::mutate(|key| sp_std::mem::replace(key, new_key.clone()));
+      Self::deposit_event(Event::KeyChanged { old_key, new_key });
+      Ok(().into())
+    }
+
+    /// `upload` with a client request ID. Fails with `DuplicateRequest` if
+    /// the filenode used the ID within `RequestIdRetention` blocks.
+    #[pallet::weight((T::WeightInfo::upload_batch(1), Pays::No))]
+    pub fn upload_with_request_id(origin: OriginFor<T>,
+      user_eth_address: Vec<u8>,
+      file_name_hash: Vec<u8>,
+      file_size_bytes: u128,
+      file_contents_hash: Vec<u8>,
+      gateway_eth_address: Vec<u8>,
+      file_storage_class: u8,
+      is_forced: bool,
+      request_id: RequestId,
+    ) -> DispatchResultWithPostInfo {
+      let sender = ensure_signed(origin)?;
+      ensure!(Self::has_file_permission(&sender), Error::<T>::Unauthorized);
+
+      let filenode_eth_address = FilePermissionOwnersByAccountId::<T>::get(&sender);
+
+      Self::do_upload(&filenode_eth_address, UploadRecord {
+        user_eth_address,
+        file_name_hash,
+        file_size_bytes,
+        file_contents_hash,
+        gateway_eth_address,
+        file_storage_class,
+        is_forced,
+        request_id: Some(request_id),
+      })?;
+
+      Ok(().into())
+    }
+
+    /// `download` with a client request ID, see `upload_with_request_id`.
+    #[pallet::weight((T::WeightInfo::download_batch(1), Pays::No))]
+    pub fn download_with_request_id(origin: OriginFor<T>,
+      user_eth_address: Vec<u8>,
+      file_name_hash: Vec<u8>,
+      file_size_bytes: u128,
+      file_contents_hash: Vec<u8>,
+      gateway_eth_address: Vec<u8>,
+      request_id: RequestId,
+    ) -> DispatchResultWithPostInfo {
+      let sender = ensure_signed(origin)?;
+      ensure!(Self::has_file_permission(&sender), Error::<T>::Unauthorized);
+
+      let filenode_eth_address = FilePermissionOwnersByAccountId::<T>::get(&sender);
+
+      Self::do_download(&filenode_eth_address, DownloadRecord {
+        user_eth_address,
+        file_name_hash,
+        file_size_bytes,
+        file_contents_hash,
+        gateway_eth_address,
+        request_id: Some(request_id),
+      })?;
+
+      Ok(().into())
+    }
+
+    /// `delete` with a client request ID, see `upload_with_request_id`.
+    #[pallet::weight((T::WeightInfo::delete_batch(1), Pays::No))]
+    pub fn delete_with_request_id(origin: OriginFor<T>,
+      user_eth_address: Vec<u8>,
+      file_name_hash: Vec<u8>,
+      request_id: RequestId,
+    ) -> DispatchResultWithPostInfo {
+      let sender = ensure_signed(origin)?;
+      ensure!(Self::has_file_permission(&sender), Error::<T>::Unauthorized);
+
+      let filenode_eth_address = FilePermissionOwnersByAccountId::<T>::get(&sender);
+
+      Self::do_delete(&filenode_eth_address, DeleteRecord {
+        user_eth_address,
+        file_name_hash,
+        request_id: Some(request_id),
+      })?;
+
       Ok(().into())
     }
+  }
+
+  #[pallet::validate_unsigned]
+  impl<T: Config> ValidateUnsigned for Pallet<T> {
+    type Call = Call<T>;
+
+    fn validate_unsigned(_source: TransactionSource, call: &Self::Call) -> TransactionValidity {
+      let (payload, signature) = match call {
+        Call::submit_usage_unsigned(payload, signature) => (payload, signature),
+        _ => return InvalidTransaction::Call.into(),
+      };
+
+      let sender = payload.public.clone().into_account();
+      let genesis_hash = frame_system::Pallet::<T>::block_hash(T::BlockNumber::zero());
+      if !signature.verify(&usage_signing_payload(&genesis_hash, payload)[..], &sender) {
+        return InvalidTransaction::BadProof.into()
+      }
+      if !Self::has_file_permission(&sender) {
+        return InvalidTransaction::BadSigner.into()
+      }
+
+      let last_sequence = UsageSequence::<T>::get(&sender);
+      if payload.sequence <= last_sequence {
+        return InvalidTransaction::Stale.into()
+      }
+
+      let kind = payload.usage.kind();
+      let mut validity = ValidTransaction::with_tag_prefix("ColdStackUsage")
+        .priority(T::UnsignedValidity::priority(kind))
+        .longevity(T::UnsignedValidity::longevity(kind))
+        .and_provides((&sender, payload.sequence))
+        .propagate(true);
+      if payload.sequence > last_sequence + 1 {
+        validity = validity.and_requires((&sender, payload.sequence - 1));
+      }
+      validity.build()
+    }
+  }
+
+  impl<T: Config> Pallet<T> {
+    /// Deposit `event` with its `Event::topics`, so that clients can find
+    /// it through `System::EventTopics`.
+    pub(super) fn deposit_event(event: Event<T>) {
+      let topics = event.topics();
+      let event = <<T as Config>::Event as From<Event<T>>>::from(event);
+      let event = <<T as Config>::Event as Into<<T as frame_system::Config>::Event>>::into(event);
+      frame_system::Pallet::<T>::deposit_event_indexed(&topics, event);
+    }
+
+    /// Topic of events involving `eth_address`:
+    /// `hash("coldstack:address:" ++ eth_address)`.
+    pub fn eth_address_topic(eth_address: &[u8]) -> T::Hash {
+      T::Hashing::hash(&[&b"coldstack:address:"[..], eth_address].concat())
+    }
+
+    /// Topic of events concerning the file `file_name_hash`:
+    /// `hash("coldstack:file:" ++ file_name_hash)`.
+    pub fn file_topic(file_name_hash: &[u8]) -> T::Hash {
+      T::Hashing::hash(&[&b"coldstack:file:"[..], file_name_hash].concat())
+    }
+
+    /// Check that `origin` is `AdminOrigin` or signed by the admin key.
+    fn ensure_admin(origin: OriginFor<T>) -> DispatchResult {
+      let origin = match T::AdminOrigin::try_origin(origin) {
+        Ok(_) => return Ok(()),
+        Err(origin) => origin,
+      };
+      let sender = ensure_signed(origin)?;
+      ensure!(sender == Self::key(), Error::<T>::Unauthorized);
+      Ok(())
+    }
+
+    /// Whether `who` may submit `upload`, `download` and `delete`.
+    pub fn has_file_permission(who: &T::AccountId) -> bool {
+      // is admin
+      *who == Self::key()
+      ||
+      FilePermissionOwnersByAccountId::<T>::contains_key(who)
+    }
+
+    /// Whether `who` may move balances and manage staking.
+    pub fn has_billing_permission(who: &T::AccountId) -> bool {
+      // is admin
+      *who == Self::key()
+      ||
+      BillingPermissionOwnersByAccountId::<T>::contains_key(who)
+    }
+
+    /// Whether `who` holds the permission `call` checks at dispatch.
+    pub fn can_submit(who: &T::AccountId, call: &Call<T>) -> bool {
+      match call {
+        Call::upload(..)
+        | Call::download(..)
+        | Call::delete(..)
+        | Call::upload_with_request_id(..)
+        | Call::download_with_request_id(..)
+        | Call::delete_with_request_id(..)
+        | Call::upload_batch(..)
+        | Call::download_batch(..)
+        | Call::delete_batch(..)
+        | Call::change_status_gateway(..)
+        | Call::delete_gateway(..) => Self::has_file_permission(who),
+        Call::deposit(..)
+        | Call::withdraw(..)
+        | Call::transfer(..)
+        | Call::start_staking(..)
+        | Call::cancel_staking(..)
+        | Call::end_staking(..)
+        | Call::reward_staking(..) => Self::has_billing_permission(who),
+        Call::grant_file_permission(..)
+        | Call::grant_billing_permission(..)
+        | Call::revoke_file_permission(..)
+        | Call::revoke_billing_permission(..)
+        | Call::register_gateway_node(..) => *who == Self::key(),
+        // `submit_usage_unsigned` is only valid as an unsigned extrinsic.
+        _ => false,
+      }
+    }
+
+    /// Number of ColdStack calls of `who` already included in the current
+    /// block.
+    pub fn calls_in_block(who: &T::AccountId) -> u32 {
+      let (block, calls) = CallsInBlock::<T>::get(who);
+      if block == frame_system::Pallet::<T>::block_number() { calls } else { 0 }
+    }
+
+    /// Count one more ColdStack call of `who` in the current block.
+    pub(crate) fn note_call(who: &T::AccountId) {
+      let calls = Self::calls_in_block(who);
+      CallsInBlock::<T>::insert(who, (frame_system::Pallet::<T>::block_number(), calls + 1));
+    }
+
+    fn do_upload(filenode_eth_address: &[u8], record: UploadRecord) -> DispatchResult {
+      ensure!(record.user_eth_address.len() == 20, Error::<T>::InvalidArguments);
+      ensure!(record.gateway_eth_address.len() == 20, Error::<T>::InvalidArguments);
+      ensure!(record.file_contents_hash.len() == 32, Error::<T>::InvalidArguments);
+      ensure!(record.file_name_hash.len() == 32, Error::<T>::InvalidArguments);
+      Self::note_request(filenode_eth_address, record.request_id)?;
+
+      <TotalFileCount<T>>::put(Self::total_file_count() + 1);
+      <TotalFileSize<T>>::put(Self::total_file_size() + record.file_size_bytes);
+
+      Self::deposit_event(Event::Upload {
+        user_eth_address: record.user_eth_address,
+        file_name_hash: record.file_name_hash,
+        file_size_bytes: record.file_size_bytes,
+        file_contents_hash: record.file_contents_hash,
+        gateway_eth_address: record.gateway_eth_address,
+        filenode_eth_address: filenode_eth_address.to_vec(),
+        file_storage_class: record.file_storage_class,
+        is_forced: record.is_forced,
+      });
+      Ok(())
+    }
+
+    fn do_download(filenode_eth_address: &[u8], record: DownloadRecord) -> DispatchResult {
+      ensure!(record.user_eth_address.len() == 20, Error::<T>::InvalidArguments);
+      ensure!(record.gateway_eth_address.len() == 20, Error::<T>::InvalidArguments);
+      ensure!(record.file_contents_hash.len() == 32, Error::<T>::InvalidArguments);
+      ensure!(record.file_name_hash.len() == 32, Error::<T>::InvalidArguments);
+      Self::note_request(filenode_eth_address, record.request_id)?;
+
+      Self::deposit_event(Event::Download {
+        user_eth_address: record.user_eth_address,
+        file_name_hash: record.file_name_hash,
+        file_size_bytes: record.file_size_bytes,
+        file_contents_hash: record.file_contents_hash,
+        gateway_eth_address: record.gateway_eth_address,
+        filenode_eth_address: filenode_eth_address.to_vec(),
+      });
+      Ok(())
+    }
 
+    fn do_delete(filenode_eth_address: &[u8], record: DeleteRecord) -> DispatchResult {
+      ensure!(record.user_eth_address.len() == 20, Error::<T>::InvalidArguments);
+      ensure!(record.file_name_hash.len() == 32, Error::<T>::InvalidArguments);
+      Self::note_request(filenode_eth_address, record.request_id)?;
+
+      // The totals count uploads; deleting leaves them as they are.
+      Self::deposit_event(Event::Delete {
+        user_eth_address: record.user_eth_address,
+        file_name_hash: record.file_name_hash,
+        filenode_eth_address: filenode_eth_address.to_vec(),
+      });
+      Ok(())
+    }
+
+    /// Remember `request_id` of `filenode_eth_address` for
+    /// `RequestIdRetention` blocks, failing if that filenode already used it.
+    fn note_request(filenode_eth_address: &[u8], request_id: Option<RequestId>) -> DispatchResult {
+      if let Some(request_id) = request_id {
+        ensure!(
+          !ProcessedRequests::<T>::contains_key(filenode_eth_address, &request_id),
+          Error::<T>::DuplicateRequest
+        );
+        let now = frame_system::Pallet::<T>::block_number();
+        ProcessedRequests::<T>::insert(filenode_eth_address, &request_id, now);
+        RequestExpiry::<T>::insert(
+          now + T::RequestIdRetention::get(),
+          (filenode_eth_address.to_vec(), request_id),
+          (),
+        );
+      }
+      Ok(())
+    }
+
+    /// Those of `request_ids` that `filenode_eth_address` had processed within
+    /// the retention window.
+    pub fn processed_requests(filenode_eth_address: Vec<u8>, request_ids: Vec<RequestId>) -> Vec<RequestId> {
+      request_ids.into_iter()
+        .filter(|id| ProcessedRequests::<T>::contains_key(&filenode_eth_address, id))
+        .collect()
+    }
+
+    /// Balance of `eth_address`, zero if it has none.
+    pub fn balance_of(eth_address: Vec<u8>) -> u128 {
+      Balances::<T>::get(eth_address)
+    }
+
+    /// All registered gateways with their node URLs.
+    pub fn gateways() -> Vec<GatewayInfo> {
+      Gateways::<T>::iter_values()
+        .map(|gateway| GatewayInfo {
+          node_url: NodeURLs::<T>::get(&gateway.address),
+          address: gateway.address,
+          seed_address: gateway.seed_address,
+          storage: gateway.storage,
+          is_active: gateway.is_active,
+        })
+        .collect()
+    }
+
+    /// Global file and issuance totals.
+    pub fn stats() -> Stats {
+      Stats {
+        total_file_count: Self::total_file_count(),
+        total_file_size: Self::total_file_size(),
+        total_issuance: Self::total_issuance(),
+        locked_funds: Self::locked_funds(),
+      }
+    }
+
+    /// File `file_name_hash` of `user_eth_address` as of its last upload.
+    ///
+    /// Files are not kept in chain state: this reads the persistent offchain
+    /// storage of the node, where the node's history writer puts them once
+    /// the upload is finalized. Returns `None` on nodes that do not write
+    /// history. Only callable where offchain storage is readable, i.e. from
+    /// runtime API calls, not from block execution.
+    pub fn file_info(user_eth_address: Vec<u8>, file_name_hash: Vec<u8>) -> Option<FileInfo> {
+      sp_io::offchain::local_storage_get(
+        sp_runtime::offchain::StorageKind::PERSISTENT,
+        &file_key(&user_eth_address, &file_name_hash),
+      )
+        .and_then(|value| FileInfo::decode(&mut &value[..]).ok())
+    }
+
+    /// Account holding file permission for `eth_address`.
+    pub fn file_permission_owner(eth_address: Vec<u8>) -> Option<T::AccountId> {
+      FilePermissionOwnersByETHAddress::<T>::try_get(eth_address).ok()
+    }
+
+    /// Account holding billing permission for `eth_address`.
+    pub fn billing_permission_owner(eth_address: Vec<u8>) -> Option<T::AccountId> {
+      BillingPermissionOwnersByETHAddress::<T>::try_get(eth_address).ok()
+    }
+
+    /// Whether `account` may report uploads.
+    pub fn can_upload(account: &T::AccountId) -> bool {
+      Self::has_file_permission(account)
+    }
+
+    /// Permissions held by `account`.
+    pub fn permissions_of(account: &T::AccountId) -> Permissions {
+      Permissions {
+        is_admin: *account == Self::key(),
+        file: FilePermissionOwnersByAccountId::<T>::try_get(account).ok(),
+        billing: BillingPermissionOwnersByAccountId::<T>::try_get(account).ok(),
+      }
+    }
+
+    /// Run `process` over `records`, reporting the outcome with a
+    /// `BatchCompleted` or `BatchInterrupted` event.
+    fn process_batch<R>(
+      records: Vec<R>,
+      mode: BatchMode,
+      mut process: impl FnMut(R) -> DispatchResult,
+    ) -> DispatchResultWithPostInfo {
+      let total = records.len() as u32;
+
+      let outcome = with_transaction(|| {
+        let mut failed = Vec::new();
+        for (index, record) in records.into_iter().enumerate() {
+          if let Err(e) = process(record) {
+            if mode == BatchMode::AllOrNothing {
+              return TransactionOutcome::Rollback(Err((index as u32, e)))
+            }
+            failed.push((index as u32, e));
+          }
+        }
+        TransactionOutcome::Commit(Ok(failed))
+      });
+
+      match outcome {
+        Ok(failed) => {
+          let succeeded = total - failed.len() as u32;
+          Self::deposit_event(Event::BatchCompleted { succeeded, failed });
+        }
+        Err((index, error)) => Self::deposit_event(Event::BatchInterrupted { index, error }),
+      }
+      Ok(().into())
+    }
   }
 }
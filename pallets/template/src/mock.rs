@@ -2,7 +2,8 @@ use crate as pallet_template;
 use sp_core::H256;
 use frame_support::parameter_types;
 use sp_runtime::{
-	traits::{BlakeTwo256, IdentityLookup}, testing::Header,
+	traits::{BlakeTwo256, IdentityLookup}, testing::{Header, TestSignature, UintAuthorityId},
+	transaction_validity::{TransactionLongevity, TransactionPriority},
 };
 use frame_system as system;
 
@@ -17,13 +18,16 @@ frame_support::construct_runtime!(
 		UncheckedExtrinsic = UncheckedExtrinsic,
 	{
 		System: frame_system::{Pallet, Call, Config, Storage, Event<T>},
-		ColdStack: pallet_template::{Pallet, Call, Storage, Event<T>},
+		ColdStack: pallet_template::{Pallet, Call, Storage, Event<T>, ValidateUnsigned},
 	}
 );
 
 parameter_types! {
 	pub const BlockHashCount: u64 = 250;
 	pub const SS58Prefix: u8 = 42;
+	pub const MaxBatchSize: u32 = 4;
+	pub const RequestIdRetention: u64 = 10;
+	pub const MaxCallsPerBlock: u32 = 2;
 }
 
 impl system::Config for Test {
@@ -54,6 +58,30 @@ impl system::Config for Test {
 
 impl pallet_template::Config for Test {
 	type Event = Event;
+	type MaxBatchSize = MaxBatchSize;
+	type WeightInfo = ();
+	type RequestIdRetention = RequestIdRetention;
+	type Public = UintAuthorityId;
+	type Signature = TestSignature;
+	type UnsignedValidity = TestUnsignedValidity;
+	type MaxCallsPerBlock = MaxCallsPerBlock;
+	type AdminOrigin = frame_system::EnsureRoot<u64>;
+}
+
+pub struct TestUnsignedValidity;
+
+impl pallet_template::UnsignedValidity for TestUnsignedValidity {
+	fn priority(kind: pallet_template::UsageKind) -> TransactionPriority {
+		match kind {
+			pallet_template::UsageKind::Upload => 30,
+			pallet_template::UsageKind::Delete => 20,
+			pallet_template::UsageKind::Download => 10,
+		}
+	}
+
+	fn longevity(_kind: pallet_template::UsageKind) -> TransactionLongevity {
+		64
+	}
 }
 
 // Build genesis storage according to the mock runtime.
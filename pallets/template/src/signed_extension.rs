@@ -0,0 +1,99 @@
+//! Transaction pool guard for the fee-less ColdStack calls.
+
+use codec::{Decode, Encode};
+use frame_support::traits::{Get, IsSubType};
+use sp_runtime::{
+	traits::{DispatchInfoOf, SignedExtension},
+	transaction_validity::{
+		InvalidTransaction, TransactionValidity, TransactionValidityError, ValidTransaction,
+	},
+};
+use sp_std::{fmt, marker::PhantomData};
+
+use crate::{Call, Config, Pallet};
+
+/// Rejects ColdStack calls whose signer lacks the permission the call needs,
+/// and limits each account to `MaxCallsPerBlock` ColdStack calls per block.
+///
+/// ColdStack calls are `Pays::No`, so without this anyone could fill blocks
+/// with calls that only fail at dispatch. Calls to other pallets pass through.
+///
+/// The rate limit is applied when a block is built: calls over the limit are
+/// skipped and stay in the pool for the next block.
+#[derive(Encode, Decode, Clone, Eq, PartialEq)]
+pub struct CheckColdStackPermission<T: Config + Send + Sync>(PhantomData<T>);
+
+impl<T: Config + Send + Sync> CheckColdStackPermission<T> {
+	pub fn new() -> Self {
+		Self(PhantomData)
+	}
+}
+
+impl<T: Config + Send + Sync> Default for CheckColdStackPermission<T> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<T: Config + Send + Sync> fmt::Debug for CheckColdStackPermission<T> {
+	#[cfg(feature = "std")]
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "CheckColdStackPermission")
+	}
+
+	#[cfg(not(feature = "std"))]
+	fn fmt(&self, _: &mut fmt::Formatter) -> fmt::Result {
+		Ok(())
+	}
+}
+
+impl<T: Config + Send + Sync> SignedExtension for CheckColdStackPermission<T>
+where
+	T::Call: IsSubType<Call<T>>,
+{
+	const IDENTIFIER: &'static str = "CheckColdStackPermission";
+	type AccountId = T::AccountId;
+	type Call = T::Call;
+	type AdditionalSigned = ();
+	type Pre = ();
+
+	fn additional_signed(&self) -> Result<(), TransactionValidityError> {
+		Ok(())
+	}
+
+	fn validate(
+		&self,
+		who: &Self::AccountId,
+		call: &Self::Call,
+		_info: &DispatchInfoOf<Self::Call>,
+		_len: usize,
+	) -> TransactionValidity {
+		let call = match call.is_sub_type() {
+			Some(call) => call,
+			None => return Ok(ValidTransaction::default()),
+		};
+
+		if !Pallet::<T>::can_submit(who, call) {
+			return InvalidTransaction::BadSigner.into()
+		}
+		if Pallet::<T>::calls_in_block(who) >= T::MaxCallsPerBlock::get() {
+			return InvalidTransaction::ExhaustsResources.into()
+		}
+
+		Ok(ValidTransaction::default())
+	}
+
+	fn pre_dispatch(
+		self,
+		who: &Self::AccountId,
+		call: &Self::Call,
+		info: &DispatchInfoOf<Self::Call>,
+		len: usize,
+	) -> Result<(), TransactionValidityError> {
+		self.validate(who, call, info, len)?;
+		if call.is_sub_type().is_some() {
+			Pallet::<T>::note_call(who);
+		}
+		Ok(())
+	}
+}
@@ -1,23 +1,466 @@
-use crate::{Error, mock::*};
+use crate::{
+	Error, mock::*, usage_signing_payload, BatchMode, CheckColdStackPermission, Key, UploadRecord, Usage,
+	UsagePayload,
+};
+use frame_support::traits::OnInitialize;
+use sp_core::H256;
+use sp_runtime::{
+	testing::{TestSignature, UintAuthorityId},
+	traits::{SignedExtension, ValidateUnsigned},
+	transaction_validity::{InvalidTransaction, TransactionSource, TransactionValidity},
+};
 use frame_support::{assert_ok, assert_noop};
+use sp_std::convert::TryInto;
+
+fn upload_record(user: u8) -> UploadRecord {
+	UploadRecord {
+		user_eth_address: vec![user; 20],
+		file_name_hash: vec![user; 32],
+		file_size_bytes: 100,
+		file_contents_hash: vec![user; 32],
+		gateway_eth_address: vec![0x22; 20],
+		file_storage_class: 0,
+		is_forced: false,
+		request_id: None,
+	}
+}
+
+fn last_event() -> Event {
+	System::events().pop().expect("an event was deposited").event
+}
+
+#[test]
+fn upload_batch_best_effort_reports_failed_records() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		Key::<Test>::put(1);
+		let invalid = UploadRecord { user_eth_address: vec![0x33; 19], ..upload_record(0x33) };
+		let records = vec![upload_record(0x11), invalid, upload_record(0x44)];
+
+		assert_ok!(ColdStack::upload_batch(
+			Origin::signed(1), records.try_into().unwrap(), BatchMode::BestEffort,
+		));
+
+		assert_eq!(ColdStack::total_file_count(), 2);
+		assert_eq!(ColdStack::total_file_size(), 200);
+		assert_eq!(
+			last_event(),
+			Event::ColdStack(crate::Event::BatchCompleted {
+				succeeded: 2,
+				failed: vec![(1, Error::<Test>::InvalidArguments.into())],
+			}),
+		);
+	});
+}
+
+#[test]
+fn upload_batch_all_or_nothing_rolls_back() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		Key::<Test>::put(1);
+		let invalid = UploadRecord { file_name_hash: vec![0x33; 31], ..upload_record(0x33) };
+		let records = vec![upload_record(0x11), invalid, upload_record(0x44)];
+
+		assert_ok!(ColdStack::upload_batch(
+			Origin::signed(1), records.try_into().unwrap(), BatchMode::AllOrNothing,
+		));
+
+		assert_eq!(ColdStack::total_file_count(), 0);
+		assert_eq!(System::events().len(), 1);
+		assert_eq!(
+			last_event(),
+			Event::ColdStack(crate::Event::BatchInterrupted { index: 1, error: Error::<Test>::InvalidArguments.into() }),
+		);
+	});
+}
+
+#[test]
+fn upload_batch_requires_file_permission() {
+	new_test_ext().execute_with(|| {
+		Key::<Test>::put(1);
+		assert_noop!(
+			ColdStack::upload_batch(
+				Origin::signed(2), vec![upload_record(0x11)].try_into().unwrap(), BatchMode::BestEffort,
+			),
+			Error::<Test>::Unauthorized,
+		);
+	});
+}
+
+#[test]
+fn duplicate_request_id_is_rejected_within_retention() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		Key::<Test>::put(1);
+		assert_ok!(ColdStack::grant_file_permission(Origin::signed(1), vec![0x44; 20], 2, vec![]));
+		assert_ok!(ColdStack::grant_file_permission(Origin::signed(1), vec![0x55; 20], 3, vec![]));
+		let request_id = [7u8; 16];
+		let upload = |filenode, request_id| ColdStack::upload_with_request_id(
+			Origin::signed(filenode), vec![0x11; 20], vec![0x11; 32], 100, vec![0x11; 32], vec![0x22; 20], 0, false,
+			request_id,
+		);
+
+		assert_ok!(upload(2, request_id));
+		assert_noop!(upload(2, request_id), Error::<Test>::DuplicateRequest);
+		assert_eq!(
+			ColdStack::processed_requests(vec![0x44; 20], vec![[7u8; 16], [8u8; 16]]),
+			vec![[7u8; 16]]
+		);
+		// Request IDs are scoped to the filenode that used them.
+		assert_eq!(ColdStack::processed_requests(vec![0x55; 20], vec![[7u8; 16]]), vec![]);
+		assert_ok!(upload(3, request_id));
+
+		// Forgotten once the retention window has passed.
+		System::set_block_number(11);
+		ColdStack::on_initialize(11);
+		assert_eq!(ColdStack::processed_requests(vec![0x44; 20], vec![[7u8; 16]]), vec![]);
+		assert_ok!(upload(2, request_id));
+	});
+}
+
+fn usage_payload(signer: u64, sequence: u64) -> (UsagePayload<UintAuthorityId>, TestSignature) {
+	let payload = UsagePayload {
+		public: UintAuthorityId(signer),
+		sequence,
+		usage: Usage::Upload(upload_record(0x11)),
+	};
+	let genesis_hash = System::block_hash(0);
+	let signature = TestSignature(signer, usage_signing_payload(&genesis_hash, &payload));
+	(payload, signature)
+}
+
+fn validate_usage(payload: UsagePayload<UintAuthorityId>, signature: TestSignature) -> TransactionValidity {
+	let call = crate::Call::submit_usage_unsigned(payload, signature);
+	ColdStack::validate_unsigned(TransactionSource::External, &call)
+}
+
+#[test]
+fn unsigned_usage_is_ordered_by_sequence() {
+	new_test_ext().execute_with(|| {
+		Key::<Test>::put(1);
+
+		let (payload, signature) = usage_payload(1, 1);
+		let first = validate_usage(payload.clone(), signature.clone()).unwrap();
+		assert_eq!(first.priority, 30);
+		assert!(first.requires.is_empty());
+
+		let (later, later_signature) = usage_payload(1, 3);
+		assert_eq!(validate_usage(later, later_signature).unwrap().requires.len(), 1);
+
+		assert_ok!(ColdStack::submit_usage_unsigned(Origin::none(), payload.clone(), signature.clone()));
+		assert_eq!(ColdStack::total_file_count(), 1);
+		assert_eq!(validate_usage(payload, signature), Err(InvalidTransaction::Stale.into()));
+	});
+}
+
+#[test]
+fn unsigned_usage_requires_valid_signature_and_permission() {
+	new_test_ext().execute_with(|| {
+		Key::<Test>::put(1);
+
+		let (payload, _) = usage_payload(1, 1);
+		assert_eq!(
+			validate_usage(payload.clone(), TestSignature(1, vec![])),
+			Err(InvalidTransaction::BadProof.into()),
+		);
+		// A signature made for another chain does not verify here.
+		let other_chain = usage_signing_payload(&H256::repeat_byte(1), &payload);
+		assert_eq!(
+			validate_usage(payload, TestSignature(1, other_chain)),
+			Err(InvalidTransaction::BadProof.into()),
+		);
+
+		let (payload, signature) = usage_payload(2, 1);
+		assert_eq!(validate_usage(payload, signature), Err(InvalidTransaction::BadSigner.into()));
+	});
+}
+
+fn delete_call() -> Call {
+	Call::ColdStack(crate::Call::delete(vec![0x11; 20], vec![0x11; 32]))
+}
+
+#[test]
+fn signed_extension_rejects_calls_without_permission() {
+	new_test_ext().execute_with(|| {
+		Key::<Test>::put(1);
+		let extension = CheckColdStackPermission::<Test>::new();
+		let info = Default::default();
+
+		assert!(extension.validate(&1, &delete_call(), &info, 0).is_ok());
+		assert_eq!(
+			extension.validate(&2, &delete_call(), &info, 0),
+			Err(InvalidTransaction::BadSigner.into()),
+		);
+
+		let grant = Call::ColdStack(crate::Call::grant_file_permission(vec![0x11; 20], 2, vec![]));
+		assert_eq!(extension.validate(&2, &grant, &info, 0), Err(InvalidTransaction::BadSigner.into()));
+
+		// Calls to other pallets are not the extension's business.
+		let remark = Call::System(frame_system::Call::remark(vec![]));
+		assert!(extension.validate(&2, &remark, &info, 0).is_ok());
+	});
+}
+
+#[test]
+fn signed_extension_limits_calls_per_block() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		Key::<Test>::put(1);
+		let info = Default::default();
+		let pre_dispatch = || CheckColdStackPermission::<Test>::new().pre_dispatch(&1, &delete_call(), &info, 0);
+
+		assert_ok!(pre_dispatch());
+		assert_ok!(pre_dispatch());
+		assert_eq!(pre_dispatch(), Err(InvalidTransaction::ExhaustsResources.into()));
+
+		System::set_block_number(2);
+		assert_ok!(pre_dispatch());
+	});
+}
 
 #[test]
-fn it_works_for_default_value() {
+fn totals_count_every_upload() {
 	new_test_ext().execute_with(|| {
-		// Dispatch a signed extrinsic.
-		assert_ok!(TemplateModule::do_something(Origin::signed(1), 42));
-		// Read pallet storage and assert an expected result.
-		assert_eq!(TemplateModule::something(), Some(42));
+		System::set_block_number(1);
+		Key::<Test>::put(1);
+		let upload = |user: u8, file_size_bytes: u128| {
+			let record = UploadRecord { file_size_bytes, ..upload_record(user) };
+			ColdStack::upload_batch(Origin::signed(1), vec![record].try_into().unwrap(), BatchMode::AllOrNothing)
+		};
+
+		assert_ok!(upload(0x11, 100));
+		assert_ok!(upload(0x44, 50));
+		// Re-uploading under the same name counts as another upload.
+		assert_ok!(upload(0x11, 70));
+		assert_eq!(ColdStack::total_file_count(), 3);
+		assert_eq!(ColdStack::total_file_size(), 220);
+
+		// Deleting leaves the totals as they are.
+		assert_ok!(ColdStack::delete(Origin::signed(1), vec![0x11; 20], vec![0x11; 32]));
+		assert_eq!(ColdStack::total_file_count(), 3);
+		assert_eq!(ColdStack::total_file_size(), 220);
+	});
+}
+
+#[test]
+fn permissions_of_reports_held_permissions() {
+	new_test_ext().execute_with(|| {
+		Key::<Test>::put(1);
+		assert_ok!(ColdStack::grant_file_permission(Origin::signed(1), vec![0x44; 20], 2, vec![]));
+
+		assert!(ColdStack::can_upload(&1));
+		assert!(ColdStack::can_upload(&2));
+		assert!(!ColdStack::can_upload(&3));
+		assert_eq!(
+			ColdStack::permissions_of(&2),
+			crate::Permissions { is_admin: false, file: Some(vec![0x44; 20]), billing: None },
+		);
+		assert_eq!(ColdStack::permissions_of(&1), crate::Permissions { is_admin: true, ..Default::default() });
+	});
+}
+
+#[test]
+fn file_info_reads_offchain_storage() {
+	use codec::Encode;
+	use sp_core::offchain::{testing::TestOffchainExt, OffchainDbExt, StorageKind};
+
+	let mut ext = new_test_ext();
+	let (offchain, _) = TestOffchainExt::new();
+	ext.register_extension(OffchainDbExt::new(offchain));
+	ext.execute_with(|| {
+		let file = crate::FileInfo {
+			file_size_bytes: 10,
+			file_contents_hash: vec![0x33; 32],
+			gateway_eth_address: vec![0x22; 20],
+			filenode_eth_address: vec![0x11; 20],
+			file_storage_class: 1,
+			uploaded_at: 5,
+		};
+		sp_io::offchain::local_storage_set(
+			StorageKind::PERSISTENT,
+			&crate::file_key(&[0x44; 20], &[0x55; 32]),
+			&file.encode(),
+		);
+
+		assert_eq!(ColdStack::file_info(vec![0x44; 20], vec![0x55; 32]), Some(file));
+		assert_eq!(ColdStack::file_info(vec![0x44; 20], vec![0x66; 32]), None);
 	});
 }
 
 #[test]
-fn correct_error_for_none_value() {
+fn admin_origin_manages_permissions_and_replaces_key() {
 	new_test_ext().execute_with(|| {
-		// Ensure the expected error is thrown when no value is present.
+		System::set_block_number(1);
+		Key::<Test>::put(1);
+		assert_ok!(ColdStack::grant_file_permission(Origin::root(), vec![0x44; 20], 2, vec![]));
+		assert!(ColdStack::can_upload(&2));
 		assert_noop!(
-			TemplateModule::cause_error(Origin::signed(1)),
-			Error::<Test>::NoneValue
+			ColdStack::revoke_file_permission(Origin::signed(3), vec![0x44; 20]),
+			Error::<Test>::Unauthorized,
 		);
+
+		assert_noop!(ColdStack::set_key(Origin::signed(1), 3), sp_runtime::traits::BadOrigin);
+		assert_ok!(ColdStack::set_key(Origin::root(), 3));
+		assert_eq!(ColdStack::key(), 3);
+		assert_eq!(last_event(), Event::ColdStack(crate::Event::KeyChanged { old_key: 1, new_key: 3 }));
+		assert_ok!(ColdStack::revoke_file_permission(Origin::signed(3), vec![0x44; 20]));
+		assert!(!ColdStack::can_upload(&2));
+	});
+}
+
+#[test]
+fn events_are_deposited_with_address_and_file_topics() {
+	new_test_ext().execute_with(|| {
+		System::set_block_number(1);
+		Key::<Test>::put(1);
+		assert_ok!(ColdStack::grant_file_permission(Origin::signed(1), vec![0x44; 20], 2, vec![]));
+		assert_ok!(ColdStack::delete(Origin::signed(2), vec![0x11; 20], vec![0x22; 32]));
+
+		let record = System::events().pop().expect("an event was deposited");
+		let mut expected = vec![
+			ColdStack::eth_address_topic(&[0x11; 20]),
+			ColdStack::eth_address_topic(&[0x44; 20]),
+			ColdStack::file_topic(&[0x22; 32]),
+		];
+		expected.sort();
+		assert_eq!(record.topics, expected);
+		assert_eq!(
+			System::event_topics(&ColdStack::file_topic(&[0x22; 32])),
+			vec![(1, System::event_count() - 1)],
+		);
+	});
+}
+
+#[test]
+fn every_eth_address_gets_a_topic() {
+	use crate::Event::*;
+
+	let address = |byte| vec![byte; 20];
+	let events: Vec<crate::Event<Test>> = vec![
+		Upload {
+			user_eth_address: address(1),
+			file_name_hash: vec![0x22; 32],
+			file_size_bytes: 10,
+			file_contents_hash: vec![0x33; 32],
+			gateway_eth_address: address(2),
+			filenode_eth_address: address(3),
+			file_storage_class: 0,
+			is_forced: false,
+		},
+		ChangeStatusGateway { gateway_eth_address: address(1), is_active: true },
+		DeleteGateway { gateway_eth_address: address(1) },
+		Download {
+			user_eth_address: address(1),
+			file_name_hash: vec![0x22; 32],
+			file_size_bytes: 10,
+			file_contents_hash: vec![0x33; 32],
+			gateway_eth_address: address(2),
+			filenode_eth_address: address(3),
+		},
+		Delete { user_eth_address: address(1), file_name_hash: vec![0x22; 32], filenode_eth_address: address(3) },
+		Deposit { eth_address: address(1), value: 1 },
+		Withdraw { eth_address: address(1), value: 1 },
+		Transfer { from: address(1), to: address(2), value: 1 },
+		StartStaking { from: address(1), to: address(2), value: 1 },
+		CancelStaking { from: address(1), to: address(2), value: 1 },
+		EndStaking { from: address(1), to: address(2), value: 1 },
+		RewardStaking { from: address(1), eth_address: address(2), value: 1 },
+		FilePermissionGranted { eth_address: address(1), account_id: 1, node_url: vec![] },
+		FilePermissionRevoked { eth_address: address(1), account_id: 1 },
+		BillingPermissionGranted { eth_address: address(1), account_id: 1, node_url: vec![] },
+		BillingPermissionRevoked { eth_address: address(1), account_id: 1 },
+		GatewayNodeRegistered {
+			eth_address: address(1),
+			seed_eth_address: Some(address(2)),
+			storage: 0,
+			node_url: vec![],
+			is_active: true,
+		},
+		UsageRejected { filenode_eth_address: address(1), sequence: 0, error: Error::<Test>::StaleSequence.into() },
+	];
+
+	for event in events {
+		// Every field named `*eth_address`, `from` or `to` of these events
+		// holds an address 1, 2 or 3.
+		let encoded = codec::Encode::encode(&event);
+		let topics = event.topics();
+		for byte in 1..=3 {
+			if encoded.windows(20).any(|window| window == &address(byte)[..]) {
+				assert!(
+					topics.contains(&ColdStack::eth_address_topic(&address(byte))),
+					"{:?} has no topic for address {}",
+					event,
+					byte,
+				);
+			}
+		}
+	}
+}
+
+fn genesis_ext(config: &crate::GenesisConfig<Test>) -> sp_io::TestExternalities {
+	use frame_support::traits::GenesisBuild;
+	let mut storage = frame_system::GenesisConfig::default().build_storage::<Test>().unwrap();
+	GenesisBuild::<Test>::assimilate_storage(config, &mut storage).unwrap();
+	storage.into()
+}
+
+fn genesis_config() -> crate::GenesisConfig<Test> {
+	use crate::{GatewayInfo, GenesisPermission};
+	crate::GenesisConfig {
+		key: 1,
+		total_issuance: 1_000,
+		balances: vec![(vec![0x11; 20], 100), (vec![0x12; 20], 50)],
+		file_permissions: vec![GenesisPermission {
+			eth_address: vec![0x21; 20], account_id: 2, node_url: b"filenode".to_vec(),
+		}],
+		billing_permissions: vec![GenesisPermission {
+			eth_address: vec![0x31; 20], account_id: 3, node_url: b"billing".to_vec(),
+		}],
+		gateways: vec![GatewayInfo {
+			address: vec![0x41; 20], seed_address: None, storage: 2, is_active: true, node_url: b"gateway".to_vec(),
+		}],
+		total_file_count: 1,
+		total_file_size: 10,
+		..Default::default()
+	}
+}
+
+#[test]
+fn genesis_builds_ledger_permissions_gateways_and_totals() {
+	genesis_ext(&genesis_config()).execute_with(|| {
+		use crate::{
+			Balances, BillingPermissionOwnersByAccountId, FilePermissionOwnersByETHAddress, Gateways, NodeURLs,
+		};
+		assert_eq!(ColdStack::locked_funds(), 850);
+		assert_eq!(Balances::<Test>::get(vec![0x12; 20]), 50);
+		assert_eq!(FilePermissionOwnersByETHAddress::<Test>::get(vec![0x21; 20]), 2);
+		assert_eq!(BillingPermissionOwnersByAccountId::<Test>::get(3), vec![0x31; 20]);
+		assert!(Gateways::<Test>::contains_key(vec![0x41; 20]));
+		assert_eq!(NodeURLs::<Test>::get(vec![0x41; 20]), b"gateway".to_vec());
+		assert_eq!(ColdStack::total_file_count(), 1);
+		assert_eq!(ColdStack::total_file_size(), 10);
 	});
 }
+
+#[test]
+fn genesis_rejects_inconsistent_config() {
+	let mut config = genesis_config();
+	config.locked_funds = Some(900);
+	assert!(config.validate().unwrap_err().contains("must equal total issuance"));
+
+	let mut config = genesis_config();
+	config.balances.push((vec![0x11; 20], 1));
+	assert!(config.validate().unwrap_err().contains("Duplicate balance"));
+
+	let mut config = genesis_config();
+	config.gateways[0].address = vec![0x41; 19];
+	assert!(config.validate().is_err());
+}
+
+#[test]
+#[should_panic(expected = "Invalid ColdStack genesis")]
+fn genesis_build_panics_on_invalid_config() {
+	let mut config = genesis_config();
+	config.total_issuance = 100;
+	genesis_ext(&config);
+}
@@ -0,0 +1,148 @@
+//! Time-locked enactment: `ProposeOrigin`, typically a council threshold,
+//! schedules a call that the scheduler dispatches with the root origin after
+//! `EnactmentDelay` blocks. Until then `CancelOrigin` may cancel it.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+pub use pallet::*;
+
+#[cfg(test)]
+mod mock;
+
+#[cfg(test)]
+mod tests;
+
+#[frame_support::pallet]
+pub mod pallet {
+  use frame_support::{
+    dispatch::{DispatchResult, Dispatchable, GetDispatchInfo, PostDispatchInfo},
+    pallet_prelude::*,
+    traits::schedule::{DispatchTime, Named as ScheduleNamed, HARD_DEADLINE},
+  };
+  use frame_system::pallet_prelude::*;
+  use codec::Encode;
+  use sp_runtime::traits::{Hash, Saturating};
+  use sp_std::{boxed::Box, vec::Vec};
+
+  #[pallet::config]
+  pub trait Config: frame_system::Config {
+    type Event: From<Event<Self>> + IsType<<Self as frame_system::Config>::Event>;
+
+    /// The runtime call type.
+    type Call: Parameter
+      + Dispatchable<Origin = Self::Origin, PostInfo = PostDispatchInfo>
+      + GetDispatchInfo
+      + From<Call<Self>>;
+
+    /// The caller origin, as the scheduler stores it.
+    type PalletsOrigin: From<frame_system::RawOrigin<Self::AccountId>>;
+
+    type Scheduler: ScheduleNamed<Self::BlockNumber, <Self as Config>::Call, Self::PalletsOrigin>;
+
+    /// Origin allowed to schedule calls.
+    type ProposeOrigin: EnsureOrigin<Self::Origin>;
+
+    /// Origin allowed to cancel scheduled calls.
+    type CancelOrigin: EnsureOrigin<Self::Origin>;
+
+    /// Blocks between scheduling a call and its dispatch.
+    #[pallet::constant]
+    type EnactmentDelay: Get<Self::BlockNumber>;
+  }
+
+  #[pallet::pallet]
+  #[pallet::generate_store(pub(super) trait Store)]
+  pub struct Pallet<T>(_);
+
+  /// Index of the next scheduled call.
+  #[pallet::storage]
+  #[pallet::getter(fn next_index)]
+  pub type NextIndex<T: Config> = StorageValue<_, u32, ValueQuery>;
+
+  /// Hash of each call waiting for its dispatch, and the block it is due at.
+  #[pallet::storage]
+  #[pallet::getter(fn pending)]
+  pub type Pending<T: Config> = StorageMap<_, Twox64Concat, u32, (T::Hash, T::BlockNumber)>;
+
+  #[pallet::event]
+  #[pallet::metadata(T::Hash = "Hash", T::BlockNumber = "BlockNumber")]
+  #[pallet::generate_deposit(pub(super) fn deposit_event)]
+  pub enum Event<T: Config> {
+    /// A call is dispatched at block `when` unless cancelled.
+    /// \[index, call_hash, when\]
+    Scheduled { index: u32, call_hash: T::Hash, when: T::BlockNumber },
+    /// A scheduled call was cancelled. \[index\]
+    Cancelled { index: u32 },
+    /// A scheduled call was dispatched. \[index, result\]
+    Enacted { index: u32, result: DispatchResult },
+  }
+
+  #[pallet::error]
+  pub enum Error<T> {
+    /// No call is scheduled under this index.
+    UnknownIndex,
+    /// The call does not match the scheduled call.
+    CallMismatch,
+    /// The scheduler refused the call.
+    ScheduleFailed,
+  }
+
+  #[pallet::hooks]
+  impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {}
+
+  #[pallet::call]
+  impl<T: Config> Pallet<T> {
+    /// Dispatch `call` with the root origin after `EnactmentDelay` blocks.
+    #[pallet::weight(T::DbWeight::get().reads_writes(2, 3))]
+    pub fn schedule(origin: OriginFor<T>, call: Box<<T as Config>::Call>) -> DispatchResult {
+      T::ProposeOrigin::ensure_origin(origin)?;
+      let index = NextIndex::<T>::get();
+      let call_hash = T::Hashing::hash_of(&call);
+      let when = frame_system::Pallet::<T>::block_number().saturating_add(T::EnactmentDelay::get());
+
+      T::Scheduler::schedule_named(
+        Self::schedule_id(index),
+        DispatchTime::At(when),
+        None,
+        HARD_DEADLINE,
+        frame_system::RawOrigin::Root.into(),
+        Call::enact(index, call).into(),
+      ).map_err(|()| Error::<T>::ScheduleFailed)?;
+
+      NextIndex::<T>::put(index.wrapping_add(1));
+      Pending::<T>::insert(index, (call_hash, when));
+      Self::deposit_event(Event::Scheduled { index, call_hash, when });
+      Ok(())
+    }
+
+    /// Cancel the scheduled call `index`.
+    #[pallet::weight(T::DbWeight::get().reads_writes(2, 2))]
+    pub fn cancel(origin: OriginFor<T>, #[pallet::compact] index: u32) -> DispatchResult {
+      T::CancelOrigin::ensure_origin(origin)?;
+      ensure!(Pending::<T>::contains_key(index), Error::<T>::UnknownIndex);
+      T::Scheduler::cancel_named(Self::schedule_id(index)).map_err(|()| Error::<T>::UnknownIndex)?;
+      Pending::<T>::remove(index);
+      Self::deposit_event(Event::Cancelled { index });
+      Ok(())
+    }
+
+    /// Dispatch the scheduled call `index`. Dispatched by the scheduler.
+    #[pallet::weight(call.get_dispatch_info().weight.saturating_add(T::DbWeight::get().reads_writes(1, 1)))]
+    pub fn enact(origin: OriginFor<T>, index: u32, call: Box<<T as Config>::Call>) -> DispatchResult {
+      ensure_root(origin)?;
+      let (call_hash, _) = Pending::<T>::get(index).ok_or(Error::<T>::UnknownIndex)?;
+      ensure!(call_hash == T::Hashing::hash_of(&call), Error::<T>::CallMismatch);
+      Pending::<T>::remove(index);
+
+      let result = call.dispatch(frame_system::RawOrigin::Root.into()).map(|_| ()).map_err(|e| e.error);
+      Self::deposit_event(Event::Enacted { index, result });
+      Ok(())
+    }
+  }
+
+  impl<T: Config> Pallet<T> {
+    fn schedule_id(index: u32) -> Vec<u8> {
+      (b"timelock", index).encode()
+    }
+  }
+}
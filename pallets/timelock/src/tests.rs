@@ -0,0 +1,72 @@
+use crate::{mock::*, Error, Event as TimelockEvent};
+use frame_support::{assert_noop, assert_ok};
+use sp_runtime::{traits::{BadOrigin, BlakeTwo256, Hash}, DispatchError};
+
+fn set_storage_call(value: u8) -> Box<Call> {
+	Box::new(Call::System(frame_system::Call::set_storage(vec![(b"key".to_vec(), vec![value])])))
+}
+
+fn timelock_events() -> Vec<TimelockEvent<Test>> {
+	System::events().into_iter().filter_map(|record| match record.event {
+		Event::Timelock(event) => Some(event),
+		_ => None,
+	}).collect()
+}
+
+#[test]
+fn scheduled_call_is_dispatched_as_root_after_delay() {
+	new_test_ext().execute_with(|| {
+		assert_noop!(Timelock::schedule(Origin::signed(1), set_storage_call(1)), BadOrigin);
+
+		let call = set_storage_call(1);
+		let call_hash = BlakeTwo256::hash_of(&call);
+		assert_ok!(Timelock::schedule(Origin::root(), call));
+		assert_eq!(Timelock::pending(0), Some((call_hash, 11)));
+		assert_eq!(timelock_events(), vec![TimelockEvent::Scheduled { index: 0, call_hash, when: 11 }]);
+
+		run_to_block(10);
+		assert_eq!(sp_io::storage::get(b"key"), None);
+
+		run_to_block(11);
+		assert_eq!(sp_io::storage::get(b"key"), Some(vec![1]));
+		assert_eq!(Timelock::pending(0), None);
+		assert!(timelock_events().contains(&TimelockEvent::Enacted { index: 0, result: Ok(()) }));
+	});
+}
+
+#[test]
+fn cancelled_call_is_not_dispatched() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Timelock::schedule(Origin::root(), set_storage_call(1)));
+		assert_ok!(Timelock::schedule(Origin::root(), set_storage_call(2)));
+		assert_eq!(Timelock::next_index(), 2);
+
+		assert_noop!(Timelock::cancel(Origin::none(), 0), BadOrigin);
+		assert_ok!(Timelock::cancel(Origin::signed(1), 1));
+		assert_eq!(Timelock::pending(1), None);
+		assert_noop!(Timelock::cancel(Origin::signed(1), 1), Error::<Test>::UnknownIndex);
+
+		run_to_block(11);
+		assert_eq!(sp_io::storage::get(b"key"), Some(vec![1]));
+		assert!(!timelock_events().iter().any(|e| matches!(e, TimelockEvent::Enacted { index: 1, .. })));
+	});
+}
+
+#[test]
+fn enact_only_dispatches_the_scheduled_call() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Timelock::schedule(Origin::root(), set_storage_call(1)));
+
+		assert_noop!(Timelock::enact(Origin::signed(1), 0, set_storage_call(1)), BadOrigin);
+		assert_noop!(Timelock::enact(Origin::root(), 0, set_storage_call(2)), Error::<Test>::CallMismatch);
+		assert_noop!(Timelock::enact(Origin::root(), 1, set_storage_call(1)), Error::<Test>::UnknownIndex);
+
+		// Failures of the call itself are reported, not returned.
+		assert_ok!(Timelock::schedule(Origin::root(), Box::new(Call::Timelock(crate::Call::cancel(7)))));
+		run_to_block(11);
+		assert!(timelock_events().contains(&TimelockEvent::Enacted {
+			index: 1,
+			result: Err(DispatchError::BadOrigin),
+		}));
+	});
+}
@@ -7,10 +7,12 @@
 include!(concat!(env!("OUT_DIR"), "/wasm_binary.rs"));
 
 use sp_std::prelude::*;
-use sp_core::{crypto::KeyTypeId, OpaqueMetadata};
+use sp_core::{crypto::KeyTypeId, u32_trait::{_1, _2, _3}, OpaqueMetadata};
 use sp_runtime::{
 	ApplyExtrinsicResult, generic, create_runtime_str, impl_opaque_keys, MultiSignature,
-	transaction_validity::{TransactionValidity, TransactionSource},
+	transaction_validity::{
+		TransactionValidity, TransactionSource, TransactionPriority, TransactionLongevity,
+	},
 };
 use sp_runtime::traits::{
 	BlakeTwo256, Block as BlockT, AccountIdLookup, Verify, IdentifyAccount, NumberFor,
@@ -40,6 +42,8 @@ pub use frame_support::{
 	},
 };
 use pallet_transaction_payment::CurrencyAdapter;
+use pallet_session::historical as pallet_session_historical;
+use sp_staking::{offence::{OffenceDetails, OnOffenceHandler}, SessionIndex};
 
 /// Import the template pallet.
 pub use pallet_template;
@@ -83,6 +87,7 @@ pub mod opaque {
 		pub struct SessionKeys {
 			pub aura: Aura,
 			pub grandpa: Grandpa,
+			pub liveness: Liveness,
 		}
 	}
 }
@@ -99,7 +104,7 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
 	//   `spec_version`, and `authoring_version` are the same between Wasm and native.
 	// This value is set to 100 to notify Polkadot-JS App (https://polkadot.js.org/apps) to use
 	//   the compatible custom types.
-	spec_version: 110,
+	spec_version: 111,
 	impl_version: 1,
 	apis: RUNTIME_API_VERSIONS,
 	transaction_version: 2,
@@ -203,11 +208,16 @@ impl pallet_aura::Config for Runtime {
 	type AuthorityId = AuraId;
 }
 
+parameter_types! {
+	/// Blocks an equivocation report stays valid in the transaction pool.
+	pub const ReportLongevity: u64 = DAYS as u64;
+}
+
 impl pallet_grandpa::Config for Runtime {
 	type Event = Event;
 	type Call = Call;
 
-	type KeyOwnerProofSystem = ();
+	type KeyOwnerProofSystem = Historical;
 
 	type KeyOwnerProof =
 		<Self::KeyOwnerProofSystem as KeyOwnerProofSystem<(KeyTypeId, GrandpaId)>>::Proof;
@@ -217,11 +227,20 @@ impl pallet_grandpa::Config for Runtime {
 		GrandpaId,
 	)>>::IdentificationTuple;
 
-	type HandleEquivocation = ();
+	type HandleEquivocation =
+		pallet_grandpa::EquivocationHandler<Self::KeyOwnerIdentification, Offences, ReportLongevity>;
 
 	type WeightInfo = ();
 }
 
+impl<C> frame_system::offchain::SendTransactionTypes<C> for Runtime
+where
+	Call: From<C>,
+{
+	type Extrinsic = UncheckedExtrinsic;
+	type OverarchingCall = Call;
+}
+
 parameter_types! {
 	pub const MinimumPeriod: u64 = SLOT_DURATION / 2;
 }
@@ -269,9 +288,45 @@ impl pallet_sudo::Config for Runtime {
 	type Call = Call;
 }
 
+parameter_types! {
+	pub const ColdStackMaxBatchSize: u32 = 256;
+	pub const ColdStackRequestIdRetention: BlockNumber = 7 * DAYS;
+	pub const ColdStackMaxCallsPerBlock: u32 = 64;
+}
+
 /// Configure the pallet-template in pallets/template.
 impl pallet_template::Config for Runtime {
 	type Event = Event;
+	type MaxBatchSize = ColdStackMaxBatchSize;
+	type WeightInfo = pallet_template::weights::SubstrateWeight<Runtime>;
+	type RequestIdRetention = ColdStackRequestIdRetention;
+	type Public = <Signature as Verify>::Signer;
+	type Signature = Signature;
+	type UnsignedValidity = ColdStackUnsignedValidity;
+	type MaxCallsPerBlock = ColdStackMaxCallsPerBlock;
+	type AdminOrigin = GovernanceOrigin;
+}
+
+/// Pool parameters of usage reports that filenodes submit unsigned.
+pub struct ColdStackUnsignedValidity;
+
+impl pallet_template::UnsignedValidity for ColdStackUnsignedValidity {
+	fn priority(kind: pallet_template::UsageKind) -> TransactionPriority {
+		use pallet_template::UsageKind;
+		// Downloads only feed billing and may lag behind uploads and deletes.
+		match kind {
+			UsageKind::Upload | UsageKind::Delete => TransactionPriority::max_value() / 2,
+			UsageKind::Download => TransactionPriority::max_value() / 4,
+		}
+	}
+
+	fn longevity(kind: pallet_template::UsageKind) -> TransactionLongevity {
+		use pallet_template::UsageKind;
+		match kind {
+			UsageKind::Upload | UsageKind::Delete => HOURS as TransactionLongevity,
+			UsageKind::Download => (10 * MINUTES) as TransactionLongevity,
+		}
+	}
 }
 
 // Create the runtime by composing the FRAME pallets that were previously configured.
@@ -281,19 +336,27 @@ construct_runtime!(
 		NodeBlock = opaque::Block,
 		UncheckedExtrinsic = UncheckedExtrinsic
 	{
-		System: frame_system::{Pallet, Call, Config, Storage, Event<T>},
-		RandomnessCollectiveFlip: pallet_randomness_collective_flip::{Pallet, Storage},
-		Timestamp: pallet_timestamp::{Pallet, Call, Storage, Inherent},
-		Balances: pallet_balances::{Pallet, Call, Storage, Config<T>, Event<T>},
-    Session: pallet_session::{Pallet, Call, Storage, Event, Config<T>},
-    ValidatorSet: validatorset::{Pallet, Call, Storage, Event<T>, Config<T>},
-		Aura: pallet_aura::{Pallet, Config<T>},
-		Grandpa: pallet_grandpa::{Pallet, Call, Storage, Config, Event},
-		TransactionPayment: pallet_transaction_payment::{Pallet, Storage},
-		Sudo: pallet_sudo::{Pallet, Call, Config<T>, Storage, Event<T>},
-    Scheduler: pallet_scheduler::{Pallet, Call, Storage, Event<T>},
+		// Indices are explicit, so that removing a pallet, such as `Sudo`, does not
+		// change how the calls and events of the others are encoded.
+		System: frame_system::{Pallet, Call, Config, Storage, Event<T>} = 0,
+		RandomnessCollectiveFlip: pallet_randomness_collective_flip::{Pallet, Storage} = 1,
+		Timestamp: pallet_timestamp::{Pallet, Call, Storage, Inherent} = 2,
+		Balances: pallet_balances::{Pallet, Call, Storage, Config<T>, Event<T>} = 3,
+    Session: pallet_session::{Pallet, Call, Storage, Event, Config<T>} = 4,
+    ValidatorSet: validatorset::{Pallet, Call, Storage, Event<T>, Config<T>} = 5,
+		Aura: pallet_aura::{Pallet, Config<T>} = 6,
+		Grandpa: pallet_grandpa::{Pallet, Call, Storage, Config, Event, ValidateUnsigned} = 7,
+		TransactionPayment: pallet_transaction_payment::{Pallet, Storage} = 8,
+		Sudo: pallet_sudo::{Pallet, Call, Config<T>, Storage, Event<T>} = 9,
+    Scheduler: pallet_scheduler::{Pallet, Call, Storage, Event<T>} = 10,
 		// Include the custom logic from the pallet-template in the runtime.
-		ColdStack: pallet_template::{Pallet, Call, Storage, Event<T>, Config<T>},
+		ColdStack: pallet_template::{Pallet, Call, Storage, Event<T>, Config<T>, ValidateUnsigned} = 11,
+		Historical: pallet_session_historical::{Pallet} = 12,
+		Offences: pallet_offences::{Pallet, Storage, Event} = 13,
+		Liveness: pallet_liveness::{Pallet, Call, Storage, Event<T>, Config, ValidateUnsigned} = 14,
+		Candidacy: pallet_candidacy::{Pallet, Call, Storage, Event<T>} = 15,
+		Council: pallet_collective::<Instance1>::{Pallet, Call, Storage, Origin<T>, Event<T>, Config<T>} = 16,
+		Timelock: pallet_timelock::{Pallet, Call, Storage, Event<T>} = 17,
 	}
 );
 
@@ -311,7 +374,8 @@ pub type SignedExtra = (
 	frame_system::CheckEra<Runtime>,
 	frame_system::CheckNonce<Runtime>,
 	frame_system::CheckWeight<Runtime>,
-	pallet_transaction_payment::ChargeTransactionPayment<Runtime>
+	pallet_transaction_payment::ChargeTransactionPayment<Runtime>,
+	pallet_template::CheckColdStackPermission<Runtime>,
 );
 /// Unchecked extrinsic type as expected by this runtime.
 pub type UncheckedExtrinsic = generic::UncheckedExtrinsic<Address, Call, Signature, SignedExtra>;
@@ -322,6 +386,7 @@ pub type Executive = frame_executive::Executive<
 	frame_system::ChainContext<Runtime>,
 	Runtime,
 	AllPallets,
+	LivenessUpgrade,
 >;
 
 impl_runtime_apis! {
@@ -409,23 +474,29 @@ impl_runtime_apis! {
 		}
 
 		fn submit_report_equivocation_unsigned_extrinsic(
-			_equivocation_proof: fg_primitives::EquivocationProof<
+			equivocation_proof: fg_primitives::EquivocationProof<
 				<Block as BlockT>::Hash,
 				NumberFor<Block>,
 			>,
-			_key_owner_proof: fg_primitives::OpaqueKeyOwnershipProof,
+			key_owner_proof: fg_primitives::OpaqueKeyOwnershipProof,
 		) -> Option<()> {
-			None
+			let key_owner_proof = key_owner_proof.decode()?;
+
+			Grandpa::submit_unsigned_equivocation_report(
+				equivocation_proof,
+				key_owner_proof,
+			)
 		}
 
 		fn generate_key_ownership_proof(
 			_set_id: fg_primitives::SetId,
-			_authority_id: GrandpaId,
+			authority_id: GrandpaId,
 		) -> Option<fg_primitives::OpaqueKeyOwnershipProof> {
-			// NOTE: this is the only implementation possible since we've
-			// defined our key owner proof type as a bottom type (i.e. a type
-			// with no values).
-			None
+			use codec::Encode;
+
+			Historical::prove((fg_primitives::KEY_TYPE, authority_id))
+				.map(|p| p.encode())
+				.map(fg_primitives::OpaqueKeyOwnershipProof::new)
 		}
 	}
 
@@ -450,6 +521,50 @@ impl_runtime_apis! {
 		}
 	}
 
+	impl pallet_template_rpc_runtime_api::ColdStackApi<Block, AccountId> for Runtime {
+		fn processed_requests(
+			filenode_eth_address: Vec<u8>,
+			request_ids: Vec<pallet_template::RequestId>,
+		) -> Vec<pallet_template::RequestId> {
+			ColdStack::processed_requests(filenode_eth_address, request_ids)
+		}
+
+		fn balance_of(eth_address: Vec<u8>) -> u128 {
+			ColdStack::balance_of(eth_address)
+		}
+
+		fn gateways() -> Vec<pallet_template::GatewayInfo> {
+			ColdStack::gateways()
+		}
+
+		fn stats() -> pallet_template::Stats {
+			ColdStack::stats()
+		}
+
+		fn file_info(
+			user_eth_address: Vec<u8>,
+			file_name_hash: Vec<u8>,
+		) -> Option<pallet_template::FileInfo> {
+			ColdStack::file_info(user_eth_address, file_name_hash)
+		}
+
+		fn file_permission_owner(eth_address: Vec<u8>) -> Option<AccountId> {
+			ColdStack::file_permission_owner(eth_address)
+		}
+
+		fn billing_permission_owner(eth_address: Vec<u8>) -> Option<AccountId> {
+			ColdStack::billing_permission_owner(eth_address)
+		}
+
+		fn can_upload(account: AccountId) -> bool {
+			ColdStack::can_upload(&account)
+		}
+
+		fn permissions_of(account: AccountId) -> pallet_template::Permissions {
+			ColdStack::permissions_of(&account)
+		}
+	}
+
 	#[cfg(feature = "runtime-benchmarks")]
 	impl frame_benchmarking::Benchmark<Block> for Runtime {
 		fn dispatch_benchmark(
@@ -511,18 +626,306 @@ impl pallet_scheduler::Config for Runtime {
 
 impl validatorset::Config for Runtime {
     type Event = Event;
-    type AddRemoveOrigin = EnsureRoot<AccountId>;
+    type AddRemoveOrigin = GovernanceOrigin;
+}
+
+parameter_types! {
+    /// Sessions also end this often, so validator liveness is judged even
+    /// while the validator set does not change.
+    pub const SessionPeriod: BlockNumber = HOURS;
+    pub const SessionOffset: BlockNumber = 0;
+}
+
+/// Ends a session when `ValidatorSet` has a change queued, and at least every
+/// `SessionPeriod` blocks.
+pub struct SessionRotation;
+
+impl pallet_session::ShouldEndSession<BlockNumber> for SessionRotation {
+    fn should_end_session(now: BlockNumber) -> bool {
+        <ValidatorSet as pallet_session::ShouldEndSession<BlockNumber>>::should_end_session(now)
+            || pallet_session::PeriodicSessions::<SessionPeriod, SessionOffset>::should_end_session(now)
+    }
 }
 
 impl pallet_session::Config for Runtime {
     type SessionHandler = <opaque::SessionKeys as OpaqueKeys>::KeyTypeIdProviders;
-    type ShouldEndSession = ValidatorSet;
-    type SessionManager = ValidatorSet;
+    type ShouldEndSession = SessionRotation;
+    type SessionManager = pallet_session_historical::NoteHistoricalRoot<Self, ValidatorSetHistory>;
     type Event = Event;
     type Keys = opaque::SessionKeys;
-    type NextSessionRotation = ValidatorSet;
+    type NextSessionRotation = pallet_session::PeriodicSessions<SessionPeriod, SessionOffset>;
     type ValidatorId = <Self as frame_system::Config>::AccountId;
     type ValidatorIdOf = validatorset::ValidatorOf<Self>;
     type DisabledValidatorsThreshold = ();
     type WeightInfo = ();
 }
+
+/// Whether `who` registered session keys with `Session::set_keys`.
+/// `pallet_session` has no public getter for `NextKeys`, so its storage is
+/// read directly.
+pub fn has_next_session_keys(who: &AccountId) -> bool {
+    use codec::Encode;
+    use frame_support::{StorageHasher, Twox64Concat};
+
+    frame_support::storage::migration::have_storage_value(
+        b"Session",
+        b"NextKeys",
+        &Twox64Concat::hash(&who.encode()),
+    )
+}
+
+impl pallet_session_historical::Config for Runtime {
+    type FullIdentification = Self::ValidatorId;
+    type FullIdentificationOf = validatorset::ValidatorOf<Self>;
+}
+
+/// `ValidatorSet` as a session manager that also records each session's
+/// validators, identified by their account, for key ownership proofs.
+pub struct ValidatorSetHistory;
+
+impl pallet_session_historical::SessionManager<AccountId, AccountId> for ValidatorSetHistory {
+    fn new_session(new_index: SessionIndex) -> Option<Vec<(AccountId, AccountId)>> {
+        <ValidatorSet as pallet_session::SessionManager<AccountId>>::new_session(new_index)
+            .map(|validators| validators.into_iter().map(|v| (v.clone(), v)).collect())
+    }
+
+    fn start_session(start_index: SessionIndex) {
+        <ValidatorSet as pallet_session::SessionManager<AccountId>>::start_session(start_index)
+    }
+
+    fn end_session(end_index: SessionIndex) {
+        <ValidatorSet as pallet_session::SessionManager<AccountId>>::end_session(end_index)
+    }
+}
+
+impl pallet_offences::Config for Runtime {
+    type Event = Event;
+    type IdentificationTuple = pallet_session_historical::IdentificationTuple<Self>;
+    type OnOffenceHandler = RemoveOffenders;
+}
+
+parameter_types! {
+    /// Validators are not removed from `ValidatorSet` below this many.
+    pub const MinValidators: u32 = 1;
+}
+
+/// Removes validators from `ValidatorSet`, which takes effect at the next
+/// session. Refuses when that would leave fewer than `MinValidators`
+/// validators, so the chain keeps authoring.
+pub struct ValidatorRemoval;
+
+impl ValidatorRemoval {
+    /// Validators `ValidatorSet` will start the next session with, including
+    /// removals made earlier in this block.
+    fn queued_validators() -> Vec<AccountId> {
+        ValidatorSet::validators().unwrap_or_default()
+    }
+}
+
+impl pallet_liveness::RemoveValidator<AccountId> for ValidatorRemoval {
+    fn remove_validator(who: &AccountId) -> sp_runtime::DispatchResult {
+        let validators = Self::queued_validators();
+        if !validators.contains(who) {
+            return Err(sp_runtime::DispatchError::Other("Not in the validator set"))
+        }
+        if validators.len() as u32 <= MinValidators::get() {
+            return Err(sp_runtime::DispatchError::Other("Too few validators left"))
+        }
+        ValidatorSet::remove_validator(frame_system::RawOrigin::Root.into(), who.clone())
+            .map(|_| ())
+            .map_err(Into::into)
+    }
+}
+
+/// Punishes validators reported to `Offences`: burns the bond of those that
+/// joined through `Candidacy`, then removes them through `ValidatorRemoval`.
+/// The bond is burned even if removal is refused.
+pub struct RemoveOffenders;
+
+impl OnOffenceHandler<AccountId, pallet_session_historical::IdentificationTuple<Runtime>, Weight>
+    for RemoveOffenders
+{
+    fn on_offence(
+        offenders: &[OffenceDetails<AccountId, pallet_session_historical::IdentificationTuple<Runtime>>],
+        _slash_fraction: &[Perbill],
+        _session: SessionIndex,
+    ) -> Weight {
+        use pallet_liveness::RemoveValidator;
+
+        for details in offenders {
+            let (offender, _) = &details.offender;
+            Candidacy::slash(offender);
+            if let Err(e) = ValidatorRemoval::remove_validator(offender) {
+                log::warn!(
+                    target: "runtime::offences",
+                    "Unable to remove offender {:?}: {:?}",
+                    offender, e,
+                );
+            }
+        }
+        RocksDbWeight::get().reads_writes(5, 5).saturating_mul(offenders.len() as Weight)
+    }
+}
+
+parameter_types! {
+    pub const LivenessUnsignedPriority: TransactionPriority = TransactionPriority::max_value() / 2;
+    /// Sessions ended early by a validator set change are not judged.
+    pub const MinSessionLength: BlockNumber = 10 * MINUTES;
+    pub const LivenessHistoryDepth: SessionIndex = 84;
+}
+
+impl pallet_liveness::Config for Runtime {
+    type Event = Event;
+    type AuthorityId = pallet_liveness::sr25519::AuthorityId;
+    type ValidatorSet = Session;
+    /// Being offline is not misbehaviour, so the bond is left alone.
+    type ValidatorRemoval = ValidatorRemoval;
+    type ConfigOrigin = GovernanceOrigin;
+    type UnsignedPriority = LivenessUnsignedPriority;
+    type MinSessionLength = MinSessionLength;
+    type HistoryDepth = LivenessHistoryDepth;
+}
+
+impl_opaque_keys! {
+    /// Session keys before `Liveness` got its own key.
+    pub struct LegacySessionKeys {
+        pub aura: Aura,
+        pub grandpa: Grandpa,
+    }
+}
+
+/// Brings chains started without `Liveness` in line with its genesis config:
+/// validators get a liveness key equal to their Aura key, and the offline
+/// threshold is set to 3 with removal off. Runs once, while `OfflineThreshold`
+/// is unset.
+pub struct LivenessUpgrade;
+
+impl frame_support::traits::OnRuntimeUpgrade for LivenessUpgrade {
+    fn on_runtime_upgrade() -> Weight {
+        if pallet_liveness::OfflineThreshold::<Runtime>::exists() {
+            return RocksDbWeight::get().reads(1)
+        }
+        Session::upgrade_keys::<LegacySessionKeys, _>(|_, old| opaque::SessionKeys {
+            liveness: sp_core::sr25519::Public::from(old.aura.clone()).into(),
+            aura: old.aura,
+            grandpa: old.grandpa,
+        });
+        pallet_liveness::OfflineThreshold::<Runtime>::put(3);
+        pallet_liveness::RemoveOffline::<Runtime>::put(false);
+        BlockWeights::get().max_block
+    }
+}
+
+parameter_types! {
+    pub const CandidacyBond: Balance = 1_000_000_000_000_000;
+    pub const MaxCandidates: u32 = 32;
+    /// A withdrawn validator keeps validating for up to two sessions; the
+    /// rest of the day is left for its equivocations to be reported.
+    pub const UnbondingPeriod: BlockNumber = DAYS;
+}
+
+/// `ValidatorSet` as seen by `Candidacy`.
+pub struct CandidacyValidators;
+
+impl pallet_candidacy::ValidatorRegistry<AccountId> for CandidacyValidators {
+    fn has_session_keys(who: &AccountId) -> bool {
+        has_next_session_keys(who)
+    }
+
+    fn is_validator(who: &AccountId) -> bool {
+        ValidatorRemoval::queued_validators().contains(who)
+    }
+
+    fn add_validator(who: &AccountId) -> sp_runtime::DispatchResult {
+        ValidatorSet::add_validator(frame_system::RawOrigin::Root.into(), who.clone())
+            .map(|_| ())
+            .map_err(Into::into)
+    }
+
+    fn remove_validator(who: &AccountId) -> sp_runtime::DispatchResult {
+        <ValidatorRemoval as pallet_liveness::RemoveValidator<AccountId>>::remove_validator(who)
+    }
+}
+
+impl pallet_candidacy::Config for Runtime {
+    type Event = Event;
+    type Currency = Balances;
+    type Validators = CandidacyValidators;
+    type ApproveOrigin = GovernanceOrigin;
+    type CandidacyBond = CandidacyBond;
+    type MaxCandidates = MaxCandidates;
+    type UnbondingPeriod = UnbondingPeriod;
+}
+
+parameter_types! {
+    pub const CouncilMotionDuration: BlockNumber = DAYS;
+    pub const CouncilMaxProposals: u32 = 100;
+    pub const CouncilMaxMembers: u32 = 16;
+}
+
+pub type CouncilCollective = pallet_collective::Instance1;
+
+impl pallet_collective::Config<CouncilCollective> for Runtime {
+    type Origin = Origin;
+    type Proposal = Call;
+    type Event = Event;
+    type MotionDuration = CouncilMotionDuration;
+    type MaxProposals = CouncilMaxProposals;
+    type MaxMembers = CouncilMaxMembers;
+    type DefaultVote = pallet_collective::PrimeDefaultVote;
+    type WeightInfo = pallet_collective::weights::SubstrateWeight<Runtime>;
+}
+
+/// Root only. The council reaches privileged calls through `Timelock`, which
+/// dispatches them as root once `EnactmentDelay` has passed; no council
+/// majority skips the delay.
+pub type GovernanceOrigin = EnsureRoot<AccountId>;
+
+parameter_types! {
+    pub const EnactmentDelay: BlockNumber = 2 * DAYS;
+}
+
+impl pallet_timelock::Config for Runtime {
+    type Event = Event;
+    type Call = Call;
+    type PalletsOrigin = OriginCaller;
+    type Scheduler = Scheduler;
+    /// Two thirds of the council schedule a call.
+    type ProposeOrigin = frame_system::EnsureOneOf<
+        AccountId,
+        EnsureRoot<AccountId>,
+        pallet_collective::EnsureProportionAtLeast<_2, _3, AccountId, CouncilCollective>,
+    >;
+    /// A third of the council cancel it before it is dispatched.
+    type CancelOrigin = frame_system::EnsureOneOf<
+        AccountId,
+        EnsureRoot<AccountId>,
+        pallet_collective::EnsureProportionAtLeast<_1, _3, AccountId, CouncilCollective>,
+    >;
+    type EnactmentDelay = EnactmentDelay;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use frame_support::assert_ok;
+    use sp_core::{ed25519, sr25519};
+
+    #[test]
+    fn has_next_session_keys_reads_session_storage() {
+        sp_io::TestExternalities::default().execute_with(|| {
+            let who = AccountId::from([1; 32]);
+            let keys = opaque::SessionKeys {
+                aura: sr25519::Public::from_raw([1; 32]).into(),
+                grandpa: ed25519::Public::from_raw([2; 32]).into(),
+                liveness: sr25519::Public::from_raw([3; 32]).into(),
+            };
+            assert!(!has_next_session_keys(&who));
+
+            frame_system::Pallet::<Runtime>::inc_providers(&who);
+            assert_ok!(Session::set_keys(Origin::signed(who.clone()), keys, vec![]));
+            assert!(has_next_session_keys(&who));
+            assert!(!has_next_session_keys(&AccountId::from([2; 32])));
+        });
+    }
+}
@@ -0,0 +1,21 @@
+#!/usr/bin/env bash
+# Regenerates pallets/template/src/weights.rs from the pallet benchmarks.
+# Run it on validator-grade hardware whenever a benchmarked call or the
+# storage it touches changes.
+set -e
+
+cd "$(dirname "$0")/.."
+
+cargo build --release --manifest-path node/Cargo.toml --features runtime-benchmarks
+
+./target/release/node-template benchmark \
+   --chain dev \
+   --execution wasm \
+   --wasm-execution compiled \
+   --pallet pallet_template \
+   --extrinsic '*' \
+   --steps 50 \
+   --repeat 20 \
+   --heap-pages 4096 \
+   --template .maintain/frame-weight-template.hbs \
+   --output pallets/template/src/weights.rs
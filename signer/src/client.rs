@@ -0,0 +1,267 @@
+//! The node's end: a keystore that forwards every call to the daemon.
+
+use std::{
+	io::{self, Write},
+	net::{TcpStream, ToSocketAddrs},
+	os::unix::net::UnixStream,
+	thread,
+	time::Duration,
+};
+
+use async_trait::async_trait;
+use codec::{Decode, Encode};
+use futures::channel::oneshot;
+use log::warn;
+use sp_core::{
+	crypto::{CryptoTypeId, CryptoTypePublicPair, KeyTypeId},
+	ecdsa, ed25519, sr25519,
+};
+use sp_keystore::{
+	vrf::{VRFSignature, VRFTranscriptData},
+	CryptoStore, Error, SyncCryptoStore,
+};
+
+use crate::{http, Endpoint, RemoteError, Request};
+
+/// Time to wait for the daemon to accept a connection or answer a call.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Keystore whose keys are held by a signer daemon.
+///
+/// `SyncCryptoStore` calls block until the daemon answers; `CryptoStore` calls
+/// wait for it on a thread of their own, so that they do not stall the async
+/// executor. When the daemon cannot be reached, key lookups come back empty
+/// and signing fails with `Error::Unavailable`, so the node keeps running but
+/// stops authoring until the daemon is back.
+#[derive(Clone)]
+pub struct RemoteKeystore {
+	endpoint: Endpoint,
+	token: Option<String>,
+}
+
+impl RemoteKeystore {
+	/// Keystore talking to the daemon at `uri`, sending `token` with each
+	/// call if given. An `http://` daemon needs a token.
+	pub fn open(uri: &str, token: Option<String>) -> Result<Self, String> {
+		let endpoint = uri.parse()?;
+		if let (Endpoint::Http(_), None) = (&endpoint, &token) {
+			return Err(format!("Signer URI `{}` needs a token; set `{}`.", uri, crate::TOKEN_ENV))
+		}
+		Ok(RemoteKeystore { endpoint, token })
+	}
+
+	fn exchange<S: io::Read + Write>(&self, mut stream: S, host: &str, body: &[u8]) -> io::Result<(u16, Vec<u8>)> {
+		http::write_request(&mut stream, host, self.token.as_deref(), body)?;
+		http::read_response(stream)
+	}
+
+	fn call<T: Decode>(&self, request: Request) -> Result<T, Error> {
+		let body = request.encode();
+		let response = match &self.endpoint {
+			Endpoint::Unix(path) => UnixStream::connect(path).and_then(|stream| {
+				stream.set_read_timeout(Some(TIMEOUT))?;
+				self.exchange(stream, "localhost", &body)
+			}),
+			Endpoint::Http(address) => connect(address).and_then(|stream| {
+				stream.set_read_timeout(Some(TIMEOUT))?;
+				self.exchange(stream, address, &body)
+			}),
+		};
+		let (status, body) = response.map_err(|e| {
+			warn!(target: "keystore", "Signer daemon at {:?} unreachable: {}", self.endpoint, e);
+			Error::Unavailable
+		})?;
+		if status != 200 {
+			return Err(Error::Other(format!(
+				"Signer daemon refused the call with status {}: {}", status, String::from_utf8_lossy(&body),
+			)))
+		}
+		Result::<T, RemoteError>::decode(&mut &body[..])
+			.map_err(|e| Error::Other(format!("Unable to decode signer daemon reply: {}", e)))?
+			.map_err(Into::into)
+	}
+
+	/// Run `f` on a new thread and wait for its result without blocking the
+	/// executor.
+	async fn blocking<R, F>(&self, f: F) -> R
+	where
+		R: Send + 'static,
+		F: FnOnce(&RemoteKeystore) -> R + Send + 'static,
+	{
+		let keystore = self.clone();
+		let (sender, receiver) = oneshot::channel();
+		thread::spawn(move || {
+			let _ = sender.send(f(&keystore));
+		});
+		receiver.await.expect("the thread sends a result unless it panics; qed")
+	}
+
+	fn public_keys(&self, scheme: CryptoTypeId, id: KeyTypeId) -> Vec<Vec<u8>> {
+		self.call(Request::PublicKeys(scheme, id)).unwrap_or_else(|e| {
+			warn!(target: "keystore", "Unable to list {:?} keys of the signer daemon: {:?}", id, e);
+			Vec::new()
+		})
+	}
+
+	fn generate_new(&self, scheme: CryptoTypeId, id: KeyTypeId, seed: Option<&str>) -> Result<Vec<u8>, Error> {
+		self.call(Request::GenerateNew(scheme, id, seed.map(Into::into)))
+	}
+}
+
+/// Connect to `address`, giving up on each of its socket addresses after
+/// `TIMEOUT`.
+fn connect(address: &str) -> io::Result<TcpStream> {
+	let mut error = io::Error::new(io::ErrorKind::NotFound, format!("{} resolves to no address", address));
+	for address in address.to_socket_addrs()? {
+		match TcpStream::connect_timeout(&address, TIMEOUT) {
+			Ok(stream) => return Ok(stream),
+			Err(e) => error = e,
+		}
+	}
+	Err(error)
+}
+
+fn public<P: Default + AsMut<[u8]>>(raw: Vec<u8>) -> Option<P> {
+	let mut public = P::default();
+	if public.as_mut().len() != raw.len() {
+		return None
+	}
+	public.as_mut().copy_from_slice(&raw);
+	Some(public)
+}
+
+fn publics<P: Default + AsMut<[u8]>>(raw: Vec<Vec<u8>>) -> Vec<P> {
+	raw.into_iter().filter_map(public).collect()
+}
+
+fn generated<P: Default + AsMut<[u8]>>(raw: Vec<u8>) -> Result<P, Error> {
+	public(raw).ok_or_else(|| Error::Other("Signer daemon returned a malformed public key".into()))
+}
+
+impl SyncCryptoStore for RemoteKeystore {
+	fn sr25519_public_keys(&self, id: KeyTypeId) -> Vec<sr25519::Public> {
+		publics(self.public_keys(sr25519::CRYPTO_ID, id))
+	}
+
+	fn sr25519_generate_new(&self, id: KeyTypeId, seed: Option<&str>) -> Result<sr25519::Public, Error> {
+		generated(self.generate_new(sr25519::CRYPTO_ID, id, seed)?)
+	}
+
+	fn ed25519_public_keys(&self, id: KeyTypeId) -> Vec<ed25519::Public> {
+		publics(self.public_keys(ed25519::CRYPTO_ID, id))
+	}
+
+	fn ed25519_generate_new(&self, id: KeyTypeId, seed: Option<&str>) -> Result<ed25519::Public, Error> {
+		generated(self.generate_new(ed25519::CRYPTO_ID, id, seed)?)
+	}
+
+	fn ecdsa_public_keys(&self, id: KeyTypeId) -> Vec<ecdsa::Public> {
+		publics(self.public_keys(ecdsa::CRYPTO_ID, id))
+	}
+
+	fn ecdsa_generate_new(&self, id: KeyTypeId, seed: Option<&str>) -> Result<ecdsa::Public, Error> {
+		generated(self.generate_new(ecdsa::CRYPTO_ID, id, seed)?)
+	}
+
+	fn insert_unknown(&self, id: KeyTypeId, suri: &str, public: &[u8]) -> Result<(), ()> {
+		self.call(Request::InsertUnknown(id, suri.into(), public.to_vec())).map_err(|_| ())
+	}
+
+	fn supported_keys(&self, id: KeyTypeId, keys: Vec<CryptoTypePublicPair>) -> Result<Vec<CryptoTypePublicPair>, Error> {
+		self.call(Request::SupportedKeys(id, keys))
+	}
+
+	fn keys(&self, id: KeyTypeId) -> Result<Vec<CryptoTypePublicPair>, Error> {
+		self.call(Request::Keys(id))
+	}
+
+	fn has_keys(&self, public_keys: &[(Vec<u8>, KeyTypeId)]) -> bool {
+		self.call(Request::HasKeys(public_keys.to_vec())).unwrap_or(false)
+	}
+
+	fn sign_with(&self, id: KeyTypeId, key: &CryptoTypePublicPair, msg: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+		self.call(Request::SignWith(id, key.clone(), msg.to_vec()))
+	}
+
+	fn sr25519_vrf_sign(
+		&self,
+		_key_type: KeyTypeId,
+		_public: &sr25519::Public,
+		_transcript_data: VRFTranscriptData,
+	) -> Result<Option<VRFSignature>, Error> {
+		// Aura and GRANDPA never ask for VRF signatures.
+		Err(Error::Other("VRF signing is not supported by the remote keystore".into()))
+	}
+}
+
+#[async_trait]
+impl CryptoStore for RemoteKeystore {
+	async fn sr25519_public_keys(&self, id: KeyTypeId) -> Vec<sr25519::Public> {
+		self.blocking(move |keystore| SyncCryptoStore::sr25519_public_keys(keystore, id)).await
+	}
+
+	async fn sr25519_generate_new(&self, id: KeyTypeId, seed: Option<&str>) -> Result<sr25519::Public, Error> {
+		let seed = seed.map(String::from);
+		self.blocking(move |keystore| SyncCryptoStore::sr25519_generate_new(keystore, id, seed.as_deref())).await
+	}
+
+	async fn ed25519_public_keys(&self, id: KeyTypeId) -> Vec<ed25519::Public> {
+		self.blocking(move |keystore| SyncCryptoStore::ed25519_public_keys(keystore, id)).await
+	}
+
+	async fn ed25519_generate_new(&self, id: KeyTypeId, seed: Option<&str>) -> Result<ed25519::Public, Error> {
+		let seed = seed.map(String::from);
+		self.blocking(move |keystore| SyncCryptoStore::ed25519_generate_new(keystore, id, seed.as_deref())).await
+	}
+
+	async fn ecdsa_public_keys(&self, id: KeyTypeId) -> Vec<ecdsa::Public> {
+		self.blocking(move |keystore| SyncCryptoStore::ecdsa_public_keys(keystore, id)).await
+	}
+
+	async fn ecdsa_generate_new(&self, id: KeyTypeId, seed: Option<&str>) -> Result<ecdsa::Public, Error> {
+		let seed = seed.map(String::from);
+		self.blocking(move |keystore| SyncCryptoStore::ecdsa_generate_new(keystore, id, seed.as_deref())).await
+	}
+
+	async fn insert_unknown(&self, id: KeyTypeId, suri: &str, public: &[u8]) -> Result<(), ()> {
+		let (suri, public) = (suri.to_string(), public.to_vec());
+		self.blocking(move |keystore| SyncCryptoStore::insert_unknown(keystore, id, &suri, &public)).await
+	}
+
+	async fn supported_keys(
+		&self,
+		id: KeyTypeId,
+		keys: Vec<CryptoTypePublicPair>,
+	) -> Result<Vec<CryptoTypePublicPair>, Error> {
+		self.blocking(move |keystore| SyncCryptoStore::supported_keys(keystore, id, keys)).await
+	}
+
+	async fn keys(&self, id: KeyTypeId) -> Result<Vec<CryptoTypePublicPair>, Error> {
+		self.blocking(move |keystore| SyncCryptoStore::keys(keystore, id)).await
+	}
+
+	async fn has_keys(&self, public_keys: &[(Vec<u8>, KeyTypeId)]) -> bool {
+		let public_keys = public_keys.to_vec();
+		self.blocking(move |keystore| SyncCryptoStore::has_keys(keystore, &public_keys)).await
+	}
+
+	async fn sign_with(
+		&self,
+		id: KeyTypeId,
+		key: &CryptoTypePublicPair,
+		msg: &[u8],
+	) -> Result<Option<Vec<u8>>, Error> {
+		let (key, msg) = (key.clone(), msg.to_vec());
+		self.blocking(move |keystore| SyncCryptoStore::sign_with(keystore, id, &key, &msg)).await
+	}
+
+	async fn sr25519_vrf_sign(
+		&self,
+		key_type: KeyTypeId,
+		public: &sr25519::Public,
+		transcript_data: VRFTranscriptData,
+	) -> Result<Option<VRFSignature>, Error> {
+		// Answered without calling the daemon.
+		SyncCryptoStore::sr25519_vrf_sign(self, key_type, public, transcript_data)
+	}
+}
@@ -0,0 +1,112 @@
+//! The bit of HTTP/1.1 the signer speaks: one `POST /` with a
+//! `Content-Length` body per connection, answered with one response.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+/// Largest request or response body accepted.
+pub const MAX_BODY: usize = 1 << 20;
+
+fn invalid(message: &str) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Start line and headers of a message, header names in lower case.
+fn read_head<R: BufRead>(reader: &mut R) -> io::Result<(String, Vec<(String, String)>)> {
+	let mut start = String::new();
+	reader.read_line(&mut start)?;
+	let mut headers = Vec::new();
+	loop {
+		let mut line = String::new();
+		if reader.read_line(&mut line)? == 0 {
+			return Err(invalid("Connection closed within headers"))
+		}
+		let line = line.trim_end();
+		if line.is_empty() {
+			break
+		}
+		let (name, value) = line.split_once(':').ok_or_else(|| invalid("Malformed header"))?;
+		headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+	}
+	Ok((start.trim_end().to_string(), headers))
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+	headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+}
+
+fn read_body<R: Read>(reader: &mut R, headers: &[(String, String)]) -> io::Result<Vec<u8>> {
+	let length: usize = header(headers, "content-length")
+		.ok_or_else(|| invalid("Missing Content-Length"))?
+		.parse()
+		.map_err(|_| invalid("Malformed Content-Length"))?;
+	if length > MAX_BODY {
+		return Err(invalid("Body too large"))
+	}
+	let mut body = vec![0; length];
+	reader.read_exact(&mut body)?;
+	Ok(body)
+}
+
+/// Send `body` to the daemon, with `token` as bearer token if given.
+pub fn write_request<W: Write>(writer: &mut W, host: &str, token: Option<&str>, body: &[u8]) -> io::Result<()> {
+	let mut head = format!(
+		"POST / HTTP/1.1\r\nHost: {}\r\nContent-Type: application/octet-stream\r\nContent-Length: {}\r\nConnection: close\r\n",
+		host, body.len(),
+	);
+	if let Some(token) = token {
+		head.push_str(&format!("Authorization: Bearer {}\r\n", token));
+	}
+	head.push_str("\r\n");
+	writer.write_all(head.as_bytes())?;
+	writer.write_all(body)?;
+	writer.flush()
+}
+
+/// A request as received by the daemon.
+pub struct IncomingRequest {
+	/// Bearer token, if the request carried one.
+	pub token: Option<String>,
+	pub body: Vec<u8>,
+}
+
+/// Read a request sent with [`write_request`].
+pub fn read_request<R: Read>(reader: R) -> io::Result<IncomingRequest> {
+	let mut reader = BufReader::new(reader);
+	let (start, headers) = read_head(&mut reader)?;
+	if !start.starts_with("POST ") {
+		return Err(invalid("Only POST is supported"))
+	}
+	let token = header(&headers, "authorization")
+		.and_then(|value| value.strip_prefix("Bearer "))
+		.map(str::to_string);
+	let body = read_body(&mut reader, &headers)?;
+	Ok(IncomingRequest { token, body })
+}
+
+/// Answer a request with `status` and `body`.
+pub fn write_response<W: Write>(writer: &mut W, status: u16, body: &[u8]) -> io::Result<()> {
+	let reason = match status {
+		200 => "OK",
+		400 => "Bad Request",
+		401 => "Unauthorized",
+		_ => "Error",
+	};
+	write!(
+		writer,
+		"HTTP/1.1 {} {}\r\nContent-Type: application/octet-stream\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+		status, reason, body.len(),
+	)?;
+	writer.write_all(body)?;
+	writer.flush()
+}
+
+/// Read the status and body of a response sent with [`write_response`].
+pub fn read_response<R: Read>(reader: R) -> io::Result<(u16, Vec<u8>)> {
+	let mut reader = BufReader::new(reader);
+	let (start, headers) = read_head(&mut reader)?;
+	let status = start.split(' ').nth(1)
+		.and_then(|status| status.parse().ok())
+		.ok_or_else(|| invalid("Malformed status line"))?;
+	let body = read_body(&mut reader, &headers)?;
+	Ok((status, body))
+}
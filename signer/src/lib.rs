@@ -0,0 +1,217 @@
+//! Keeps validator keys out of the node: a signer daemon holds them in a
+//! local keystore, and the node's [`RemoteKeystore`] forwards every keystore
+//! call to it.
+//!
+//! The daemon listens on a Unix socket (`unix:///path/to/socket`) or TCP on
+//! a loopback address (`http://127.0.0.1:port`); calls are not encrypted, so
+//! they never leave the host. Either way each call is one HTTP/1.1 `POST` whose
+//! body is a SCALE-encoded [`Request`] and whose response body is the
+//! SCALE-encoded `Result<_, RemoteError>` of the call.
+
+use codec::{Decode, Encode};
+use sp_core::crypto::{CryptoTypeId, CryptoTypePublicPair, KeyTypeId};
+
+mod client;
+pub mod http;
+mod server;
+
+pub use client::RemoteKeystore;
+pub use server::{dispatch, Listener};
+
+/// Environment variable holding the token the node sends to the daemon.
+pub const TOKEN_ENV: &str = "COLDSTACK_SIGNER_TOKEN";
+
+/// A keystore call. The comment of each variant names the type of its reply.
+#[derive(Debug, Clone, PartialEq, Encode, Decode)]
+pub enum Request {
+	/// Public keys of a crypto scheme and key type. `Vec<Vec<u8>>`.
+	PublicKeys(CryptoTypeId, KeyTypeId),
+	/// Generate a key of a crypto scheme, from `seed` if given. `Vec<u8>`,
+	/// the public key.
+	GenerateNew(CryptoTypeId, KeyTypeId, Option<String>),
+	/// Store the secret URI of a key. `()`.
+	InsertUnknown(KeyTypeId, String, Vec<u8>),
+	/// Those of the given keys that the daemon holds.
+	/// `Vec<CryptoTypePublicPair>`.
+	SupportedKeys(KeyTypeId, Vec<CryptoTypePublicPair>),
+	/// All keys of a key type. `Vec<CryptoTypePublicPair>`.
+	Keys(KeyTypeId),
+	/// Whether the daemon holds all the given keys. `bool`.
+	HasKeys(Vec<(Vec<u8>, KeyTypeId)>),
+	/// Sign a message. `Option<Vec<u8>>`, `None` if the key is not held.
+	SignWith(KeyTypeId, CryptoTypePublicPair, Vec<u8>),
+}
+
+impl Request {
+	/// Name of the call and the key type it concerns, if one. Unlike the
+	/// `Debug` output, this never contains seeds or secret URIs, so it may be
+	/// logged.
+	pub fn summary(&self) -> (&'static str, Option<KeyTypeId>) {
+		match self {
+			Request::PublicKeys(_, id) => ("PublicKeys", Some(*id)),
+			Request::GenerateNew(_, id, _) => ("GenerateNew", Some(*id)),
+			Request::InsertUnknown(id, _, _) => ("InsertUnknown", Some(*id)),
+			Request::SupportedKeys(id, _) => ("SupportedKeys", Some(*id)),
+			Request::Keys(id) => ("Keys", Some(*id)),
+			Request::HasKeys(_) => ("HasKeys", None),
+			Request::SignWith(id, _, _) => ("SignWith", Some(*id)),
+		}
+	}
+}
+
+/// `sp_keystore::Error` on the wire.
+#[derive(Debug, Clone, PartialEq, Encode, Decode)]
+pub enum RemoteError {
+	KeyNotSupported(KeyTypeId),
+	Validation(String),
+	Unavailable,
+	Other(String),
+}
+
+impl From<sp_keystore::Error> for RemoteError {
+	fn from(e: sp_keystore::Error) -> Self {
+		match e {
+			sp_keystore::Error::KeyNotSupported(id) => RemoteError::KeyNotSupported(id),
+			sp_keystore::Error::ValidationError(e) => RemoteError::Validation(e),
+			sp_keystore::Error::Unavailable => RemoteError::Unavailable,
+			sp_keystore::Error::Other(e) => RemoteError::Other(e),
+		}
+	}
+}
+
+impl From<RemoteError> for sp_keystore::Error {
+	fn from(e: RemoteError) -> Self {
+		match e {
+			RemoteError::KeyNotSupported(id) => sp_keystore::Error::KeyNotSupported(id),
+			RemoteError::Validation(e) => sp_keystore::Error::ValidationError(e),
+			RemoteError::Unavailable => sp_keystore::Error::Unavailable,
+			RemoteError::Other(e) => sp_keystore::Error::Other(e),
+		}
+	}
+}
+
+/// Where the daemon listens, parsed from a `unix://` or `http://` URI.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Endpoint {
+	Unix(std::path::PathBuf),
+	/// `host:port`, with a loopback host.
+	Http(String),
+}
+
+/// Whether the host of `address`, `host:port`, is `localhost` or a loopback
+/// IP address.
+fn is_loopback(address: &str) -> bool {
+	let host = address.rfind(':').map_or(address, |colon| &address[..colon]);
+	let host = host.trim_start_matches('[').trim_end_matches(']');
+	host == "localhost" || host.parse::<std::net::IpAddr>().map_or(false, |ip| ip.is_loopback())
+}
+
+impl std::str::FromStr for Endpoint {
+	type Err = String;
+
+	fn from_str(uri: &str) -> Result<Self, Self::Err> {
+		if let Some(path) = uri.strip_prefix("unix://") {
+			Ok(Endpoint::Unix(path.into()))
+		} else if let Some(address) = uri.strip_prefix("http://") {
+			let address = address.trim_end_matches('/');
+			if !is_loopback(address) {
+				return Err(format!(
+					"Signer URI `{}` is not a loopback address; keys and tokens would cross the network \
+					unencrypted. Use `unix://<path>` or `http://127.0.0.1:<port>`.",
+					uri,
+				))
+			}
+			Ok(Endpoint::Http(address.into()))
+		} else {
+			Err(format!("Unsupported signer URI `{}`, expected `unix://<path>` or `http://<host>:<port>`", uri))
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use sc_keystore::LocalKeystore;
+	use sp_core::{sr25519, Pair};
+	use sp_keystore::{CryptoStore, SyncCryptoStore};
+	use std::sync::Arc;
+
+	const AURA: KeyTypeId = KeyTypeId(*b"aura");
+
+	fn socket_path(name: &str) -> std::path::PathBuf {
+		std::env::temp_dir().join(format!("coldstack-signer-{}-{}.sock", name, std::process::id()))
+	}
+
+	#[test]
+	fn parses_endpoints() {
+		assert_eq!("unix:///run/signer.sock".parse(), Ok(Endpoint::Unix("/run/signer.sock".into())));
+		assert_eq!("http://127.0.0.1:9955/".parse(), Ok(Endpoint::Http("127.0.0.1:9955".into())));
+		assert_eq!("http://localhost:9955".parse(), Ok(Endpoint::Http("localhost:9955".into())));
+		assert_eq!("http://[::1]:9955".parse(), Ok(Endpoint::Http("[::1]:9955".into())));
+		assert!("http://10.0.0.2:9955".parse::<Endpoint>().is_err());
+		assert!("http://signer.internal:9955".parse::<Endpoint>().is_err());
+		assert!("https://127.0.0.1:9955".parse::<Endpoint>().is_err());
+	}
+
+	#[test]
+	fn remote_keystore_signs_with_daemon_keys() {
+		let path = socket_path("sign");
+		let daemon = Arc::new(LocalKeystore::in_memory());
+		let public = SyncCryptoStore::sr25519_generate_new(&*daemon, AURA, Some("//Alice")).unwrap();
+		let listener = Listener::bind(&Endpoint::Unix(path.clone())).unwrap();
+		std::thread::spawn(move || listener.serve(daemon, None));
+
+		let remote = RemoteKeystore::open(&format!("unix://{}", path.display()), None).unwrap();
+		assert_eq!(SyncCryptoStore::sr25519_public_keys(&remote, AURA), vec![public]);
+		assert!(SyncCryptoStore::has_keys(&remote, &[(public.0.to_vec(), AURA)]));
+
+		let key = CryptoTypePublicPair(sr25519::CRYPTO_ID, public.0.to_vec());
+		let signature = SyncCryptoStore::sign_with(&remote, AURA, &key, b"block").unwrap().unwrap();
+		let signature = sr25519::Signature::from_slice(&signature);
+		assert!(sr25519::Pair::verify(&signature, b"block", &public));
+
+		let signature = futures::executor::block_on(CryptoStore::sign_with(&remote, AURA, &key, b"block"))
+			.unwrap()
+			.unwrap();
+		let signature = sr25519::Signature::from_slice(&signature);
+		assert!(sr25519::Pair::verify(&signature, b"block", &public));
+
+		let unknown = CryptoTypePublicPair(sr25519::CRYPTO_ID, vec![0; 32]);
+		assert_eq!(SyncCryptoStore::sign_with(&remote, AURA, &unknown, b"block").unwrap(), None);
+		let _ = std::fs::remove_file(path);
+	}
+
+	#[test]
+	fn http_needs_token() {
+		let listener = Listener::bind(&Endpoint::Http("127.0.0.1:0".into())).unwrap();
+		let daemon = Arc::new(LocalKeystore::in_memory());
+		assert!(listener.serve(daemon, None).is_err());
+		assert!(RemoteKeystore::open("http://127.0.0.1:9955", None).is_err());
+		assert!(RemoteKeystore::open("http://127.0.0.1:9955", Some("secret".into())).is_ok());
+	}
+
+	#[test]
+	fn summary_leaves_out_secrets() {
+		let request = Request::InsertUnknown(AURA, "//Alice".into(), vec![1; 32]);
+		assert_eq!(request.summary(), ("InsertUnknown", Some(AURA)));
+	}
+
+	#[test]
+	fn daemon_rejects_wrong_token() {
+		let path = socket_path("token");
+		let daemon = Arc::new(LocalKeystore::in_memory());
+		let listener = Listener::bind(&Endpoint::Unix(path.clone())).unwrap();
+		std::thread::spawn(move || listener.serve(daemon, Some("secret".into())));
+
+		let uri = format!("unix://{}", path.display());
+		for wrong in ["wrong", "secre", "secret2"].iter() {
+			let remote = RemoteKeystore::open(&uri, Some(wrong.to_string())).unwrap();
+			assert!(SyncCryptoStore::keys(&remote, AURA).is_err());
+		}
+		let remote = RemoteKeystore::open(&uri, None).unwrap();
+		assert!(SyncCryptoStore::keys(&remote, AURA).is_err());
+		let remote = RemoteKeystore::open(&uri, Some("secret".into())).unwrap();
+		assert_eq!(SyncCryptoStore::keys(&remote, AURA).unwrap(), vec![]);
+		let _ = std::fs::remove_file(path);
+	}
+}
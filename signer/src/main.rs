@@ -0,0 +1,67 @@
+//! `coldstack-signer`: holds validator keys for nodes started with
+//! `--keystore-uri`.
+
+use std::{path::{Path, PathBuf}, sync::Arc};
+
+use coldstack_signer::{Endpoint, Listener};
+use sc_keystore::LocalKeystore;
+use sp_core::crypto::SecretString;
+use structopt::StructOpt;
+
+/// Signer daemon holding validator keys.
+#[derive(Debug, StructOpt)]
+#[structopt(name = "coldstack-signer")]
+struct Opt {
+	/// Keystore directory, as written by `key insert` or
+	/// `secrets insert-keys`.
+	#[structopt(long, value_name = "PATH", parse(from_os_str))]
+	keystore_path: PathBuf,
+
+	/// File holding the keystore password.
+	#[structopt(long, value_name = "PATH", parse(from_os_str))]
+	password_filename: Option<PathBuf>,
+
+	/// `unix://<path>` or `http://<loopback host>:<port>` to listen on.
+	#[structopt(long, value_name = "URI", default_value = "unix:///run/coldstack-signer/signer.sock")]
+	listen: Endpoint,
+
+	/// File holding a token the node must send, set on the node with the
+	/// `COLDSTACK_SIGNER_TOKEN` environment variable. Required with
+	/// `http://`.
+	#[structopt(long, value_name = "PATH", parse(from_os_str))]
+	token_file: Option<PathBuf>,
+}
+
+fn read_secret(path: &Path) -> Result<String, String> {
+	std::fs::read_to_string(path)
+		.map(|secret| secret.trim_end().to_string())
+		.map_err(|e| format!("Unable to read {}: {}", path.display(), e))
+}
+
+fn run() -> Result<(), String> {
+	let opt = Opt::from_args();
+	let password = opt.password_filename.as_deref().map(read_secret).transpose()?;
+	let token = opt.token_file.as_deref().map(read_secret).transpose()?;
+	if let (Endpoint::Http(_), None) = (&opt.listen, &token) {
+		return Err("Listening on `http://` needs `--token-file`.".into())
+	}
+
+	let keystore = LocalKeystore::open(&opt.keystore_path, password.map(SecretString::new))
+		.map_err(|e| format!("Unable to open keystore {}: {:?}", opt.keystore_path.display(), e))?;
+	let listener = Listener::bind(&opt.listen)
+		.map_err(|e| format!("Unable to listen on {:?}: {}", opt.listen, e))?;
+	// `localhost` might resolve to a network address.
+	if !listener.is_local() {
+		return Err("Refusing to serve keys on a network address.".into())
+	}
+
+	eprintln!("Serving keys of {} on {:?}", opt.keystore_path.display(), opt.listen);
+	listener.serve(Arc::new(keystore), token).map_err(|e| format!("Listener failed: {}", e))
+}
+
+fn main() {
+	if let Err(e) = run() {
+		eprintln!("{}", e);
+		std::process::exit(1);
+	}
+}
@@ -0,0 +1,155 @@
+//! The daemon's end: answers keystore calls from a local keystore.
+
+use std::{
+	io::{self, Read, Write},
+	net::TcpListener,
+	os::unix::{fs::PermissionsExt, net::UnixListener},
+	sync::Arc,
+	thread,
+	time::Duration,
+};
+
+use codec::{Decode, Encode};
+use log::{debug, warn};
+use sc_keystore::LocalKeystore;
+use sp_core::{crypto::CryptoTypeId, ecdsa, ed25519, sr25519};
+use sp_keystore::SyncCryptoStore;
+
+use crate::{http, Endpoint, RemoteError, Request};
+
+/// Time a connection may take to send a call or take its answer before the
+/// daemon drops it.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A bound daemon socket.
+pub enum Listener {
+	Unix(UnixListener),
+	Tcp(TcpListener),
+}
+
+impl Listener {
+	/// Bind to `endpoint`. A stale Unix socket is replaced, and the new one is
+	/// only accessible to the daemon's user and group.
+	pub fn bind(endpoint: &Endpoint) -> io::Result<Self> {
+		match endpoint {
+			Endpoint::Unix(path) => {
+				if path.exists() {
+					std::fs::remove_file(path)?;
+				}
+				let listener = UnixListener::bind(path)?;
+				std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o660))?;
+				Ok(Listener::Unix(listener))
+			},
+			Endpoint::Http(address) => Ok(Listener::Tcp(TcpListener::bind(address)?)),
+		}
+	}
+
+	/// Whether only this host can connect.
+	pub fn is_local(&self) -> bool {
+		match self {
+			Listener::Unix(_) => true,
+			Listener::Tcp(listener) => listener.local_addr().map_or(false, |addr| addr.ip().is_loopback()),
+		}
+	}
+
+	/// Answer calls with keys of `keystore`, one thread per connection.
+	/// Calls without `token`, if given, are refused. A TCP listener needs a
+	/// token, since any local user may connect to it.
+	pub fn serve(self, keystore: Arc<LocalKeystore>, token: Option<String>) -> io::Result<()> {
+		if let (Listener::Tcp(_), None) = (&self, &token) {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, "serving keys over TCP needs a token"))
+		}
+		let token = Arc::new(token);
+		loop {
+			let (keystore, token) = (keystore.clone(), token.clone());
+			match &self {
+				Listener::Unix(listener) => {
+					let (stream, _) = listener.accept()?;
+					stream.set_read_timeout(Some(TIMEOUT))?;
+					stream.set_write_timeout(Some(TIMEOUT))?;
+					thread::spawn(move || handle(stream, &keystore, token.as_deref()));
+				},
+				Listener::Tcp(listener) => {
+					let (stream, _) = listener.accept()?;
+					stream.set_read_timeout(Some(TIMEOUT))?;
+					stream.set_write_timeout(Some(TIMEOUT))?;
+					thread::spawn(move || handle(stream, &keystore, token.as_deref()));
+				},
+			}
+		}
+	}
+}
+
+fn handle<S: Read + Write>(mut stream: S, keystore: &LocalKeystore, token: Option<&str>) {
+	let result = http::read_request(&mut stream).and_then(|request| {
+		if token.map_or(false, |token| !token_matches(token, request.token.as_deref())) {
+			return http::write_response(&mut stream, 401, b"Missing or wrong token")
+		}
+		match Request::decode(&mut &request.body[..]) {
+			Ok(request) => {
+				let (call, key_type) = request.summary();
+				debug!(target: "coldstack-signer", "{} {:?}", call, key_type);
+				http::write_response(&mut stream, 200, &dispatch(keystore, request))
+			},
+			Err(e) => http::write_response(&mut stream, 400, e.to_string().as_bytes()),
+		}
+	});
+	if let Err(e) = result {
+		warn!(target: "coldstack-signer", "Connection failed: {}", e);
+	}
+}
+
+/// Whether `given` is `expected`, in time that only depends on their
+/// lengths, so that response times do not tell how much of the token a
+/// caller got right.
+fn token_matches(expected: &str, given: Option<&str>) -> bool {
+	let (expected, given) = (expected.as_bytes(), given.unwrap_or_default().as_bytes());
+	let difference = expected.iter().zip(given)
+		.fold(expected.len() ^ given.len(), |difference, (a, b)| difference | (a ^ b) as usize);
+	difference == 0
+}
+
+fn unsupported(scheme: CryptoTypeId) -> RemoteError {
+	RemoteError::Other(format!("Unsupported crypto scheme {:?}", scheme))
+}
+
+/// Run `request` against `keystore` and encode its reply.
+pub fn dispatch(keystore: &LocalKeystore, request: Request) -> Vec<u8> {
+	fn reply<T: Encode>(result: Result<T, RemoteError>) -> Vec<u8> {
+		result.encode()
+	}
+	fn raw<P: AsRef<[u8]>>(keys: Vec<P>) -> Vec<Vec<u8>> {
+		keys.iter().map(|key| key.as_ref().to_vec()).collect()
+	}
+
+	match request {
+		Request::PublicKeys(scheme, id) => reply(match scheme {
+			sr25519::CRYPTO_ID => Ok(raw(SyncCryptoStore::sr25519_public_keys(keystore, id))),
+			ed25519::CRYPTO_ID => Ok(raw(SyncCryptoStore::ed25519_public_keys(keystore, id))),
+			ecdsa::CRYPTO_ID => Ok(raw(SyncCryptoStore::ecdsa_public_keys(keystore, id))),
+			scheme => Err(unsupported(scheme)),
+		}),
+		Request::GenerateNew(scheme, id, seed) => {
+			let seed = seed.as_deref();
+			reply(match scheme {
+				sr25519::CRYPTO_ID => SyncCryptoStore::sr25519_generate_new(keystore, id, seed)
+					.map(|key| key.as_ref().to_vec()).map_err(Into::into),
+				ed25519::CRYPTO_ID => SyncCryptoStore::ed25519_generate_new(keystore, id, seed)
+					.map(|key| key.as_ref().to_vec()).map_err(Into::into),
+				ecdsa::CRYPTO_ID => SyncCryptoStore::ecdsa_generate_new(keystore, id, seed)
+					.map(|key| key.as_ref().to_vec()).map_err(Into::into),
+				scheme => Err(unsupported(scheme)),
+			})
+		},
+		Request::InsertUnknown(id, suri, public) => reply(
+			SyncCryptoStore::insert_unknown(keystore, id, &suri, &public)
+				.map_err(|()| RemoteError::Other("Unable to insert key".into())),
+		),
+		Request::SupportedKeys(id, keys) =>
+			reply(SyncCryptoStore::supported_keys(keystore, id, keys).map_err(Into::into)),
+		Request::Keys(id) => reply(SyncCryptoStore::keys(keystore, id).map_err(Into::into)),
+		Request::HasKeys(keys) => reply::<bool>(Ok(SyncCryptoStore::has_keys(keystore, &keys))),
+		Request::SignWith(id, key, msg) =>
+			reply(SyncCryptoStore::sign_with(keystore, id, &key, &msg).map_err(Into::into)),
+	}
+}
@@ -8,6 +8,9 @@ const NODE_URL= process.env.NODE_URL;
 
 console.log('NODE_URL', NODE_URL)
 
+// Error of a transaction rejected by `CheckColdStackPermission`
+const POOL_UNAUTHORIZED = '1010: Invalid Transaction: Invalid signing address'
+
 async function expectOk(promise){
   try {
     await promise
@@ -37,12 +40,8 @@ async function expectFail(promise, string){
   const wsProvider = new WsProvider(NODE_URL)
   const api = await ApiPromise.create({ 
     provider: wsProvider,
-    types: {
-      Gateway: {
-        address: 'Vec<u8>',
-        seedAddress: 'Option<Vec<u8>>',
-        storage: 'u8',
-      },
+    signedExtensions: {
+      CheckColdStackPermission: { extrinsic: {}, payload: {} },
     },
   });
 
@@ -52,6 +51,8 @@ async function expectFail(promise, string){
 
   async function sendTxAndWait(account, tx){
     return new Promise(async (resolve, reject) => {
+      // Transactions the pool refuses, e.g. for lack of permission, never
+      // reach a block.
       const unsub = await tx.signAndSend(account, (result) => {
         if (result.status.isInBlock) {
           let rejected = false
@@ -76,20 +77,17 @@ async function expectFail(promise, string){
             resolve(result)
           }
         }
-      })
+      }).catch(e => reject(e.message))
     })
   }
 
   async function gatewayNodes(){
-    const nodeEntries = await api.query.coldStack.gateways.entries()
-    return Promise.all(nodeEntries.map(async ([_, gateway]) => {
-      const nodeAddress = gateway.address.toString('hex')
-      return {
-        nodeAddress,
-        seedAddress: gateway.seedAddress.isNone ? null : gateway.seedAddress.toString('hex'),
-        storage: gateway.storage.toNumber(),
-        url: u8aToString(await api.query.coldStack.nodeURLs(nodeAddress)),
-      }
+    const gateways = await wsProvider.send('coldstack_gateways', [])
+    return gateways.map(gateway => ({
+      nodeAddress: gateway.address,
+      seedAddress: gateway.seedAddress,
+      storage: gateway.storage,
+      url: gateway.nodeUrl,
     }))
   }
 
@@ -190,7 +188,7 @@ async function expectFail(promise, string){
       bob,
       upload()
     ),
-    'coldStack.Unauthorized'
+    POOL_UNAUTHORIZED
   )
 
   console.log("bob failed to upload file")
@@ -250,7 +248,7 @@ async function expectFail(promise, string){
       bob,
       upload()
     ),
-    'coldStack.Unauthorized'
+    POOL_UNAUTHORIZED
   )
 
   // testAddress has zero balance
@@ -338,7 +336,7 @@ async function expectFail(promise, string){
       bob,
       api.tx.coldStack.grantBillingPermission(bobEthAddress, bob.address, 'http://foo.bar')
     ),
-    'coldStack.Unauthorized'
+    POOL_UNAUTHORIZED
   )
 
   console.log("bob failed to grant permission to himself")
@@ -350,7 +348,7 @@ async function expectFail(promise, string){
       bob,
       api.tx.coldStack.deposit(testAddress, 1)
     ),
-    'coldStack.Unauthorized'
+    POOL_UNAUTHORIZED
   )
 
   console.log("bob failed to deposit 1 token")
@@ -393,7 +391,7 @@ async function expectFail(promise, string){
       bob,
       api.tx.coldStack.deposit(testAddress, 1)
     ),
-    'coldStack.Unauthorized'
+    POOL_UNAUTHORIZED
   )
 
   console.log('Tests passed')